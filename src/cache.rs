@@ -0,0 +1,53 @@
+extern crate rspotify;
+extern crate serde_json;
+
+use crate::spoterm::SpotifyData;
+
+use self::rspotify::model::device::Device;
+use self::rspotify::model::playing::PlayHistory;
+use self::rspotify::model::track::SavedTrack;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// The part of `SpotifyData` kept between launches.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CachedLibrary {
+    pub saved_tracks: Vec<SavedTrack>,
+    //the total of Liked Songs reported by Spotify when saved_tracks was last synced
+    pub saved_tracks_total: Option<u32>,
+    pub recent_play_histories: Option<Vec<PlayHistory>>,
+    pub devices: Option<Vec<Device>>,
+}
+
+/// The library as one JSON file, so the lists can be shown before the first request returns.
+#[derive(Clone, Debug)]
+pub struct LibraryCache {
+    pub path: PathBuf,
+}
+
+impl LibraryCache {
+    pub fn new(path: PathBuf) -> LibraryCache {
+        LibraryCache { path }
+    }
+    pub fn load(&self) -> Result<CachedLibrary, failure::Error> {
+        let content = fs::read_to_string(&self.path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+    pub fn save(&self, data: &SpotifyData) -> Result<(), failure::Error> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let library = CachedLibrary {
+            saved_tracks: data.saved_tracks.clone(),
+            saved_tracks_total: data.saved_tracks_total,
+            recent_play_histories: data.recent_play_histories.clone(),
+            devices: data.devices.clone(),
+        };
+        //written next to it and renamed, so a crash never leaves half a file
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string(&library)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,853 @@
+extern crate crossbeam;
+extern crate rspotify;
+
+use self::rspotify::model::context::CurrentlyPlaybackContext;
+use self::rspotify::model::PlayingItem;
+use self::rspotify::senum::RepeatState;
+use crate::export;
+use crate::export::{Column, ExportTrack, Format, Source};
+use crate::query::Query;
+use crate::spoterm::SpotifyData;
+use crate::spotify::{SpotifyAPIResult, SpotifyService};
+use crate::status::Status;
+use std::cmp;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const EXIT_OK: i32 = 0;
+//the request failed (network, API or token errors)
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+//there is no active device or nothing is playing
+pub const EXIT_NO_PLAYBACK: i32 = 3;
+
+pub const USAGE: &str = "Usage: spoterm [COMMAND]
+
+Without a command the terminal UI is started.
+
+Commands:
+    play [URI]                    resume playback or play a track/album/playlist URI
+    pause                         pause playback
+    toggle                        play or pause
+    next                          skip to the next track
+    prev                          go back to the previous track
+    seek <POSITION>               seek to 1:23 or 83 (seconds), or +10/-10 relative
+    volume [VOLUME]               print the volume or set it to 0-100, +N or -N
+    shuffle [on|off|toggle]       change shuffle (default: toggle)
+    repeat [off|track|context]    change repeat (default: cycle like the r key)
+    like                          like or unlike the current track
+    devices                       list available devices
+    status [OPTIONS]              print the current track for status bars
+        --format <TEMPLATE>       e.g. \"{artist} - {title} [{progress}/{duration}] {like_icon}\"
+        --json                    print a JSON object (the rendered template is in \"text\")
+        --watch                   keep running and print a line whenever the output changes
+        --interval <SECONDS>      polling interval for --watch (default: 1)
+    export <liked|recent|history> <FILE> [OPTIONS]
+                                  write a list to CSV, JSON or M3U (by the extension)
+        --columns <COLUMNS>       e.g. name,artists,uri (default: all of name, artists,
+                                  album, duration, popularity, added_at, uri, isrc)
+        --format <csv|json|m3u>   override the format of the extension
+        --filter <QUERY>          only tracks matching QUERY as the filter of the UI,
+                                  e.g. \"artist:radiohead year:<2000 -album:live\"
+    daemon                        keep the session and polling in the background;
+                                  the UI and the commands above attach to it when running
+    record <FILE>                 start the UI and write every API event and result to FILE
+    replay <FILE>                 start the UI with the results recorded in FILE, offline
+    help                          print this message
+
+Status placeholders:
+    {artist} {artists} {title} {album} {progress} {duration} {percent} {like_icon}
+    {status} {status_icon} {shuffle} {repeat} {volume} {device} {uri} {track_id} {context_uri}";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Play(Option<String>),
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Seek(Position),
+    Volume(Option<Position>),
+    Shuffle(Option<bool>),
+    Repeat(Option<RepeatState>),
+    Like,
+    Devices,
+    Status(StatusOptions),
+    Export(ExportOptions),
+    Daemon,
+    //start the terminal UI recording to / replaying from a file
+    Record(PathBuf),
+    Replay(PathBuf),
+    Help,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusOptions {
+    //None uses [status] format in config.toml or status::DEFAULT_FORMAT
+    pub format: Option<String>,
+    pub json: bool,
+    pub watch: bool,
+    pub interval: Duration,
+}
+
+impl Default for StatusOptions {
+    fn default() -> Self {
+        StatusOptions {
+            format: None,
+            json: false,
+            watch: false,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    pub source: Source,
+    pub path: PathBuf,
+    pub columns: Vec<Column>,
+    pub format: Option<Format>,
+    pub filter: String,
+}
+
+//an absolute value or an offset from the current one
+#[derive(Clone, Debug, PartialEq)]
+pub enum Position {
+    Absolute(u32),
+    Relative(i64),
+}
+
+impl Position {
+    pub fn apply(&self, current: u32) -> u32 {
+        match self {
+            Position::Absolute(value) => *value,
+            Position::Relative(offset) => cmp::max(current as i64 + offset, 0) as u32,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CliError {
+    pub message: String,
+    pub exit_code: i32,
+}
+
+impl CliError {
+    pub fn usage(message: String) -> CliError {
+        CliError {
+            message,
+            exit_code: EXIT_USAGE,
+        }
+    }
+    fn no_playback() -> CliError {
+        CliError {
+            message: "No active device or nothing is playing".to_string(),
+            exit_code: EXIT_NO_PLAYBACK,
+        }
+    }
+}
+
+impl From<failure::Error> for CliError {
+    fn from(e: failure::Error) -> Self {
+        CliError {
+            message: e.to_string(),
+            exit_code: EXIT_FAILURE,
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for CliError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        CliError {
+            message: e.to_string(),
+            exit_code: EXIT_FAILURE,
+        }
+    }
+}
+
+//"1:23", "83" (seconds) or "+10"/"-10"; returns milliseconds
+pub fn parse_seek_position(arg: &str) -> Option<Position> {
+    let (sign, rest) = match arg.chars().next()? {
+        '+' => (Some(1), &arg[1..]),
+        '-' => (Some(-1), &arg[1..]),
+        _ => (None, arg),
+    };
+    let mut seconds: u64 = 0;
+    for part in rest.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    let ms = seconds * 1000;
+    Some(match sign {
+        Some(sign) => Position::Relative(sign * ms as i64),
+        None => Position::Absolute(ms as u32),
+    })
+}
+
+pub fn parse_volume(arg: &str) -> Option<Position> {
+    match arg.chars().next()? {
+        '+' | '-' => arg.parse::<i64>().ok().map(Position::Relative),
+        _ => arg
+            .parse::<u32>()
+            .ok()
+            .filter(|x| *x <= 100)
+            .map(Position::Absolute),
+    }
+}
+
+fn parse_status_options(args: &[String]) -> Result<StatusOptions, CliError> {
+    let mut options = StatusOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--watch" => options.watch = true,
+            "--format" => match args.next() {
+                Some(format) => options.format = Some(format.clone()),
+                None => return Err(CliError::usage("--format needs a template".to_string())),
+            },
+            "--interval" => match args.next().and_then(|x| x.parse::<f64>().ok()) {
+                Some(seconds) if seconds > 0.0 => {
+                    options.interval = Duration::from_millis((seconds * 1000.0) as u64)
+                }
+                _ => return Err(CliError::usage("invalid --interval".to_string())),
+            },
+            arg => {
+                return Err(CliError::usage(format!(
+                    "unknown option for status: {}",
+                    arg
+                )))
+            }
+        }
+    }
+    Ok(options)
+}
+
+fn parse_export_options(args: &[String]) -> Result<ExportOptions, CliError> {
+    let mut args = args.iter();
+    let source = match args.next() {
+        Some(source) => Source::parse(source)
+            .ok_or_else(|| CliError::usage(format!("unknown list: {}", source)))?,
+        None => return Err(CliError::usage("export needs a list".to_string())),
+    };
+    let path = match args.next() {
+        Some(path) => PathBuf::from(path),
+        None => return Err(CliError::usage("export needs a file".to_string())),
+    };
+    let mut options = ExportOptions {
+        source,
+        path,
+        columns: export::ALL_COLUMNS.to_vec(),
+        format: None,
+        filter: String::new(),
+    };
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| CliError::usage(format!("{} needs a value", arg)))?;
+        match arg.as_str() {
+            "--columns" => {
+                options.columns = export::parse_columns(value).map_err(CliError::usage)?
+            }
+            "--format" => {
+                options.format = Some(
+                    Format::parse(value)
+                        .ok_or_else(|| CliError::usage(format!("unknown format: {}", value)))?,
+                )
+            }
+            "--filter" => {
+                Query::parse(value).map_err(|e| CliError::usage(format!("--filter: {}", e)))?;
+                options.filter = value.clone()
+            }
+            arg => {
+                return Err(CliError::usage(format!(
+                    "unknown option for export: {}",
+                    arg
+                )))
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Parses the arguments after the program name. `Ok(None)` means no subcommand (run the UI).
+pub fn parse_args(args: &[String]) -> Result<Option<Command>, CliError> {
+    let (name, rest) = match args.split_first() {
+        Some((name, rest)) => (name.as_str(), rest),
+        None => return Ok(None),
+    };
+    if name == "status" {
+        return Ok(Some(Command::Status(parse_status_options(rest)?)));
+    }
+    if name == "export" {
+        return Ok(Some(Command::Export(parse_export_options(rest)?)));
+    }
+    let arg = rest.first().map(|x| x.as_str());
+    if rest.len() > 1 {
+        return Err(CliError::usage(format!("too many arguments for {}", name)));
+    }
+    let command = match (name, arg) {
+        ("play", uri) => Command::Play(uri.map(|x| x.to_string())),
+        ("pause", None) => Command::Pause,
+        ("toggle", None) => Command::Toggle,
+        ("next", None) => Command::Next,
+        ("prev", None) | ("previous", None) => Command::Previous,
+        ("seek", Some(arg)) => match parse_seek_position(arg) {
+            Some(position) => Command::Seek(position),
+            None => return Err(CliError::usage(format!("invalid position: {}", arg))),
+        },
+        ("volume", None) => Command::Volume(None),
+        ("volume", Some(arg)) => match parse_volume(arg) {
+            Some(volume) => Command::Volume(Some(volume)),
+            None => return Err(CliError::usage(format!("invalid volume: {}", arg))),
+        },
+        ("shuffle", None) | ("shuffle", Some("toggle")) => Command::Shuffle(None),
+        ("shuffle", Some("on")) => Command::Shuffle(Some(true)),
+        ("shuffle", Some("off")) => Command::Shuffle(Some(false)),
+        ("repeat", None) => Command::Repeat(None),
+        ("repeat", Some("off")) => Command::Repeat(Some(RepeatState::Off)),
+        ("repeat", Some("track")) => Command::Repeat(Some(RepeatState::Track)),
+        ("repeat", Some("context")) => Command::Repeat(Some(RepeatState::Context)),
+        ("like", None) => Command::Like,
+        ("devices", None) => Command::Devices,
+        ("daemon", None) => Command::Daemon,
+        ("record", Some(path)) => Command::Record(PathBuf::from(path)),
+        ("replay", Some(path)) => Command::Replay(PathBuf::from(path)),
+        ("help", None) | ("--help", None) | ("-h", None) => Command::Help,
+        (name, Some(arg)) => {
+            return Err(CliError::usage(format!(
+                "unknown command or argument: {} {}",
+                name, arg
+            )))
+        }
+        (name, None) => return Err(CliError::usage(format!("unknown command: {}", name))),
+    };
+    Ok(Some(command))
+}
+
+fn describe(current_playback: &CurrentlyPlaybackContext) -> String {
+    match current_playback.item.as_ref() {
+        Some(PlayingItem::Track(track)) => format!(
+            "{} - {}",
+            track
+                .artists
+                .iter()
+                .map(|x| x.name.clone())
+                .collect::<Vec<String>>()
+                .join(", "),
+            track.name
+        ),
+        Some(PlayingItem::Episode(episode)) => episode.name.clone(),
+        None => "nothing".to_string(),
+    }
+}
+
+fn repeat_state_name(state: &RepeatState) -> &'static str {
+    match state {
+        RepeatState::Off => "off",
+        RepeatState::Track => "track",
+        RepeatState::Context => "context",
+    }
+}
+
+//the line `status` prints for the current playback (None when nothing is playing)
+pub fn format_status(status: Option<&Status>, format: &str, json: bool) -> String {
+    match (status, json) {
+        (Some(status), true) => status.to_json(format),
+        (Some(status), false) => status.render(format),
+        (None, true) => "{}".to_string(),
+        (None, false) => String::new(),
+    }
+}
+
+/// Writes the list of `options` from `data` and returns the message to print.
+pub fn export(options: &ExportOptions, data: &SpotifyData) -> Result<String, CliError> {
+    let query = Query::new(&options.filter);
+    let tracks: Vec<ExportTrack> = export::tracks_from(options.source, data)?
+        .into_iter()
+        .filter(|x| query.matches(x))
+        .collect();
+    export::write(&options.path, &tracks, &options.columns, options.format).map_err(|e| {
+        CliError::from(failure::err_msg(format!(
+            "failed to write {}: {}",
+            options.path.display(),
+            e
+        )))
+    })?;
+    Ok(format!(
+        "Exported {} tracks to {}",
+        tracks.len(),
+        options.path.display()
+    ))
+}
+
+/// Runs a command with the service's client without starting the UI event loop.
+pub struct Cli {
+    spotify: SpotifyService,
+    rx: crossbeam::channel::Receiver<SpotifyAPIResult>,
+    //[status] format in config.toml
+    pub status_format: Option<String>,
+    //(track id, liked) of the last track `status` checked
+    liked_cache: Option<(String, bool)>,
+}
+
+impl Cli {
+    pub fn new(spotify: SpotifyService) -> Cli {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        Cli {
+            spotify: spotify.api_result_tx(tx),
+            rx,
+            status_format: None,
+            liked_cache: None,
+        }
+    }
+    pub fn status_format(mut self, format: Option<String>) -> Self {
+        self.status_format = format;
+        self
+    }
+    //prints the result (or the error) and returns the exit code
+    pub async fn run(&mut self, command: Command) -> i32 {
+        if let Command::Status(options) = command.clone() {
+            if options.watch {
+                return self.watch_status(options).await;
+            }
+        }
+        match self.output(command).await {
+            Ok(message) => {
+                if !message.is_empty() {
+                    println!("{}", message);
+                }
+                EXIT_OK
+            }
+            Err(e) => {
+                eprintln!("{}", e.message);
+                e.exit_code
+            }
+        }
+    }
+    //the message `run` prints; `status --watch` is rendered once
+    pub async fn output(&mut self, command: Command) -> Result<String, CliError> {
+        if let Err(e) = self.spotify.refresh_client().await {
+            return Err(CliError::from(failure::err_msg(format!(
+                "failed to refresh the access token: {}",
+                e
+            ))));
+        }
+        match command {
+            Command::Status(options) => self.render_status(&options).await,
+            command => self.execute(command).await,
+        }
+    }
+    pub fn status_format_or_default(&self, options: &StatusOptions) -> String {
+        options
+            .format
+            .clone()
+            .or_else(|| self.status_format.clone())
+            .unwrap_or_else(|| crate::status::DEFAULT_FORMAT.to_string())
+    }
+    pub async fn current_playback(&self) -> Result<CurrentlyPlaybackContext, CliError> {
+        self.spotify.fetch_current_playback().await?;
+        self.rx
+            .try_iter()
+            .find_map(|x| match x {
+                SpotifyAPIResult::CurrentPlayBack(current_playback) => current_playback,
+                _ => None,
+            })
+            .ok_or_else(CliError::no_playback)
+    }
+    pub async fn is_saved(&self, track_id: &str) -> Result<bool, CliError> {
+        self.spotify
+            .fetch_check_current_user_saved_tracks(&vec![track_id.to_string()])
+            .await?;
+        self.rx
+            .try_iter()
+            .find_map(|x| match x {
+                SpotifyAPIResult::CheckCurrentUserSavedTracks(saved) => {
+                    Some(saved.first().is_some_and(|x| x.1))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| CliError::from(failure::err_msg("failed to check the saved tracks")))
+    }
+    async fn all_saved_tracks(&self) -> Result<Vec<rspotify::model::track::SavedTrack>, CliError> {
+        let mut saved_tracks = vec![];
+        let mut offset = 0;
+        loop {
+            let page = self
+                .spotify
+                .client
+                .current_user_saved_tracks(Some(50), Some(offset))
+                .await?;
+            offset = page.offset + page.limit;
+            let last = page.next.is_none();
+            saved_tracks.extend(page.items);
+            if last {
+                return Ok(saved_tracks);
+            }
+        }
+    }
+    async fn render_status(&mut self, options: &StatusOptions) -> Result<String, CliError> {
+        let format = self.status_format_or_default(options);
+        let current_playback = match self.current_playback().await {
+            Ok(current_playback) => current_playback,
+            //nothing playing is a normal state for a status bar
+            Err(e) if e.exit_code == EXIT_NO_PLAYBACK => {
+                return Ok(format_status(None, &format, options.json));
+            }
+            Err(e) => return Err(e),
+        };
+        let mut status = Status::new(&current_playback, None);
+        if let Some(track_id) = status.track_id.clone() {
+            let liked = match self.liked_cache.as_ref() {
+                Some((id, liked)) if *id == track_id => *liked,
+                _ => self.is_saved(&track_id).await?,
+            };
+            self.liked_cache = Some((track_id, liked));
+            status.liked = Some(liked);
+        }
+        Ok(format_status(Some(&status), &format, options.json))
+    }
+    async fn watch_status(&mut self, options: StatusOptions) -> i32 {
+        let mut last_line = None;
+        loop {
+            match self.output(Command::Status(options.clone())).await {
+                Ok(line) => {
+                    if last_line.as_ref() != Some(&line) {
+                        println!("{}", line);
+                        //status bars read line by line from a pipe
+                        std::io::stdout().flush().ok();
+                        last_line = Some(line);
+                    }
+                }
+                Err(e) => eprintln!("{}", e.message),
+            }
+            tokio::time::delay_for(options.interval).await;
+        }
+    }
+    async fn execute(&self, command: Command) -> Result<String, CliError> {
+        match command {
+            Command::Help => Ok(USAGE.to_string()),
+            //handled by output and main
+            Command::Status(_) | Command::Daemon | Command::Record(_) | Command::Replay(_) => {
+                Ok(String::new())
+            }
+            Command::Export(options) => {
+                let mut data = SpotifyData::new();
+                match options.source {
+                    Source::Liked => data.saved_tracks = self.all_saved_tracks().await?,
+                    Source::Recent => {
+                        self.spotify.fetch_current_user_recently_played().await?;
+                        data.recent_play_histories = self.rx.try_iter().find_map(|x| match x {
+                            SpotifyAPIResult::CurrentUserRecentlyPlayed(histories) => {
+                                Some(histories)
+                            }
+                            _ => None,
+                        });
+                    }
+                    Source::History => {}
+                }
+                export(&options, &data)
+            }
+            Command::Devices => {
+                self.spotify.fetch_device().await?;
+                let devices = self
+                    .rx
+                    .try_iter()
+                    .find_map(|x| match x {
+                        SpotifyAPIResult::Device(devices) => Some(devices),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                Ok(devices
+                    .iter()
+                    .map(|x| {
+                        format!(
+                            "{} {}\t{:?}\t{}%\t{}",
+                            if x.is_active { "*" } else { " " },
+                            x.name,
+                            x._type,
+                            x.volume_percent,
+                            x.id
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n"))
+            }
+            Command::Play(None) => {
+                self.spotify.fetch_start_playback(None, None).await?;
+                Ok("Playing".to_string())
+            }
+            Command::Play(Some(uri)) => {
+                if uri.starts_with("spotify:track:") {
+                    self.spotify
+                        .fetch_start_playback(None, Some(vec![uri.clone()]))
+                        .await?;
+                } else {
+                    self.spotify
+                        .client
+                        .start_playback(None, Some(uri.clone()), None, None, None)
+                        .await?;
+                }
+                Ok(format!("Playing {}", uri))
+            }
+            Command::Pause => {
+                let current_playback = self.current_playback().await?;
+                self.spotify
+                    .fetch_pause_playback(Some(current_playback.device.id.clone()))
+                    .await?;
+                Ok(format!("Paused: {}", describe(&current_playback)))
+            }
+            Command::Toggle => {
+                let current_playback = self.current_playback().await?;
+                let device_id = Some(current_playback.device.id.clone());
+                if current_playback.is_playing {
+                    self.spotify.fetch_pause_playback(device_id).await?;
+                    Ok(format!("Paused: {}", describe(&current_playback)))
+                } else {
+                    self.spotify.fetch_start_playback(device_id, None).await?;
+                    Ok(format!("Playing: {}", describe(&current_playback)))
+                }
+            }
+            Command::Next => {
+                let current_playback = self.current_playback().await?;
+                self.spotify
+                    .fetch_next_track(Some(current_playback.device.id))
+                    .await?;
+                Ok(String::new())
+            }
+            Command::Previous => {
+                let current_playback = self.current_playback().await?;
+                self.spotify
+                    .fetch_previous_track(Some(current_playback.device.id))
+                    .await?;
+                Ok(String::new())
+            }
+            Command::Seek(position) => {
+                let current_playback = self.current_playback().await?;
+                let progress_ms = position.apply(current_playback.progress_ms.unwrap_or(0));
+                self.spotify
+                    .fetch_seek_track(progress_ms, Some(current_playback.device.id))
+                    .await?;
+                let progress_sec = progress_ms / 1000;
+                Ok(format!(
+                    "Seeked to {:02}:{:02}",
+                    progress_sec / 60,
+                    progress_sec % 60
+                ))
+            }
+            Command::Volume(volume) => {
+                let current_playback = self.current_playback().await?;
+                let current = current_playback.device.volume_percent;
+                let volume = match volume {
+                    Some(volume) => cmp::min(volume.apply(current), 100),
+                    None => return Ok(format!("{}", current)),
+                };
+                self.spotify
+                    .fetch_volume(volume as u8, Some(current_playback.device.id))
+                    .await?;
+                Ok(format!("{}", volume))
+            }
+            Command::Shuffle(state) => {
+                let current_playback = self.current_playback().await?;
+                let state = state.unwrap_or(!current_playback.shuffle_state);
+                self.spotify
+                    .fetch_shuffle(state, Some(current_playback.device.id))
+                    .await?;
+                Ok(format!("Shuffle: {}", if state { "on" } else { "off" }))
+            }
+            Command::Repeat(state) => {
+                let current_playback = self.current_playback().await?;
+                //same order as the `r` key
+                let state = state.unwrap_or(match current_playback.repeat_state {
+                    RepeatState::Off => RepeatState::Track,
+                    RepeatState::Track => RepeatState::Context,
+                    RepeatState::Context => RepeatState::Off,
+                });
+                let name = repeat_state_name(&state);
+                self.spotify
+                    .fetch_repeat(state, Some(current_playback.device.id))
+                    .await?;
+                Ok(format!("Repeat: {}", name))
+            }
+            Command::Like => {
+                let current_playback = self.current_playback().await?;
+                let track_id = match current_playback.item.as_ref() {
+                    Some(PlayingItem::Track(track)) => track.id.clone(),
+                    _ => None,
+                };
+                let track_id = track_id.ok_or_else(CliError::no_playback)?;
+                let track_ids = vec![track_id.clone()];
+                if self.is_saved(&track_id).await? {
+                    self.spotify
+                        .fetch_delete_current_user_saved_tracks(&track_ids)
+                        .await?;
+                    Ok(format!("Unliked: {}", describe(&current_playback)))
+                } else {
+                    self.spotify
+                        .fetch_add_current_user_saved_tracks(&track_ids)
+                        .await?;
+                    Ok(format!("Liked: {}", describe(&current_playback)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Option<Command>, CliError> {
+        let args: Vec<String> = line.split_whitespace().map(|x| x.to_string()).collect();
+        parse_args(&args)
+    }
+
+    fn usage_error(line: &str) -> String {
+        match parse(line) {
+            Err(e) => {
+                assert_eq!(e.exit_code, EXIT_USAGE);
+                e.message
+            }
+            Ok(command) => panic!("{} parsed as {:?}", line, command),
+        }
+    }
+
+    #[test]
+    fn seek_positions() {
+        assert_eq!(parse_seek_position("83"), Some(Position::Absolute(83_000)));
+        assert_eq!(
+            parse_seek_position("1:23"),
+            Some(Position::Absolute(83_000))
+        );
+        assert_eq!(
+            parse_seek_position("1:00:00"),
+            Some(Position::Absolute(3_600_000))
+        );
+        assert_eq!(parse_seek_position("+10"), Some(Position::Relative(10_000)));
+        assert_eq!(
+            parse_seek_position("-0:30"),
+            Some(Position::Relative(-30_000))
+        );
+        for arg in ["", "+", "1:", ":30", "1.5", "ten", "--10"] {
+            assert_eq!(parse_seek_position(arg), None, "{}", arg);
+        }
+    }
+
+    #[test]
+    fn volumes() {
+        assert_eq!(parse_volume("0"), Some(Position::Absolute(0)));
+        assert_eq!(parse_volume("100"), Some(Position::Absolute(100)));
+        assert_eq!(parse_volume("+10"), Some(Position::Relative(10)));
+        assert_eq!(parse_volume("-5"), Some(Position::Relative(-5)));
+        for arg in ["", "101", "+", "loud", "50%"] {
+            assert_eq!(parse_volume(arg), None, "{}", arg);
+        }
+    }
+
+    #[test]
+    fn positions_apply_to_the_current_value() {
+        assert_eq!(Position::Absolute(40).apply(70), 40);
+        assert_eq!(Position::Relative(10).apply(70), 80);
+        assert_eq!(Position::Relative(-100).apply(70), 0);
+    }
+
+    #[test]
+    fn commands() {
+        assert!(parse("").unwrap().is_none());
+        assert_eq!(parse("toggle").unwrap(), Some(Command::Toggle));
+        assert_eq!(parse("prev").unwrap(), Some(Command::Previous));
+        assert_eq!(
+            parse("play spotify:album:1").unwrap(),
+            Some(Command::Play(Some("spotify:album:1".to_string())))
+        );
+        assert_eq!(
+            parse("seek -10").unwrap(),
+            Some(Command::Seek(Position::Relative(-10_000)))
+        );
+        assert_eq!(parse("volume").unwrap(), Some(Command::Volume(None)));
+        assert_eq!(
+            parse("shuffle toggle").unwrap(),
+            Some(Command::Shuffle(None))
+        );
+        assert_eq!(
+            parse("shuffle off").unwrap(),
+            Some(Command::Shuffle(Some(false)))
+        );
+        assert_eq!(
+            parse("repeat track").unwrap(),
+            Some(Command::Repeat(Some(RepeatState::Track)))
+        );
+        assert_eq!(
+            parse("replay session.jsonl").unwrap(),
+            Some(Command::Replay(PathBuf::from("session.jsonl")))
+        );
+        assert_eq!(parse("-h").unwrap(), Some(Command::Help));
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert_eq!(usage_error("seek"), "unknown command: seek");
+        assert_eq!(usage_error("seek soon"), "invalid position: soon");
+        assert_eq!(usage_error("volume 150"), "invalid volume: 150");
+        assert_eq!(
+            usage_error("pause now"),
+            "unknown command or argument: pause now"
+        );
+        assert_eq!(usage_error("next 1 2"), "too many arguments for next");
+        assert_eq!(usage_error("rewind"), "unknown command: rewind");
+    }
+
+    #[test]
+    fn status_options() {
+        let args: Vec<String> = ["status", "--json", "--format", "{title} by {artist}"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            parse_args(&args).unwrap(),
+            Some(Command::Status(StatusOptions {
+                format: Some("{title} by {artist}".to_string()),
+                json: true,
+                ..StatusOptions::default()
+            }))
+        );
+        match parse("status --watch --interval 0.5").unwrap() {
+            Some(Command::Status(options)) => {
+                assert!(options.watch);
+                assert_eq!(options.interval, Duration::from_millis(500));
+            }
+            command => panic!("{:?}", command),
+        }
+        assert_eq!(usage_error("status --interval 0"), "invalid --interval");
+        assert_eq!(usage_error("status --format"), "--format needs a template");
+        assert_eq!(
+            usage_error("status --color"),
+            "unknown option for status: --color"
+        );
+    }
+
+    #[test]
+    fn export_options() {
+        match parse("export liked likes.csv --columns name,uri --format json").unwrap() {
+            Some(Command::Export(options)) => {
+                assert_eq!(options.source, Source::Liked);
+                assert_eq!(options.path, PathBuf::from("likes.csv"));
+                assert_eq!(options.columns, vec![Column::Name, Column::Uri]);
+                assert_eq!(options.format, Some(Format::Json));
+            }
+            command => panic!("{:?}", command),
+        }
+        assert_eq!(usage_error("export"), "export needs a list");
+        assert_eq!(usage_error("export liked"), "export needs a file");
+        assert_eq!(usage_error("export queue q.csv"), "unknown list: queue");
+        assert_eq!(
+            usage_error("export liked l.csv --format"),
+            "--format needs a value"
+        );
+        assert_eq!(
+            usage_error("export liked l.csv --format xml"),
+            "unknown format: xml"
+        );
+        assert!(usage_error("export liked l.csv --filter year:<").starts_with("--filter: "));
+    }
+}
@@ -0,0 +1,1091 @@
+extern crate rspotify;
+
+use crate::cli;
+use crate::cli::Position;
+use crate::config;
+use crate::export;
+use crate::export::Column;
+use crate::table;
+
+use self::rspotify::senum::RepeatState;
+use std::cmp;
+use std::fs;
+use std::path::PathBuf;
+use termion::event::Key;
+
+//how many lines of `:` history are kept in data_dir()/command_history
+const HISTORY_LIMIT: usize = 100;
+//a larger count prefix is cut down to this
+const COUNT_LIMIT: usize = 100_000;
+
+/// A `:` command, its arguments and what it does, for completion and the help.
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub description: &'static str,
+}
+
+pub const COMMANDS: [CommandInfo; 23] = [
+    CommandInfo {
+        name: "batch",
+        args: "like | unlike | queue | play | playlist NAME",
+        description: "act on the marked tracks (or the selected one)",
+    },
+    CommandInfo {
+        name: "details",
+        args: "",
+        description: "show the details of the selected (or playing) track",
+    },
+    CommandInfo {
+        name: "device",
+        args: "NAME",
+        description: "transfer playback to a device",
+    },
+    CommandInfo {
+        name: "diff",
+        args: "[FROM] [TO]",
+        description: "compare snapshots of Liked Songs",
+    },
+    CommandInfo {
+        name: "export",
+        args: "FILE [COLUMNS]",
+        description: "write the rows (or the marked rows) of this view to CSV, JSON or M3U",
+    },
+    CommandInfo {
+        name: "filter",
+        args: "[TEXT]",
+        description: "filter the lists",
+    },
+    CommandInfo {
+        name: "help",
+        args: "",
+        description: "show this help",
+    },
+    CommandInfo {
+        name: "import",
+        args: "FILE | apply [playlist NAME] | retry",
+        description: "match the tracks of a file, then add them",
+    },
+    CommandInfo {
+        name: "jump",
+        args: "",
+        description: "select the playing track",
+    },
+    CommandInfo {
+        name: "like",
+        args: "",
+        description: "like or unlike the playing track",
+    },
+    CommandInfo {
+        name: "loop",
+        args: "a | b | clear",
+        description: "set or clear the A-B loop",
+    },
+    CommandInfo {
+        name: "messages",
+        args: "",
+        description: "show the message log",
+    },
+    CommandInfo {
+        name: "next",
+        args: "",
+        description: "next track",
+    },
+    CommandInfo {
+        name: "prev",
+        args: "",
+        description: "previous track",
+    },
+    CommandInfo {
+        name: "quit",
+        args: "",
+        description: "quit",
+    },
+    CommandInfo {
+        name: "repeat",
+        args: "[off | track | context]",
+        description: "set or cycle the repeat state",
+    },
+    CommandInfo {
+        name: "seek",
+        args: "[+|-]POSITION",
+        description: "seek to a position such as 1:30 or +10",
+    },
+    CommandInfo {
+        name: "shuffle",
+        args: "[on | off | toggle]",
+        description: "set or toggle shuffle",
+    },
+    CommandInfo {
+        name: "sort",
+        args: "[COLUMN [asc | desc]]",
+        description: "sort the table",
+    },
+    CommandInfo {
+        name: "tab",
+        args: "NAME | NUMBER",
+        description: "switch to a tab",
+    },
+    CommandInfo {
+        name: "toggle",
+        args: "",
+        description: "play or pause",
+    },
+    CommandInfo {
+        name: "unmark",
+        args: "",
+        description: "leave the visual selection, or unmark every track",
+    },
+    CommandInfo {
+        name: "volume",
+        args: "[+|-]PERCENT",
+        description: "set the volume",
+    },
+];
+
+/// Where a key binding applies, the groups of the help.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyContext {
+    Global,
+    List,
+    //typing a filter or a `:` command
+    Input,
+}
+
+impl KeyContext {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyContext::Global => "Global",
+            KeyContext::List => "List",
+            KeyContext::Input => "Input mode",
+        }
+    }
+}
+
+pub struct KeyBinding {
+    pub keys: &'static [Key],
+    pub context: KeyContext,
+    //None for keys handled by their mode or by KeyPrefix
+    pub action: Option<Action>,
+    pub description: &'static str,
+}
+
+/// The keymap of the normal mode, and the keys of the input modes for the help.
+pub const KEYMAP: &[KeyBinding] = &[
+    KeyBinding {
+        keys: &[Key::Char('q')],
+        context: KeyContext::Global,
+        action: Some(Action::Quit),
+        description: "quit",
+    },
+    KeyBinding {
+        keys: &[Key::Char('?')],
+        context: KeyContext::Global,
+        action: Some(Action::Help),
+        description: "show this help",
+    },
+    KeyBinding {
+        keys: &[Key::Char('m')],
+        context: KeyContext::Global,
+        action: Some(Action::Messages),
+        description: "show the message log",
+    },
+    KeyBinding {
+        keys: &[Key::Char('i')],
+        context: KeyContext::Global,
+        action: Some(Action::Details),
+        description: "show the details of the selected (or playing) track",
+    },
+    KeyBinding {
+        keys: &[Key::Char('p')],
+        context: KeyContext::Global,
+        action: Some(Action::TogglePlayback),
+        description: "play or pause",
+    },
+    KeyBinding {
+        keys: &[Key::Char('>')],
+        context: KeyContext::Global,
+        action: Some(Action::NextTrack),
+        description: "next track",
+    },
+    KeyBinding {
+        keys: &[Key::Char('<')],
+        context: KeyContext::Global,
+        action: Some(Action::PreviousTrack),
+        description: "previous track (or the start of this one)",
+    },
+    KeyBinding {
+        keys: &[Key::Char('f')],
+        context: KeyContext::Global,
+        action: Some(Action::Like),
+        description: "like or unlike the playing track",
+    },
+    KeyBinding {
+        keys: &[Key::Char('+')],
+        context: KeyContext::Global,
+        action: Some(Action::VolumeUp),
+        description: "volume up",
+    },
+    KeyBinding {
+        keys: &[Key::Char('-')],
+        context: KeyContext::Global,
+        action: Some(Action::VolumeDown),
+        description: "volume down",
+    },
+    KeyBinding {
+        keys: &[Key::Char('S')],
+        context: KeyContext::Global,
+        action: Some(Action::Shuffle(None)),
+        description: "toggle shuffle",
+    },
+    KeyBinding {
+        keys: &[Key::Char('r')],
+        context: KeyContext::Global,
+        action: Some(Action::Repeat(None)),
+        description: "cycle repeat: off, context, track",
+    },
+    KeyBinding {
+        keys: &[Key::Char('a')],
+        context: KeyContext::Global,
+        action: Some(Action::LoopA),
+        description: "set the start of the A-B loop",
+    },
+    KeyBinding {
+        keys: &[Key::Char('b')],
+        context: KeyContext::Global,
+        action: Some(Action::LoopB),
+        description: "set the end of the A-B loop",
+    },
+    KeyBinding {
+        keys: &[Key::Char('c')],
+        context: KeyContext::Global,
+        action: Some(Action::ClearLoop),
+        description: "clear the A-B loop",
+    },
+    KeyBinding {
+        keys: &[Key::Right, Key::Char('l')],
+        context: KeyContext::Global,
+        action: Some(Action::NextTab),
+        description: "next tab",
+    },
+    KeyBinding {
+        keys: &[Key::Left, Key::Char('h')],
+        context: KeyContext::Global,
+        action: Some(Action::PreviousTab),
+        description: "previous tab",
+    },
+    KeyBinding {
+        keys: &[Key::Char('/')],
+        context: KeyContext::Global,
+        action: Some(Action::FilterMode),
+        description: "type a filter",
+    },
+    KeyBinding {
+        keys: &[Key::Char(':')],
+        context: KeyContext::Global,
+        action: Some(Action::CommandMode),
+        description: "type a command",
+    },
+    KeyBinding {
+        keys: &[Key::Down, Key::Char('j')],
+        context: KeyContext::List,
+        action: Some(Action::Down),
+        description: "next row",
+    },
+    KeyBinding {
+        keys: &[Key::Up, Key::Char('k')],
+        context: KeyContext::List,
+        action: Some(Action::Up),
+        description: "previous row",
+    },
+    KeyBinding {
+        keys: &[Key::PageDown, Key::Ctrl('f')],
+        context: KeyContext::List,
+        action: Some(Action::Move(Motion::Pages(1))),
+        description: "page down",
+    },
+    KeyBinding {
+        keys: &[Key::PageUp, Key::Ctrl('b')],
+        context: KeyContext::List,
+        action: Some(Action::Move(Motion::Pages(-1))),
+        description: "page up",
+    },
+    KeyBinding {
+        keys: &[Key::Ctrl('d')],
+        context: KeyContext::List,
+        action: Some(Action::Move(Motion::HalfPages(1))),
+        description: "half a page down",
+    },
+    KeyBinding {
+        keys: &[Key::Ctrl('u')],
+        context: KeyContext::List,
+        action: Some(Action::Move(Motion::HalfPages(-1))),
+        description: "half a page up",
+    },
+    KeyBinding {
+        keys: &[Key::Home],
+        context: KeyContext::List,
+        action: Some(Action::Move(Motion::Top)),
+        description: "first row",
+    },
+    KeyBinding {
+        keys: &[Key::Char('g')],
+        context: KeyContext::List,
+        action: None,
+        description: "gg: first row, 25gg: row 25",
+    },
+    KeyBinding {
+        keys: &[Key::End, Key::Char('G')],
+        context: KeyContext::List,
+        action: Some(Action::Move(Motion::Bottom)),
+        description: "last row, 25G: row 25",
+    },
+    KeyBinding {
+        keys: &[
+            Key::Char('1'),
+            Key::Char('2'),
+            Key::Char('3'),
+            Key::Char('4'),
+            Key::Char('5'),
+            Key::Char('6'),
+            Key::Char('7'),
+            Key::Char('8'),
+            Key::Char('9'),
+        ],
+        context: KeyContext::List,
+        action: None,
+        description: "a count for the next move, e.g. 25j",
+    },
+    KeyBinding {
+        keys: &[Key::Char('.')],
+        context: KeyContext::List,
+        action: Some(Action::JumpToPlaying),
+        description: "select the playing track",
+    },
+    KeyBinding {
+        keys: &[Key::Char('\n')],
+        context: KeyContext::List,
+        action: Some(Action::Enter),
+        description: "play from the selected row (accept in Import, like again in Library Diff)",
+    },
+    KeyBinding {
+        keys: &[Key::Char('[')],
+        context: KeyContext::List,
+        action: Some(Action::PreviousPeriod),
+        description: "older month, snapshot or previous alternative",
+    },
+    KeyBinding {
+        keys: &[Key::Char(']')],
+        context: KeyContext::List,
+        action: Some(Action::NextPeriod),
+        description: "newer month, snapshot or next alternative",
+    },
+    KeyBinding {
+        keys: &[Key::Char(' ')],
+        context: KeyContext::List,
+        action: Some(Action::Mark),
+        description: "mark or unmark the selected track",
+    },
+    KeyBinding {
+        keys: &[Key::Char('v')],
+        context: KeyContext::List,
+        action: Some(Action::Visual),
+        description: "start a visual selection, or mark its tracks",
+    },
+    KeyBinding {
+        keys: &[Key::Esc],
+        context: KeyContext::List,
+        action: Some(Action::ClearMarks),
+        description: "leave the visual selection, or unmark every track",
+    },
+    KeyBinding {
+        keys: &[Key::Char('F')],
+        context: KeyContext::List,
+        action: Some(Action::Batch(Batch::Like)),
+        description: "like the marked tracks (or the selected one)",
+    },
+    KeyBinding {
+        keys: &[Key::Char('D')],
+        context: KeyContext::List,
+        action: Some(Action::Batch(Batch::Unlike)),
+        description: "remove the marked tracks from Liked Songs",
+    },
+    KeyBinding {
+        keys: &[Key::Char('e')],
+        context: KeyContext::List,
+        action: Some(Action::Batch(Batch::Queue)),
+        description: "add the marked tracks to the queue",
+    },
+    KeyBinding {
+        keys: &[Key::Char('P')],
+        context: KeyContext::List,
+        action: Some(Action::Batch(Batch::Play)),
+        description: "play only the marked tracks",
+    },
+    KeyBinding {
+        keys: &[Key::Char('o')],
+        context: KeyContext::List,
+        action: Some(Action::Sort(None, None)),
+        description: "sort by the next column",
+    },
+    KeyBinding {
+        keys: &[Key::Char('O')],
+        context: KeyContext::List,
+        action: Some(Action::ReverseSort),
+        description: "reverse the order",
+    },
+    KeyBinding {
+        keys: &[Key::Char('\n'), Key::Esc],
+        context: KeyContext::Input,
+        action: None,
+        description: "finish the filter, run the command (Esc: cancel it)",
+    },
+    KeyBinding {
+        keys: &[Key::Backspace],
+        context: KeyContext::Input,
+        action: None,
+        description: "delete a character; leaves an empty command",
+    },
+    KeyBinding {
+        keys: &[Key::Char('\t')],
+        context: KeyContext::Input,
+        action: None,
+        description: "complete the command or its argument",
+    },
+    KeyBinding {
+        keys: &[Key::Up, Key::Down],
+        context: KeyContext::Input,
+        action: None,
+        description: "previous or next command in the history",
+    },
+];
+
+/// A readable name of `key`, such as "Ctrl-f" or "Space".
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("Ctrl-{}", c),
+        Key::Alt(c) => format!("Alt-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Up => "↑".to_string(),
+        Key::Down => "↓".to_string(),
+        Key::Left => "←".to_string(),
+        Key::Right => "→".to_string(),
+        Key::PageUp => "PgUp".to_string(),
+        Key::PageDown => "PgDn".to_string(),
+        key => format!("{:?}", key),
+    }
+}
+
+impl KeyBinding {
+    //the keys joined, with a run of digits as a range such as "1-9"
+    pub fn keys_text(&self) -> String {
+        let digits: Vec<char> = self
+            .keys
+            .iter()
+            .filter_map(|x| match x {
+                Key::Char(c) if c.is_ascii_digit() => Some(*c),
+                _ => None,
+            })
+            .collect();
+        if digits.len() > 2 && digits.len() == self.keys.len() {
+            return format!("{}-{}", digits[0], digits[digits.len() - 1]);
+        }
+        self.keys
+            .iter()
+            .map(|x| key_name(*x))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// How far a list selection moves; negative counts move up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Motion {
+    Rows(isize),
+    Pages(isize),
+    HalfPages(isize),
+    Top,
+    Bottom,
+    //0-based, e.g. `25G` is Row(24)
+    Row(usize),
+}
+
+impl Motion {
+    /// The row this moves `selected` to in a list of `len` rows (`len` > 0) showing `page`
+    /// rows at a time; it stops at the first and the last row.
+    pub fn target(self, selected: Option<usize>, len: usize, page: usize) -> usize {
+        //nothing selected is just above the first row
+        let current = selected.map_or(-1, |x| x as isize);
+        let page = cmp::max(page, 1) as isize;
+        let last = len as isize - 1;
+        let target = match self {
+            Motion::Rows(n) => current + n,
+            Motion::Pages(n) => current + n * page,
+            Motion::HalfPages(n) => current + n * cmp::max(page / 2, 1),
+            Motion::Top => 0,
+            Motion::Bottom => last,
+            Motion::Row(row) => row as isize,
+        };
+        target.clamp(0, last) as usize
+    }
+    fn times(self, count: usize) -> Motion {
+        let count = count as isize;
+        match self {
+            Motion::Rows(n) => Motion::Rows(n * count),
+            Motion::Pages(n) => Motion::Pages(n * count),
+            Motion::HalfPages(n) => Motion::HalfPages(n * count),
+            motion => motion,
+        }
+    }
+}
+
+/// What to do with the marked tracks of a view.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Batch {
+    Like,
+    Unlike,
+    Queue,
+    Play,
+    //a playlist name, ID or URI
+    Playlist(String),
+}
+
+/// Something the user can do from a key or a `:` command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    TogglePlayback,
+    NextTrack,
+    PreviousTrack,
+    Seek(Position),
+    VolumeUp,
+    VolumeDown,
+    Volume(Position),
+    Shuffle(Option<bool>),
+    Repeat(Option<RepeatState>),
+    Like,
+    Device(String),
+    NextTab,
+    PreviousTab,
+    //a part of the tab title (e.g. "liked") or its 1-based number
+    Tab(String),
+    Filter(String),
+    //the next column when None; the same column again reverses the order
+    Sort(Option<Column>, Option<bool>),
+    ReverseSort,
+    //writes the filtered rows of the current view; the format is from the extension
+    Export(String, Vec<Column>),
+    //compares two snapshots of Liked Songs (dates or their prefixes), the latest by default
+    Diff(Option<String>, Option<String>),
+    //reads a CSV, M3U or text file and searches its tracks for review
+    Import(String),
+    //adds the accepted matches to Liked Songs or to the named playlist
+    ImportApply(Option<String>),
+    //searches the entries whose search failed again
+    ImportRetry,
+    FilterMode,
+    CommandMode,
+    Down,
+    Up,
+    Move(Motion),
+    //selects the playing track in the current view
+    JumpToPlaying,
+    Enter,
+    PreviousPeriod,
+    NextPeriod,
+    Help,
+    Messages,
+    Details,
+    Mark,
+    Visual,
+    ClearMarks,
+    Batch(Batch),
+    LoopA,
+    LoopB,
+    ClearLoop,
+}
+
+/// The action bound to `key` in the normal mode.
+pub fn key_action(key: Key) -> Option<Action> {
+    KEYMAP
+        .iter()
+        .filter(|x| x.context != KeyContext::Input)
+        .find(|x| x.keys.contains(&key))
+        .and_then(|x| x.action.clone())
+}
+
+/// Parses a `:` command line such as `volume 40` or `tab liked`.
+pub fn parse_command(line: &str) -> Result<Action, String> {
+    let line = line.trim();
+    let (name, arg) = match line.find(' ') {
+        Some(index) => (&line[..index], Some(line[index + 1..].trim())),
+        None => (line, None),
+    };
+    let action = match (name, arg) {
+        ("q", None) | ("quit", None) => Action::Quit,
+        ("toggle", None) | ("play", None) | ("pause", None) => Action::TogglePlayback,
+        ("next", None) => Action::NextTrack,
+        ("prev", None) | ("previous", None) => Action::PreviousTrack,
+        ("seek", Some(arg)) => match cli::parse_seek_position(arg) {
+            Some(position) => Action::Seek(position),
+            None => return Err(format!("invalid position: {}", arg)),
+        },
+        ("volume", Some(arg)) => match cli::parse_volume(arg) {
+            Some(volume) => Action::Volume(volume),
+            None => return Err(format!("invalid volume: {}", arg)),
+        },
+        ("shuffle", None) | ("shuffle", Some("toggle")) => Action::Shuffle(None),
+        ("shuffle", Some("on")) => Action::Shuffle(Some(true)),
+        ("shuffle", Some("off")) => Action::Shuffle(Some(false)),
+        ("repeat", None) => Action::Repeat(None),
+        ("repeat", Some("off")) => Action::Repeat(Some(RepeatState::Off)),
+        ("repeat", Some("track")) => Action::Repeat(Some(RepeatState::Track)),
+        ("repeat", Some("context")) => Action::Repeat(Some(RepeatState::Context)),
+        ("like", None) => Action::Like,
+        ("help", None) => Action::Help,
+        ("messages", None) => Action::Messages,
+        ("details", None) => Action::Details,
+        ("jump", None) => Action::JumpToPlaying,
+        ("device", Some(name)) => Action::Device(name.to_string()),
+        ("tab", Some(name)) => Action::Tab(name.to_string()),
+        ("filter", arg) => Action::Filter(arg.unwrap_or("").to_string()),
+        ("sort", None) => Action::Sort(None, None),
+        //without asc or desc, the same column again reverses the order
+        ("sort", Some(arg)) => {
+            let (column, descending) = table::parse_sort(arg)?;
+            let descending = if arg.split_whitespace().count() > 1 {
+                Some(descending)
+            } else {
+                None
+            };
+            Action::Sort(Some(column), descending)
+        }
+        ("export", Some(arg)) => {
+            let mut args = arg.split_whitespace();
+            let path = args.next().unwrap_or("").to_string();
+            let columns = match args.next() {
+                Some(columns) => export::parse_columns(columns)?,
+                None => export::ALL_COLUMNS.to_vec(),
+            };
+            Action::Export(path, columns)
+        }
+        ("diff", arg) => {
+            let mut dates = arg.unwrap_or("").split_whitespace();
+            let from = dates.next().map(|x| x.to_string());
+            let to = dates.next().map(|x| x.to_string());
+            if dates.next().is_some() {
+                return Err("usage: diff [FROM] [TO]".to_string());
+            }
+            Action::Diff(from, to)
+        }
+        ("import", Some("apply")) => Action::ImportApply(None),
+        ("import", Some(arg)) if arg.starts_with("apply ") => {
+            let playlist = arg["apply ".len()..].trim();
+            let playlist = playlist
+                .strip_prefix("playlist ")
+                .unwrap_or(playlist)
+                .trim();
+            Action::ImportApply(Some(playlist.to_string()))
+        }
+        ("import", Some("retry")) => Action::ImportRetry,
+        ("import", Some(path)) => Action::Import(path.to_string()),
+        ("batch", Some("like")) => Action::Batch(Batch::Like),
+        ("batch", Some("unlike")) => Action::Batch(Batch::Unlike),
+        ("batch", Some("queue")) => Action::Batch(Batch::Queue),
+        ("batch", Some("play")) => Action::Batch(Batch::Play),
+        ("batch", Some(arg)) if arg.starts_with("playlist ") => {
+            Action::Batch(Batch::Playlist(arg["playlist ".len()..].trim().to_string()))
+        }
+        ("unmark", None) => Action::ClearMarks,
+        ("loop", Some("a")) => Action::LoopA,
+        ("loop", Some("b")) => Action::LoopB,
+        ("loop", Some("clear")) => Action::ClearLoop,
+        ("", None) => return Err(String::new()),
+        (name, _) if COMMANDS.iter().any(|x| x.name == name) => {
+            return Err(format!("invalid arguments for {}", name))
+        }
+        (name, _) => return Err(format!("unknown command: {}", name)),
+    };
+    Ok(action)
+}
+
+/// The count typed before a key in the normal mode (the 25 of `25j`) and a pending `g`
+/// of `gg`.
+#[derive(Clone, Debug, Default)]
+pub struct KeyPrefix {
+    count: Option<usize>,
+    g: bool,
+}
+
+impl KeyPrefix {
+    /// The action of `key` with the prefix typed before it; None while a prefix is being
+    /// typed or if nothing is bound to the keys.
+    pub fn action(&mut self, key: Key) -> Option<Action> {
+        if let Key::Char(c @ '0'..='9') = key {
+            //a leading 0 is not a count
+            if c != '0' || self.count.is_some() {
+                let digit = c.to_digit(10).unwrap() as usize;
+                let count = self.count.unwrap_or(0) * 10 + digit;
+                self.count = Some(cmp::min(count, COUNT_LIMIT));
+                self.g = false;
+                return None;
+            }
+        }
+        let count = self.count.take();
+        //Esc cancels the prefix rather than running its action
+        if key == Key::Esc && (count.is_some() || self.g) {
+            self.g = false;
+            return None;
+        }
+        if self.g {
+            self.g = false;
+            return match key {
+                Key::Char('g') => Some(Action::Move(match count {
+                    Some(count) => Motion::Row(count.saturating_sub(1)),
+                    None => Motion::Top,
+                })),
+                _ => None,
+            };
+        }
+        let action = match key {
+            Key::Char('g') => {
+                self.g = true;
+                self.count = count;
+                return None;
+            }
+            key => key_action(key)?,
+        };
+        let count = match count {
+            Some(count) => count,
+            None => return Some(action),
+        };
+        Some(match action {
+            Action::Down => Action::Move(Motion::Rows(count as isize)),
+            Action::Up => Action::Move(Motion::Rows(-(count as isize))),
+            //`25G` goes to the 25th row
+            Action::Move(Motion::Bottom) => Action::Move(Motion::Row(count.saturating_sub(1))),
+            Action::Move(motion) => Action::Move(motion.times(count)),
+            action => action,
+        })
+    }
+    //the keys typed so far, shown until the action is complete
+    pub fn pending(&self) -> String {
+        let count = self.count.map(|x| x.to_string()).unwrap_or_default();
+        if self.g {
+            count + "g"
+        } else {
+            count
+        }
+    }
+}
+
+struct Completion {
+    //the input before the completed word
+    base: String,
+    matches: Vec<String>,
+    index: usize,
+}
+
+/// The `:` input line with tab completion and history.
+pub struct CommandLine {
+    pub active: bool,
+    pub input: String,
+    history: Vec<String>,
+    history_index: Option<usize>,
+    history_path: PathBuf,
+    completion: Option<Completion>,
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        CommandLine::new()
+    }
+}
+
+impl CommandLine {
+    pub fn new() -> CommandLine {
+        let history_path = config::data_dir().join("command_history");
+        let history = fs::read_to_string(&history_path)
+            .map(|x| x.lines().map(|x| x.to_string()).collect())
+            .unwrap_or_default();
+        CommandLine {
+            active: false,
+            input: String::new(),
+            history,
+            history_index: None,
+            history_path,
+            completion: None,
+        }
+    }
+    pub fn open(&mut self) {
+        self.active = true;
+        self.input.clear();
+        self.history_index = None;
+        self.completion = None;
+    }
+    pub fn close(&mut self) {
+        self.active = false;
+        self.input.clear();
+        self.completion = None;
+    }
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.completion = None;
+    }
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.completion = None;
+    }
+    //returns the entered line and remembers it
+    pub fn submit(&mut self) -> String {
+        let line = self.input.trim().to_string();
+        self.close();
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > HISTORY_LIMIT {
+                self.history.remove(0);
+            }
+            if let Some(dir) = self.history_path.parent() {
+                fs::create_dir_all(dir).ok();
+            }
+            if let Err(e) = fs::write(&self.history_path, self.history.join("\n")) {
+                log::error!("failed to save the command history: {}", e);
+            }
+        }
+        line
+    }
+    pub fn history_previous(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match self.history_index {
+            Some(index) if index > 0 => index - 1,
+            Some(index) => index,
+            None => self.history.len() - 1,
+        };
+        self.history_index = Some(index);
+        self.input = self.history[index].clone();
+        self.completion = None;
+    }
+    pub fn history_next(&mut self) {
+        match self.history_index {
+            Some(index) if index + 1 < self.history.len() => {
+                self.history_index = Some(index + 1);
+                self.input = self.history[index + 1].clone();
+            }
+            Some(_) => {
+                self.history_index = None;
+                self.input.clear();
+            }
+            None => {}
+        }
+        self.completion = None;
+    }
+    //the command being typed, e.g. "device" for "device Kit"
+    pub fn command_name(&self) -> &str {
+        self.input.split(' ').next().unwrap_or("")
+    }
+    /// Completes the command name, or its argument from `arguments` (the candidates for
+    /// `command_name()`); pressing Tab again cycles through the matches.
+    pub fn complete(&mut self, arguments: Vec<String>) {
+        if let Some(completion) = self.completion.as_mut() {
+            completion.index = (completion.index + 1) % completion.matches.len();
+            self.input = format!(
+                "{}{}",
+                completion.base, completion.matches[completion.index]
+            );
+            return;
+        }
+        //arguments are the rest of the line, so device names may contain spaces
+        let (base, word, candidates) = match self.input.find(' ') {
+            Some(index) => (
+                self.input[..index + 1].to_string(),
+                self.input[index + 1..].to_string(),
+                arguments,
+            ),
+            None => (
+                String::new(),
+                self.input.clone(),
+                COMMANDS.iter().map(|x| x.name.to_string()).collect(),
+            ),
+        };
+        let word = word.to_lowercase();
+        let matches: Vec<String> = candidates
+            .into_iter()
+            .filter(|x| x.to_lowercase().starts_with(&word))
+            .collect();
+        if matches.is_empty() {
+            return;
+        }
+        self.input = format!("{}{}", base, matches[0]);
+        self.completion = Some(Completion {
+            base,
+            matches,
+            index: 0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the action of typing `keys` in the normal mode
+    fn typed(keys: &str) -> Vec<Action> {
+        let mut prefix = KeyPrefix::default();
+        keys.chars()
+            .filter_map(|c| prefix.action(Key::Char(c)))
+            .collect()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command("  pause ").unwrap(), Action::TogglePlayback);
+        assert_eq!(
+            parse_command("volume -10").unwrap(),
+            Action::Volume(Position::Relative(-10))
+        );
+        assert_eq!(
+            parse_command("seek 1:30").unwrap(),
+            Action::Seek(Position::Absolute(90_000))
+        );
+        assert_eq!(
+            parse_command("device Living Room").unwrap(),
+            Action::Device("Living Room".to_string())
+        );
+        assert_eq!(
+            parse_command("filter").unwrap(),
+            Action::Filter(String::new())
+        );
+        assert_eq!(
+            parse_command("diff 2020-01 2020-02").unwrap(),
+            Action::Diff(Some("2020-01".to_string()), Some("2020-02".to_string()))
+        );
+        assert_eq!(
+            parse_command("import apply playlist Road Trip").unwrap(),
+            Action::ImportApply(Some("Road Trip".to_string()))
+        );
+        assert_eq!(
+            parse_command("import apply").unwrap(),
+            Action::ImportApply(None)
+        );
+        assert_eq!(
+            parse_command("batch playlist Road Trip").unwrap(),
+            Action::Batch(Batch::Playlist("Road Trip".to_string()))
+        );
+        assert_eq!(parse_command("loop b").unwrap(), Action::LoopB);
+    }
+
+    #[test]
+    fn sort_and_export_arguments() {
+        assert_eq!(parse_command("sort").unwrap(), Action::Sort(None, None));
+        //only an explicit order is passed on, the same column again reverses it
+        assert_eq!(
+            parse_command("sort name").unwrap(),
+            Action::Sort(Some(Column::Name), None)
+        );
+        assert_eq!(
+            parse_command("sort added_at desc").unwrap(),
+            Action::Sort(Some(Column::AddedAt), Some(true))
+        );
+        assert_eq!(
+            parse_command("export out.csv name,uri").unwrap(),
+            Action::Export(
+                "out.csv".to_string(),
+                vec![export::Column::Name, export::Column::Uri]
+            )
+        );
+    }
+
+    #[test]
+    fn command_errors() {
+        assert_eq!(parse_command("").unwrap_err(), "");
+        assert_eq!(
+            parse_command("rewind").unwrap_err(),
+            "unknown command: rewind"
+        );
+        assert_eq!(
+            parse_command("volume").unwrap_err(),
+            "invalid arguments for volume"
+        );
+        assert_eq!(
+            parse_command("volume loud").unwrap_err(),
+            "invalid volume: loud"
+        );
+        assert_eq!(
+            parse_command("diff 1 2 3").unwrap_err(),
+            "usage: diff [FROM] [TO]"
+        );
+        assert!(parse_command("sort rating").is_err());
+    }
+
+    #[test]
+    fn counts_and_gg() {
+        assert_eq!(typed("j"), vec![Action::Down]);
+        assert_eq!(typed("5j"), vec![Action::Move(Motion::Rows(5))]);
+        assert_eq!(typed("12k"), vec![Action::Move(Motion::Rows(-12))]);
+        assert_eq!(typed("gg"), vec![Action::Move(Motion::Top)]);
+        assert_eq!(typed("25gg"), vec![Action::Move(Motion::Row(24))]);
+        assert_eq!(typed("25G"), vec![Action::Move(Motion::Row(24))]);
+        assert_eq!(typed("G"), vec![Action::Move(Motion::Bottom)]);
+        //a leading 0 is not a count, and g before another key does nothing
+        assert_eq!(typed("0j"), vec![Action::Down]);
+        assert_eq!(typed("gjj"), vec![Action::Down]);
+        //a count does not repeat other actions
+        assert_eq!(typed("3>"), vec![Action::NextTrack]);
+    }
+
+    #[test]
+    fn prefixes_are_pending_until_complete() {
+        let mut prefix = KeyPrefix::default();
+        assert_eq!(prefix.action(Key::Char('4')), None);
+        assert_eq!(prefix.action(Key::Char('2')), None);
+        assert_eq!(prefix.action(Key::Char('g')), None);
+        assert_eq!(prefix.pending(), "42g");
+        //Esc cancels the prefix without clearing the marks
+        assert_eq!(prefix.action(Key::Esc), None);
+        assert_eq!(prefix.pending(), "");
+        assert_eq!(prefix.action(Key::Esc), Some(Action::ClearMarks));
+        assert_eq!(
+            prefix.action(Key::Ctrl('d')),
+            Some(Action::Move(Motion::HalfPages(1)))
+        );
+        assert_eq!(prefix.action(Key::Char('2')), None);
+        assert_eq!(
+            prefix.action(Key::Ctrl('b')),
+            Some(Action::Move(Motion::Pages(-2)))
+        );
+    }
+
+    #[test]
+    fn motions_stop_at_the_ends() {
+        //10 rows, 4 shown
+        assert_eq!(Motion::Rows(1).target(None, 10, 4), 0);
+        assert_eq!(Motion::Rows(-1).target(Some(0), 10, 4), 0);
+        assert_eq!(Motion::Rows(3).target(Some(8), 10, 4), 9);
+        assert_eq!(Motion::Pages(1).target(Some(2), 10, 4), 6);
+        assert_eq!(Motion::HalfPages(-1).target(Some(2), 10, 4), 0);
+        assert_eq!(Motion::HalfPages(1).target(Some(2), 10, 1), 3);
+        assert_eq!(Motion::Bottom.target(Some(2), 10, 4), 9);
+        assert_eq!(Motion::Row(24).target(Some(2), 10, 4), 9);
+        assert_eq!(Motion::Top.target(Some(5), 10, 0), 0);
+    }
+
+    #[test]
+    fn keys_are_named_for_the_help() {
+        assert_eq!(key_name(Key::Char(' ')), "Space");
+        assert_eq!(key_name(Key::Ctrl('f')), "Ctrl-f");
+        let binding = KeyBinding {
+            keys: &[Key::Char('1'), Key::Char('2'), Key::Char('3')],
+            context: KeyContext::Global,
+            action: None,
+            description: "",
+        };
+        assert_eq!(binding.keys_text(), "1-3");
+        assert!(KEYMAP.iter().any(|x| x.keys_text() == "PgDn Ctrl-f"));
+    }
+}
@@ -1,8 +1,62 @@
+use crate::cover::Protocol;
+
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+//"~/foo" to <home>/foo
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()
+            .expect("can not find home directory")
+            .join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+//~/.local/share/spoterm on Linux (falls back to ~/.spoterm)
+pub fn data_dir() -> PathBuf {
+    match dirs::data_dir() {
+        Some(dir) => dir.join("spoterm"),
+        None => dirs::home_dir()
+            .expect("can not find home directory")
+            .join(".spoterm"),
+    }
+}
+
+//~/.cache/spoterm on Linux (falls back to data_dir()); safe to delete
+pub fn cache_dir() -> PathBuf {
+    match dirs::cache_dir() {
+        Some(dir) => dir.join("spoterm"),
+        None => data_dir(),
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserConfig {
     pub profile: Profile,
+    #[serde(default)]
+    pub listenbrainz: Option<ListenBrainz>,
+    #[serde(default)]
+    pub lyrics: Lyrics,
+    #[serde(default)]
+    pub status: Status,
+    #[serde(default)]
+    pub liked_songs: LikedSongs,
+    #[serde(default)]
+    pub cover: Cover,
+}
+
+//[listenbrainz] in config.toml; url can point to a self-hosted server
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListenBrainz {
+    #[serde(default = "ListenBrainz::default_url")]
+    pub url: String,
+    pub token: String,
+}
+impl ListenBrainz {
+    fn default_url() -> String {
+        "https://api.listenbrainz.org".to_string()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,10 +73,61 @@ impl Profile {
     }
 }
 
+//[lyrics] in config.toml
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lyrics {
+    pub dir: Option<String>,
+}
+impl Lyrics {
+    //defaults to <data dir>/lyrics, "~/" is expanded
+    pub fn dir(&self) -> PathBuf {
+        match self.dir.as_ref() {
+            Some(dir) => expand_home(dir),
+            None => data_dir().join("lyrics"),
+        }
+    }
+}
+
+//[status] in config.toml, the default template of `spoterm status`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Status {
+    pub format: Option<String>,
+}
+
+//[liked_songs] in config.toml, the columns and order of the table
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LikedSongs {
+    //e.g. ["name:4", "artists:3", "duration:1"]; a width is relative to the others
+    pub columns: Option<Vec<String>>,
+    //e.g. "added_at desc"
+    pub sort: Option<String>,
+}
+
+//[cover] in config.toml, the album art in the player
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cover {
+    //"auto" (the default), "kitty", "sixel", "truecolor", "256" or "off"
+    pub protocol: Option<String>,
+}
+impl Cover {
+    pub fn protocol(&self) -> Protocol {
+        let name = self.protocol.as_deref().unwrap_or("auto");
+        Protocol::parse(name).unwrap_or_else(|| {
+            log::warn!("unknown protocol for the album art: {}", name);
+            Protocol::detect()
+        })
+    }
+}
+
 impl UserConfig {
     pub fn new() -> Self {
         UserConfig {
             profile: Profile::new(),
+            listenbrainz: None,
+            lyrics: Lyrics::default(),
+            status: Status::default(),
+            liked_songs: LikedSongs::default(),
+            cover: Cover::default(),
         }
     }
     pub fn client_id(mut self, client_id: String) -> Self {
@@ -0,0 +1,527 @@
+extern crate base64;
+extern crate crossbeam;
+extern crate jpeg_decoder;
+extern crate reqwest;
+extern crate rspotify;
+
+use self::jpeg_decoder::PixelFormat;
+use self::rspotify::model::image::Image as AlbumImage;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::Color;
+use tui::widgets::Widget;
+
+//decoded images of this many albums are kept
+const CACHE_LIMIT: usize = 50;
+//Spotify has each album image in a few sizes; the smallest at least this wide is fetched
+const HALF_BLOCKS_IMAGE_WIDTH: u32 = 64;
+const GRAPHICS_IMAGE_WIDTH: u32 = 300;
+//kitty takes the base64 data in chunks of at most this size
+const KITTY_CHUNK_SIZE: usize = 4096;
+//the id of the album art among the images of kitty
+const KITTY_IMAGE_ID: u32 = 1;
+//when the terminal does not tell the size of its cells in pixels
+const DEFAULT_CELL_SIZE: (usize, usize) = (10, 20);
+//the levels of each channel in the 6×6×6 cube of the 256 colors
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// A decoded image, RGB row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    /// Scaled to `width` × `height`, each pixel the average of those it covers.
+    pub fn resize(&self, width: usize, height: usize) -> Image {
+        let mut pixels = Vec::with_capacity(width * height);
+        if self.width > 0 && self.height > 0 {
+            for y in 0..height {
+                let top = y * self.height / height;
+                let bottom = cmp::max((y + 1) * self.height / height, top + 1);
+                for x in 0..width {
+                    let left = x * self.width / width;
+                    let right = cmp::max((x + 1) * self.width / width, left + 1);
+                    let mut sum = [0usize; 3];
+                    for row in top..bottom {
+                        for pixel in
+                            self.pixels[row * self.width + left..row * self.width + right].iter()
+                        {
+                            for (sum, channel) in sum.iter_mut().zip(pixel.iter()) {
+                                *sum += *channel as usize;
+                            }
+                        }
+                    }
+                    let count = (bottom - top) * (right - left);
+                    pixels.push([
+                        (sum[0] / count) as u8,
+                        (sum[1] / count) as u8,
+                        (sum[2] / count) as u8,
+                    ]);
+                }
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// Decodes a JPEG, the format of Spotify's album art.
+pub fn decode(data: &[u8]) -> Result<Image, failure::Error> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let data = decoder.decode()?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| failure::err_msg("no image in the JPEG"))?;
+    let pixels: Vec<[u8; 3]> = match info.pixel_format {
+        PixelFormat::RGB24 => data.chunks_exact(3).map(|x| [x[0], x[1], x[2]]).collect(),
+        PixelFormat::L8 => data.iter().map(|x| [*x; 3]).collect(),
+        //big endian, the high byte is enough
+        PixelFormat::L16 => data.chunks_exact(2).map(|x| [x[0]; 3]).collect(),
+        PixelFormat::CMYK32 => data
+            .chunks_exact(4)
+            .map(|x| {
+                let ink = |c: u8| ((255 - c as u32) * (255 - x[3] as u32) / 255) as u8;
+                [ink(x[0]), ink(x[1]), ink(x[2])]
+            })
+            .collect(),
+    };
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+/// How the album art is drawn: with the graphics of kitty or sixel, or in half blocks of
+/// 24-bit or 256 colors.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+    TrueColor,
+    Indexed,
+    #[default]
+    Off,
+}
+
+impl Protocol {
+    //the values of `protocol` in [cover] of config.toml
+    pub fn parse(name: &str) -> Option<Protocol> {
+        match name {
+            "auto" => Some(Protocol::detect()),
+            "kitty" => Some(Protocol::Kitty),
+            "sixel" => Some(Protocol::Sixel),
+            "truecolor" => Some(Protocol::TrueColor),
+            "256" => Some(Protocol::Indexed),
+            "off" => Some(Protocol::Off),
+            _ => None,
+        }
+    }
+    /// The best the terminal is known to support, from its environment. Graphics are not
+    /// used in tmux, which does not pass them through.
+    pub fn detect() -> Protocol {
+        let var = |name| env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+        let multiplexed = env::var_os("TMUX").is_some() || term.starts_with("screen");
+        if !multiplexed
+            && (env::var_os("KITTY_WINDOW_ID").is_some()
+                || term.contains("kitty")
+                || term.contains("ghostty")
+                || program == "WezTerm")
+        {
+            Protocol::Kitty
+        } else if !multiplexed
+            && (term.contains("sixel")
+                || term.starts_with("mlterm")
+                || term.starts_with("foot")
+                || term.starts_with("yaft"))
+        {
+            Protocol::Sixel
+        } else if ["truecolor", "24bit"].contains(&var("COLORTERM").as_str()) {
+            Protocol::TrueColor
+        } else {
+            Protocol::Indexed
+        }
+    }
+    pub fn is_graphics(self) -> bool {
+        self == Protocol::Kitty || self == Protocol::Sixel
+    }
+    //graphics are scaled down by the terminal, half blocks need few pixels
+    fn image_width(self) -> u32 {
+        if self.is_graphics() {
+            GRAPHICS_IMAGE_WIDTH
+        } else {
+            HALF_BLOCKS_IMAGE_WIDTH
+        }
+    }
+}
+
+//the smallest image at least `width` wide, or else the largest
+fn image_url(images: &[AlbumImage], width: u32) -> Option<&str> {
+    let width_of = |x: &AlbumImage| x.width.unwrap_or(0);
+    images
+        .iter()
+        .filter(|x| width_of(x) >= width)
+        .min_by_key(|x| width_of(x))
+        .or_else(|| images.iter().max_by_key(|x| width_of(x)))
+        .map(|x| x.url.as_str())
+}
+
+/// The album id and the URL of its image.
+pub type CoverRequest = (String, String);
+/// The album id and its decoded image, or why there is none.
+pub type CoverResult = (String, Result<Image, String>);
+
+/// Fetches and decodes album art off the UI thread.
+pub struct CoverService {
+    pub client: reqwest::Client,
+    pub cover_request_tx: crossbeam::channel::Sender<CoverRequest>,
+    pub cover_request_rx: crossbeam::channel::Receiver<CoverRequest>,
+    pub cover_result_tx: crossbeam::channel::Sender<CoverResult>,
+    pub cover_result_rx: crossbeam::channel::Receiver<CoverResult>,
+}
+
+impl Default for CoverService {
+    fn default() -> CoverService {
+        let (request_tx, request_rx) = crossbeam::channel::unbounded();
+        let (result_tx, result_rx) = crossbeam::channel::unbounded();
+        CoverService {
+            client: reqwest::Client::new(),
+            cover_request_tx: request_tx,
+            cover_request_rx: request_rx,
+            cover_result_tx: result_tx,
+            cover_result_rx: result_rx,
+        }
+    }
+}
+
+impl CoverService {
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        tokio::spawn(async move {
+            //waiting for a request blocks, so not on a thread of the runtime
+            loop {
+                let rx = self.cover_request_rx.clone();
+                let (album_id, url) = match tokio::task::spawn_blocking(move || rx.recv()).await {
+                    Ok(Ok(request)) => request,
+                    _ => return,
+                };
+                let result = self.fetch(&url).await.map_err(|e| e.to_string());
+                if let Err(e) = result.as_ref() {
+                    log::warn!("failed to get the album art from {}: {}", url, e);
+                }
+                if self.cover_result_tx.send((album_id, result)).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(())
+    }
+    pub async fn fetch(&self, url: &str) -> Result<Image, failure::Error> {
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(failure::format_err!("{}", response.status()));
+        }
+        let data = response.bytes().await?;
+        tokio::task::spawn_blocking(move || decode(&data)).await?
+    }
+}
+
+/// The album art of the player by album id. An album is requested from the CoverService
+/// the first time it is shown, and only the last CACHE_LIMIT albums are kept.
+#[derive(Clone, Debug, Default)]
+pub struct Covers {
+    pub protocol: Protocol,
+    //None while fetching, and after a failure (which is not retried)
+    images: HashMap<String, Option<Arc<Image>>>,
+    order: VecDeque<String>,
+    cover_request_tx: Option<crossbeam::channel::Sender<CoverRequest>>,
+    cover_result_rx: Option<crossbeam::channel::Receiver<CoverResult>>,
+}
+
+impl Covers {
+    pub fn new(protocol: Protocol, service: &CoverService) -> Covers {
+        Covers {
+            protocol,
+            cover_request_tx: Some(service.cover_request_tx.clone()),
+            cover_result_rx: Some(service.cover_result_rx.clone()),
+            ..Covers::default()
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        self.protocol != Protocol::Off && self.cover_request_tx.is_some()
+    }
+    /// The image of the album, if it has been fetched.
+    pub fn get(&mut self, album_id: &str, images: &[AlbumImage]) -> Option<Arc<Image>> {
+        if !self.enabled() {
+            return None;
+        }
+        if let Some(image) = self.images.get(album_id) {
+            return image.clone();
+        }
+        if let (Some(url), Some(tx)) = (
+            image_url(images, self.protocol.image_width()),
+            self.cover_request_tx.as_ref(),
+        ) {
+            if tx.send((album_id.to_string(), url.to_string())).is_err() {
+                log::warn!("the cover service stopped, no more album art is shown");
+                self.cover_request_tx = None;
+                return None;
+            }
+        }
+        self.images.insert(album_id.to_string(), None);
+        self.order.push_back(album_id.to_string());
+        if self.order.len() > CACHE_LIMIT {
+            if let Some(oldest) = self.order.pop_front() {
+                self.images.remove(&oldest);
+            }
+        }
+        None
+    }
+    pub fn receive(&mut self) {
+        let results: Vec<CoverResult> = match self.cover_result_rx.as_ref() {
+            Some(rx) => rx.try_iter().collect(),
+            None => return,
+        };
+        for (album_id, result) in results {
+            //dropped from the cache while fetching
+            if let (Some(image), Ok(decoded)) = (self.images.get_mut(&album_id), result) {
+                *image = Some(Arc::new(decoded));
+            }
+        }
+    }
+}
+
+/// The image in half blocks: each cell is two pixels, the upper one the foreground of ▀.
+pub struct HalfBlocks<'a> {
+    image: &'a Image,
+    protocol: Protocol,
+}
+
+impl<'a> HalfBlocks<'a> {
+    pub fn new(image: &'a Image, protocol: Protocol) -> HalfBlocks<'a> {
+        HalfBlocks { image, protocol }
+    }
+    fn color(&self, [r, g, b]: [u8; 3]) -> Color {
+        match self.protocol {
+            Protocol::Indexed => Color::Indexed(indexed_color([r, g, b])),
+            _ => Color::Rgb(r, g, b),
+        }
+    }
+}
+
+impl<'a> Widget for HalfBlocks<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        let width = area.width as usize;
+        let image = self.image.resize(width, area.height as usize * 2);
+        for y in 0..area.height as usize {
+            for x in 0..width {
+                let top = image.pixels[2 * y * width + x];
+                let bottom = image.pixels[(2 * y + 1) * width + x];
+                buf.get_mut(area.x + x as u16, area.y + y as u16)
+                    .set_symbol("▀")
+                    .set_fg(self.color(top))
+                    .set_bg(self.color(bottom));
+            }
+        }
+    }
+}
+
+//the closest of the 256 colors in the cube or the gray ramp
+fn indexed_color(rgb: [u8; 3]) -> u8 {
+    let distance = |other: [u8; 3]| -> i32 {
+        rgb.iter()
+            .zip(other.iter())
+            .map(|(x, y)| (*x as i32 - *y as i32).pow(2))
+            .sum()
+    };
+    let level = |x: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|i| (CUBE_LEVELS[*i] as i32 - x as i32).abs())
+            .unwrap_or(0)
+    };
+    let (r, g, b) = (level(rgb[0]), level(rgb[1]), level(rgb[2]));
+    let cube = [CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]];
+    let average = rgb.iter().map(|x| *x as i32).sum::<i32>() / 3;
+    let gray_index = ((average - 8).max(0) / 10).min(23);
+    let gray = (8 + gray_index * 10) as u8;
+    if distance([gray, gray, gray]) < distance(cube) {
+        232 + gray_index as u8
+    } else {
+        16 + (36 * r + 6 * g + b) as u8
+    }
+}
+
+//the album, where it is and the size of the terminal
+type Shown = (String, Rect, Rect);
+
+/// The album art drawn with kitty or sixel graphics after tui has drawn the frame. The
+/// terminal keeps an image until it is deleted or drawn over, so it is only sent when the
+/// album or the layout changes.
+#[derive(Clone, Debug, Default)]
+pub struct Graphics {
+    protocol: Protocol,
+    shown: Option<Shown>,
+}
+
+impl Graphics {
+    pub fn new(protocol: Protocol) -> Graphics {
+        Graphics {
+            protocol,
+            shown: None,
+        }
+    }
+    /// Whether the screen has to be cleared (and drawn again) before showing the album art
+    /// at `area`: sixel pixels stay until text is drawn over them, which tui only does for
+    /// cells that change.
+    pub fn must_clear(&self, area: Option<Rect>, size: Rect) -> bool {
+        match self.shown.as_ref() {
+            Some((_, shown_area, shown_size)) => {
+                self.protocol == Protocol::Sixel
+                    && (area != Some(*shown_area) || size != *shown_size)
+            }
+            None => false,
+        }
+    }
+    /// Shows the image of `album_id` at `area`, or with None removes the one shown.
+    pub fn show<W: Write>(
+        &mut self,
+        out: &mut W,
+        cover: Option<(&str, &Image, Rect)>,
+        size: Rect,
+    ) -> io::Result<()> {
+        if !self.protocol.is_graphics() {
+            return Ok(());
+        }
+        let shown = cover.map(|(album_id, _, area)| (album_id.to_string(), area, size));
+        if shown == self.shown {
+            return Ok(());
+        }
+        if self.shown.is_some() && self.protocol == Protocol::Kitty {
+            write!(out, "\x1b_Ga=d,d=I,i={},q=2\x1b\\", KITTY_IMAGE_ID)?;
+        }
+        if let Some((_, image, area)) = cover {
+            let (cell_width, cell_height) = cell_size();
+            let image = image.resize(
+                area.width as usize * cell_width,
+                area.height as usize * cell_height,
+            );
+            write!(out, "{}", termion::cursor::Goto(area.x + 1, area.y + 1))?;
+            match self.protocol {
+                Protocol::Kitty => write_kitty(out, &image, area)?,
+                _ => write_sixel(out, &image)?,
+            }
+        }
+        self.shown = shown;
+        out.flush()
+    }
+}
+
+//the size of a cell in pixels
+fn cell_size() -> (usize, usize) {
+    match (termion::terminal_size(), termion::terminal_size_pixels()) {
+        (Ok((columns, rows)), Ok((width, height))) if columns > 0 && rows > 0 && width > 0 => (
+            cmp::max(width / columns, 1) as usize,
+            cmp::max(height / rows, 1) as usize,
+        ),
+        _ => DEFAULT_CELL_SIZE,
+    }
+}
+
+//transmits and places the image, replacing the last one; q=2 keeps kitty from answering on
+//the input, and C=1 from moving the cursor
+fn write_kitty<W: Write>(out: &mut W, image: &Image, area: Rect) -> io::Result<()> {
+    let data: Vec<u8> = image.pixels.iter().flatten().copied().collect();
+    let encoded = base64::encode(&data);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},c={},r={},i={},C=1,q=2,m={};",
+                image.width, image.height, area.width, area.height, KITTY_IMAGE_ID, more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
+
+//in the colors of the 6×6×6 cube, six rows of pixels at a time
+fn write_sixel<W: Write>(out: &mut W, image: &Image) -> io::Result<()> {
+    let color = |[r, g, b]: [u8; 3]| {
+        let level = |x: u8| (x as usize * 5 + 127) / 255;
+        36 * level(r) + 6 * level(g) + level(b)
+    };
+    write!(out, "\x1bPq\"1;1;{};{}", image.width, image.height)?;
+    for i in 0..216 {
+        //in percent
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            i,
+            i / 36 * 20,
+            i / 6 % 6 * 20,
+            i % 6 * 20
+        )?;
+    }
+    let colors: Vec<usize> = image.pixels.iter().map(|x| color(*x)).collect();
+    for top in (0..image.height).step_by(6) {
+        let rows = cmp::min(6, image.height - top);
+        let mut used = [false; 216];
+        for row in top..top + rows {
+            for x in 0..image.width {
+                used[colors[row * image.width + x]] = true;
+            }
+        }
+        for (i, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+            write!(out, "#{}", i)?;
+            let mut run: Option<(u8, usize)> = None;
+            for x in 0..image.width {
+                let bits = (0..rows)
+                    .filter(|row| colors[(top + row) * image.width + x] == i)
+                    .fold(0, |bits, row| bits | 1 << row);
+                let sixel = 63 + bits as u8;
+                run = match run {
+                    Some((last, count)) if last == sixel => Some((last, count + 1)),
+                    Some(last) => {
+                        write_sixel_run(out, last)?;
+                        Some((sixel, 1))
+                    }
+                    None => Some((sixel, 1)),
+                };
+            }
+            if let Some(last) = run {
+                write_sixel_run(out, last)?;
+            }
+            //back to the start of the six rows for the next color
+            write!(out, "$")?;
+        }
+        write!(out, "-")?;
+    }
+    write!(out, "\x1b\\")
+}
+
+fn write_sixel_run<W: Write>(out: &mut W, (sixel, count): (u8, usize)) -> io::Result<()> {
+    if count > 3 {
+        write!(out, "!{}{}", count, sixel as char)
+    } else {
+        write!(out, "{}", (sixel as char).to_string().repeat(count))
+    }
+}
@@ -0,0 +1,524 @@
+extern crate crossbeam;
+extern crate dirs;
+extern crate libc;
+extern crate serde_json;
+
+use crate::cli::{self, Cli, CliError, Command, StatusOptions};
+use crate::spoterm::{Field, SaveState, SpotermClient, SpotifyData};
+use crate::spotify::{SpotifyAPIEvent, SpotifyAPIResult};
+use crate::status::Status;
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `$XDG_RUNTIME_DIR/spoterm.sock`, or a socket named after the uid in the temp dir.
+pub fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("spoterm.sock"),
+        None => std::env::temp_dir().join(format!("spoterm-{}.sock", current_uid())),
+    }
+}
+
+fn current_uid() -> u32 {
+    //getuid cannot fail
+    unsafe { libc::getuid() }
+}
+
+//the temp dir is shared, a socket another user put there must not be used
+fn check_owner(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.uid() != current_uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is owned by another user", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// A line of JSON sent by a client.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    //e.g. {"type":"event","event":{"NextTrack":null}}, no response
+    Event { event: SpotifyAPIEvent },
+    //all fields of the cached SpotifyData once
+    Get,
+    //all fields now and the changed ones after every poll
+    Subscribe,
+    //a subcommand, e.g. {"type":"run","args":["volume","+10"]}
+    Run { args: Vec<String> },
+}
+
+/// A line of JSON sent by the daemon.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Data {
+        data: serde_json::Map<String, serde_json::Value>,
+    },
+    Output {
+        stdout: String,
+        stderr: String,
+        exit_code: i32,
+    },
+    Error {
+        message: String,
+    },
+    //a reply such as search results, sent to the client that asked
+    Result {
+        result: Box<SpotifyAPIResult>,
+    },
+}
+
+fn write_line<T: Serialize>(stream: &mut UnixStream, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+enum Incoming {
+    Connected(usize, UnixStream),
+    Request(usize, Result<Request, serde_json::Error>),
+    Disconnected(usize),
+}
+
+struct Connection {
+    stream: UnixStream,
+    subscribed: bool,
+}
+
+/// Owns the Spotify session and the polling, and serves it over a Unix socket.
+pub struct Daemon {
+    spoterm: SpotermClient,
+    cli: Cli,
+    path: PathBuf,
+    incoming_rx: crossbeam::channel::Receiver<Incoming>,
+    relay_rx: crossbeam::channel::Receiver<SpotifyAPIResult>,
+    //the events of clients, see SpotifyService::api_request_tx
+    request_tx: crossbeam::channel::Sender<(usize, SpotifyAPIEvent)>,
+    connections: HashMap<usize, Connection>,
+    //the revision of each field subscribers have received
+    sent_revisions: HashMap<Field, u64>,
+}
+
+impl Daemon {
+    /// Binds the socket, replacing a stale one left by a daemon that did not exit cleanly.
+    /// Only the user can connect to it.
+    pub fn bind(path: &Path) -> io::Result<UnixListener> {
+        if fs::symlink_metadata(path).is_ok() {
+            check_owner(path)?;
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("spoterm daemon is already running on {}", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+    pub fn new(
+        listener: UnixListener,
+        path: PathBuf,
+        spoterm: SpotermClient,
+        request_tx: crossbeam::channel::Sender<(usize, SpotifyAPIEvent)>,
+        cli: Cli,
+    ) -> Daemon {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let (relay_tx, relay_rx) = crossbeam::channel::unbounded();
+        thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::error!("failed to accept a daemon client: {}", e);
+                        continue;
+                    }
+                };
+                let writer = match stream.try_clone() {
+                    Ok(writer) => writer,
+                    Err(_) => continue,
+                };
+                if tx.send(Incoming::Connected(id, writer)).is_err() {
+                    return;
+                }
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for line in BufReader::new(stream).lines() {
+                        let line = match line {
+                            Ok(line) => line,
+                            Err(_) => break,
+                        };
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        let request = serde_json::from_str(&line);
+                        if tx.send(Incoming::Request(id, request)).is_err() {
+                            return;
+                        }
+                    }
+                    tx.send(Incoming::Disconnected(id)).ok();
+                });
+            }
+        });
+        Daemon {
+            spoterm: spoterm.relay_tx(relay_tx),
+            cli,
+            path,
+            incoming_rx: rx,
+            relay_rx,
+            request_tx,
+            connections: HashMap::new(),
+            sent_revisions: HashMap::new(),
+        }
+    }
+    fn poll(&mut self) {
+        //the UI marks the tracks it shows, the daemon only needs the playing one
+        if let Some(track_id) = self.spoterm.spotify_data.current_playing_track_id() {
+            let data = &mut self.spoterm.spotify_data;
+            if let Entry::Vacant(entry) = data.save_state_track_ids.entry(track_id) {
+                entry.insert(SaveState::UNKNOWN);
+                data.touch(Field::SaveStateTrackIds);
+            }
+        }
+        self.spoterm.request_device();
+        self.spoterm.request_current_playback();
+        self.spoterm.request_current_user_recently_played();
+        self.spoterm.request_current_user_saved_tracks();
+        self.spoterm.request_check_unknown_saved_tracks();
+    }
+    pub async fn run(mut self) -> Result<(), failure::Error> {
+        //stop on Ctrl-C or `kill` once the loop below has saved the history and removed the socket
+        let (stop_tx, stop_rx) = crossbeam::channel::bounded(1);
+        tokio::spawn(async move {
+            let mut terminate =
+                match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                    Ok(terminate) => terminate,
+                    Err(_) => return,
+                };
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            stop_tx.send(()).ok();
+        });
+
+        let mut polled_at = Instant::now();
+        self.poll();
+        loop {
+            if polled_at.elapsed() >= Duration::from_secs(1) {
+                polled_at = Instant::now();
+                self.poll();
+            }
+            self.spoterm.fetch_api_result();
+            //replies go to the client that asked; the daemon's own failures are only logged
+            while let Ok(result) = self.relay_rx.try_recv() {
+                if let SpotifyAPIResult::Reply(id, result) = result {
+                    self.send(id, &Response::Result { result });
+                }
+            }
+            self.spoterm.set_selected_device()?;
+            while let Ok(incoming) = self.incoming_rx.try_recv() {
+                self.handle(incoming).await;
+            }
+            self.send_changed_fields();
+            if stop_rx.try_recv().is_ok() {
+                self.spoterm.flush_history();
+                fs::remove_file(&self.path).ok();
+                return Ok(());
+            }
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+        }
+    }
+    async fn handle(&mut self, incoming: Incoming) {
+        match incoming {
+            Incoming::Connected(id, stream) => {
+                self.connections.insert(
+                    id,
+                    Connection {
+                        stream,
+                        subscribed: false,
+                    },
+                );
+            }
+            Incoming::Disconnected(id) => {
+                self.connections.remove(&id);
+            }
+            Incoming::Request(id, Err(e)) => {
+                self.send(
+                    id,
+                    &Response::Error {
+                        message: format!("invalid request: {}", e),
+                    },
+                );
+            }
+            Incoming::Request(id, Ok(request)) => match request {
+                Request::Event { event } => {
+                    self.request_tx.send((id, event)).ok();
+                }
+                Request::Get => {
+                    let data = self.spoterm.spotify_data.to_fields();
+                    self.send(id, &Response::Data { data });
+                }
+                Request::Subscribe => {
+                    if let Some(connection) = self.connections.get_mut(&id) {
+                        connection.subscribed = true;
+                    }
+                    let data = self.spoterm.spotify_data.to_fields();
+                    self.send(id, &Response::Data { data });
+                }
+                Request::Run { args } => {
+                    let response = self.run_args(&args).await;
+                    self.send(id, &response);
+                }
+            },
+        }
+    }
+    fn send(&mut self, id: usize, response: &Response) {
+        let failed = match self.connections.get_mut(&id) {
+            Some(connection) => write_line(&mut connection.stream, response).is_err(),
+            None => false,
+        };
+        if failed {
+            self.connections.remove(&id);
+        }
+    }
+    //only the changed fields are serialized, Liked Songs can be thousands of tracks
+    fn send_changed_fields(&mut self) {
+        let data = &self.spoterm.spotify_data;
+        let mut changed = serde_json::Map::new();
+        for field in Field::ALL.iter().cloned() {
+            let revision = data.revision(field);
+            if self.sent_revisions.get(&field) != Some(&revision) {
+                self.sent_revisions.insert(field, revision);
+                changed.insert(field.name().to_string(), data.field_value(field));
+            }
+        }
+        if changed.is_empty() {
+            return;
+        }
+        self.broadcast(&Response::Data { data: changed });
+    }
+    fn broadcast(&mut self, response: &Response) {
+        let subscribers: Vec<usize> = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.subscribed)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in subscribers {
+            self.send(id, response);
+        }
+    }
+    //`status` is rendered from the cache, everything else goes through the shared session
+    async fn run_args(&mut self, args: &[String]) -> Response {
+        let result = match cli::parse_args(args) {
+            Ok(Some(Command::Status(options))) => Ok(self.render_status(&options)),
+            Ok(Some(Command::Daemon)) => Err(CliError::usage(
+                "spoterm daemon is already running".to_string(),
+            )),
+            Ok(Some(Command::Record(_))) | Ok(Some(Command::Replay(_))) => Err(CliError::usage(
+                "record and replay start the terminal UI".to_string(),
+            )),
+            Ok(Some(command)) => {
+                let result = self.cli.output(command).await;
+                self.spoterm.request_current_playback();
+                result
+            }
+            Ok(None) => Err(CliError::usage("no command".to_string())),
+            Err(e) => Err(CliError::usage(format!("{}\n\n{}", e.message, cli::USAGE))),
+        };
+        match result {
+            Ok(stdout) => Response::Output {
+                stdout,
+                stderr: String::new(),
+                exit_code: cli::EXIT_OK,
+            },
+            Err(e) => Response::Output {
+                stdout: String::new(),
+                stderr: e.message,
+                exit_code: e.exit_code,
+            },
+        }
+    }
+    fn render_status(&self, options: &StatusOptions) -> String {
+        let format = self.cli.status_format_or_default(options);
+        let spotify_data = &self.spoterm.spotify_data;
+        let status = spotify_data
+            .current_playback
+            .as_ref()
+            .map(|current_playback| {
+                let liked =
+                    spotify_data.current_playing_track_id().and_then(|id| {
+                        match spotify_data.save_state_track_ids.get(&id) {
+                            Some(SaveState::SAVED) => Some(true),
+                            Some(SaveState::UNSAVED) => Some(false),
+                            _ => None,
+                        }
+                    });
+                let mut status = Status::new(current_playback, liked);
+                status.progress_ms = spotify_data
+                    .current_progress_ms()
+                    .unwrap_or(status.progress_ms);
+                status
+            });
+        cli::format_status(status.as_ref(), &format, options.json)
+    }
+}
+
+/// A connection to a running daemon.
+pub struct DaemonClient {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl DaemonClient {
+    pub fn connect() -> io::Result<DaemonClient> {
+        let path = socket_path();
+        check_owner(&path)?;
+        let stream = UnixStream::connect(path)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(DaemonClient { stream, reader })
+    }
+    pub fn request(&mut self, request: &Request) -> io::Result<()> {
+        write_line(&mut self.stream, request)
+    }
+    pub fn response(&mut self) -> io::Result<Response> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the daemon closed the connection",
+            ));
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+    fn run_args(&mut self, args: &[String]) -> io::Result<(String, String, i32)> {
+        self.request(&Request::Run {
+            args: args.to_vec(),
+        })?;
+        match self.response()? {
+            Response::Output {
+                stdout,
+                stderr,
+                exit_code,
+            } => Ok((stdout, stderr, exit_code)),
+            Response::Error { message } => Ok((String::new(), message, cli::EXIT_FAILURE)),
+            Response::Data { .. } | Response::Result { .. } => Ok((
+                String::new(),
+                "unexpected response from the daemon".to_string(),
+                cli::EXIT_FAILURE,
+            )),
+        }
+    }
+    pub fn get_data(&mut self) -> io::Result<SpotifyData> {
+        self.request(&Request::Get)?;
+        match self.response()? {
+            Response::Data { data } => {
+                let mut spotify_data = SpotifyData::new();
+                spotify_data.update_fields(data)?;
+                Ok(spotify_data)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected response from the daemon",
+            )),
+        }
+    }
+    /// Runs a subcommand in the daemon like `Cli::run` and returns the exit code.
+    pub fn run_cli(&mut self, args: &[String], command: &Command) -> io::Result<i32> {
+        //the file is written here, the daemon may run in another directory
+        if let Command::Export(options) = command {
+            return Ok(match cli::export(options, &self.get_data()?) {
+                Ok(message) => {
+                    println!("{}", message);
+                    cli::EXIT_OK
+                }
+                Err(e) => {
+                    eprintln!("{}", e.message);
+                    e.exit_code
+                }
+            });
+        }
+        let watch = match command {
+            Command::Status(options) if options.watch => Some(options.interval),
+            _ => None,
+        };
+        let interval = match watch {
+            Some(interval) => interval,
+            None => {
+                let (stdout, stderr, exit_code) = self.run_args(args)?;
+                if !stdout.is_empty() {
+                    println!("{}", stdout);
+                }
+                if !stderr.is_empty() {
+                    eprintln!("{}", stderr);
+                }
+                return Ok(exit_code);
+            }
+        };
+        let mut last_line = None;
+        loop {
+            let (stdout, stderr, _) = self.run_args(args)?;
+            if !stderr.is_empty() {
+                eprintln!("{}", stderr);
+            } else if last_line.as_ref() != Some(&stdout) {
+                println!("{}", stdout);
+                io::stdout().flush().ok();
+                last_line = Some(stdout);
+            }
+            thread::sleep(interval);
+        }
+    }
+    /// Subscribes and bridges the daemon to the channels `SpotermClient` uses.
+    pub fn attach(
+        mut self,
+    ) -> io::Result<(
+        crossbeam::channel::Sender<SpotifyAPIEvent>,
+        crossbeam::channel::Receiver<SpotifyAPIResult>,
+    )> {
+        self.request(&Request::Subscribe)?;
+        let (event_tx, event_rx) = crossbeam::channel::unbounded::<SpotifyAPIEvent>();
+        let (result_tx, result_rx) = crossbeam::channel::unbounded();
+        let mut writer = self.stream.try_clone()?;
+        thread::spawn(move || {
+            for event in event_rx {
+                if let Err(e) = write_line(&mut writer, &Request::Event { event }) {
+                    log::error!("failed to send an event to the daemon: {}", e);
+                    return;
+                }
+            }
+        });
+        thread::spawn(move || loop {
+            match self.response() {
+                Ok(Response::Data { data }) => {
+                    if result_tx.send(SpotifyAPIResult::DaemonData(data)).is_err() {
+                        return;
+                    }
+                }
+                Ok(Response::Result { result }) => {
+                    if result_tx.send(*result).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("lost the connection to the daemon: {}", e);
+                    return;
+                }
+            }
+        });
+        Ok((event_tx, result_rx))
+    }
+}
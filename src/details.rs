@@ -0,0 +1,238 @@
+extern crate rspotify;
+
+use crate::layout;
+use crate::layout::Clear;
+
+use self::rspotify::model::audio::AudioFeatures;
+use self::rspotify::model::track::FullTrack;
+use itertools::Itertools;
+use termion::event::Key;
+use tui::buffer::Buffer;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Borders, Gauge, Paragraph, Text, Widget};
+
+//how much of the terminal the popup covers
+const DETAILS_PERCENT: u16 = 70;
+//the metadata lines and the borders
+const METADATA_HEIGHT: u16 = 12;
+//the column of the labels
+const LABEL_WIDTH: usize = 14;
+//the ends of the tempo and loudness gauges
+const TEMPO_MAX: f32 = 250.0;
+const LOUDNESS_MIN: f32 = -60.0;
+
+const PITCH_CLASSES: [&str; 12] = [
+    "C",
+    "C♯/D♭",
+    "D",
+    "D♯/E♭",
+    "E",
+    "F",
+    "F♯/G♭",
+    "G",
+    "G♯/A♭",
+    "A",
+    "A♯/B♭",
+    "B",
+];
+
+/// The `i` popup with the metadata and the audio features of a track; both are requested
+/// when it opens unless the track is already known.
+#[derive(Clone, Debug, Default)]
+pub struct Details {
+    pub active: bool,
+    pub track_id: Option<String>,
+    pub track: Option<FullTrack>,
+    //why the track could not be loaded
+    pub track_error: Option<String>,
+    //None until received, an error when the request failed
+    pub features: Option<Result<AudioFeatures, String>>,
+}
+
+impl Details {
+    pub fn open(&mut self, track_id: String, track: Option<FullTrack>) {
+        *self = Details {
+            active: true,
+            track_id: Some(track_id),
+            track,
+            track_error: None,
+            features: None,
+        };
+    }
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+    //results for a track closed since are dropped
+    pub fn set_track(&mut self, track: FullTrack) {
+        if track.id.is_some() && track.id == self.track_id {
+            self.track = Some(track);
+        }
+    }
+    pub fn set_track_error(&mut self, track_id: &str, e: String) {
+        if self.track_id.as_deref() == Some(track_id) {
+            self.track_error = Some(e);
+        }
+    }
+    pub fn set_features(&mut self, track_id: &str, features: Result<AudioFeatures, String>) {
+        if self.track_id.as_deref() == Some(track_id) {
+            self.features = Some(features);
+        }
+    }
+    pub fn key(&mut self, key: Key) {
+        if let Key::Esc | Key::Char('q') | Key::Char('i') = key {
+            self.close();
+        }
+    }
+    fn metadata_lines(&self) -> Vec<(&'static str, String)> {
+        let track = match (self.track.as_ref(), self.track_error.as_ref()) {
+            (Some(track), _) => track,
+            (None, Some(e)) => return vec![("Track", format!("failed to load: {}", e))],
+            (None, None) => return vec![("Track", "loading…".to_string())],
+        };
+        let sec = track.duration_ms / 1000;
+        vec![
+            ("Title", track.name.clone()),
+            (
+                "Artists",
+                track.artists.iter().map(|x| x.name.clone()).join(", "),
+            ),
+            ("Album", track.album.name.clone()),
+            (
+                "Released",
+                track.album.release_date.clone().unwrap_or_default(),
+            ),
+            (
+                "Track",
+                format!("{} (disc {})", track.track_number, track.disc_number),
+            ),
+            ("Duration", format!("{:02}:{:02}", sec / 60, sec % 60)),
+            (
+                "Explicit",
+                if track.explicit { "yes" } else { "no" }.to_string(),
+            ),
+            ("Popularity", format!("{}/100", track.popularity)),
+            (
+                "ISRC",
+                track.external_ids.get("isrc").cloned().unwrap_or_default(),
+            ),
+            ("URI", track.uri.clone()),
+        ]
+    }
+    //the label, how full the gauge is and its text
+    fn feature_gauges(features: &AudioFeatures) -> Vec<(&'static str, f64, String)> {
+        vec![
+            (
+                "Tempo",
+                (features.tempo / TEMPO_MAX) as f64,
+                format!("{:.0} BPM", features.tempo),
+            ),
+            (
+                "Energy",
+                features.energy as f64,
+                format!("{:.2}", features.energy),
+            ),
+            (
+                "Danceability",
+                features.danceability as f64,
+                format!("{:.2}", features.danceability),
+            ),
+            (
+                "Valence",
+                features.valence as f64,
+                format!("{:.2}", features.valence),
+            ),
+            (
+                "Loudness",
+                ((features.loudness - LOUDNESS_MIN) / -LOUDNESS_MIN) as f64,
+                format!("{:.1} dB", features.loudness),
+            ),
+        ]
+    }
+    //e.g. "A minor", or "unknown" when Spotify could not tell
+    fn key_text(features: &AudioFeatures) -> String {
+        let key = match PITCH_CLASSES.get(features.key as usize) {
+            Some(key) if features.key >= 0 => key,
+            _ => return "unknown".to_string(),
+        };
+        let mode = if features.mode >= 1.0 {
+            "major"
+        } else {
+            "minor"
+        };
+        format!("{} {}", key, mode)
+    }
+    pub fn render(&self, buf: &mut Buffer, size: Rect) {
+        let area = layout::popup(size, DETAILS_PERCENT);
+        Clear.draw(area, buf);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(METADATA_HEIGHT), Constraint::Min(0)].as_ref())
+            .split(area);
+        let label_style = Style::default().fg(Color::Cyan);
+        let mut lines = vec![];
+        for (label, value) in self.metadata_lines() {
+            lines.push(Text::styled(
+                format!("{:<width$}", label, width = LABEL_WIDTH),
+                label_style,
+            ));
+            lines.push(Text::raw(format!("{}\n", value)));
+        }
+        Paragraph::new(lines.iter())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Track Details (Esc: close)")
+                    .title_style(Style::default().modifier(Modifier::BOLD)),
+            )
+            .draw(rows[0], buf);
+
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .title("Audio Features");
+        block.draw(rows[1], buf);
+        let inner = block.inner(rows[1]);
+        let features = match self.features.as_ref() {
+            Some(Ok(features)) => features,
+            Some(Err(e)) => {
+                Paragraph::new([Text::raw(format!("failed to load: {}", e))].iter())
+                    .wrap(true)
+                    .draw(inner, buf);
+                return;
+            }
+            None => {
+                Paragraph::new([Text::raw("loading…")].iter()).draw(inner, buf);
+                return;
+            }
+        };
+        let mut gauges: Vec<(&str, Option<f64>, String)> = Details::feature_gauges(features)
+            .into_iter()
+            .map(|(label, ratio, text)| (label, Some(ratio), text))
+            .collect();
+        gauges.insert(1, ("Key", None, Details::key_text(features)));
+        for (i, (label, ratio, text)) in gauges.into_iter().enumerate() {
+            if i as u16 >= inner.height {
+                break;
+            }
+            let line = Rect::new(inner.x, inner.y + i as u16, inner.width, 1);
+            Paragraph::new([Text::styled(label, label_style)].iter()).draw(line, buf);
+            let value = Rect::new(
+                line.x + LABEL_WIDTH as u16,
+                line.y,
+                line.width.saturating_sub(LABEL_WIDTH as u16),
+                1,
+            );
+            match ratio {
+                //tui centers the label in the gauge, which must be wider than it
+                Some(ratio) if value.width as usize > text.chars().count() => {
+                    Gauge::default()
+                        .style(Style::default().fg(Color::Green).bg(Color::Black))
+                        .ratio(ratio.clamp(0.0, 1.0))
+                        .label(&text)
+                        .draw(value, buf);
+                }
+                _ => Paragraph::new([Text::raw(text)].iter()).draw(value, buf),
+            }
+        }
+    }
+}
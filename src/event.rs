@@ -3,11 +3,12 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
-use termion::event::Key;
+use termion::event::{Key, MouseEvent};
 use termion::input::TermRead;
 
 pub enum Event {
     KeyInput(Key),
+    Mouse(MouseEvent),
     Tick,
     APIUpdate,
 }
@@ -26,14 +27,14 @@ impl EventHandler {
             let tx = tx.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    match evt {
-                        Ok(key) => {
-                            if let Err(_) = tx.send(Event::KeyInput(key)) {
-                                return;
-                            }
-                        }
-                        Err(_) => {}
+                for evt in stdin.events() {
+                    let event = match evt {
+                        Ok(termion::event::Event::Key(key)) => Event::KeyInput(key),
+                        Ok(termion::event::Event::Mouse(mouse)) => Event::Mouse(mouse),
+                        _ => continue,
+                    };
+                    if let Err(_) = tx.send(event) {
+                        return;
                     }
                 }
             })
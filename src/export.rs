@@ -0,0 +1,384 @@
+extern crate chrono;
+extern crate rspotify;
+extern crate serde_json;
+
+use crate::history::{HistoryEntry, ListeningHistory};
+use crate::spoterm::SpotifyData;
+
+use self::chrono::{DateTime, Utc};
+use self::rspotify::model::playing::PlayHistory;
+use self::rspotify::model::track::SavedTrack;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    Name,
+    Artists,
+    Album,
+    Duration,
+    Popularity,
+    AddedAt,
+    Uri,
+    Isrc,
+}
+
+pub const ALL_COLUMNS: [Column; 8] = [
+    Column::Name,
+    Column::Artists,
+    Column::Album,
+    Column::Duration,
+    Column::Popularity,
+    Column::AddedAt,
+    Column::Uri,
+    Column::Isrc,
+];
+
+impl Column {
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Artists => "artists",
+            Column::Album => "album",
+            Column::Duration => "duration",
+            Column::Popularity => "popularity",
+            Column::AddedAt => "added_at",
+            Column::Uri => "uri",
+            Column::Isrc => "isrc",
+        }
+    }
+}
+
+/// Parses a comma separated list such as `name,artists,uri`.
+pub fn parse_columns(columns: &str) -> Result<Vec<Column>, String> {
+    columns
+        .split(',')
+        .map(|x| x.trim().to_lowercase())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            ALL_COLUMNS
+                .iter()
+                .find(|column| column.name() == x)
+                .copied()
+                .ok_or(format!("unknown column: {}", x))
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    //extended M3U with Spotify URIs; the columns do not apply
+    M3u,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "m3u" | "m3u8" => Some(Format::M3u),
+            _ => None,
+        }
+    }
+    pub fn from_path(path: &Path) -> Option<Format> {
+        Format::parse(path.extension()?.to_str()?)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Liked,
+    Recent,
+    History,
+}
+
+impl Source {
+    pub fn parse(name: &str) -> Option<Source> {
+        match name {
+            "liked" => Some(Source::Liked),
+            "recent" => Some(Source::Recent),
+            "history" => Some(Source::History),
+            _ => None,
+        }
+    }
+}
+
+/// A row of any list; fields a list does not have are left empty.
+#[derive(Clone, Debug, Default)]
+pub struct ExportTrack {
+    pub name: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub duration_ms: u32,
+    pub popularity: Option<u32>,
+    //added to the library, played (recently played) or started (history)
+    pub added_at: Option<DateTime<Utc>>,
+    pub uri: Option<String>,
+    pub isrc: Option<String>,
+}
+
+impl ExportTrack {
+    pub fn from_saved_track(saved_track: &SavedTrack) -> ExportTrack {
+        let track = &saved_track.track;
+        ExportTrack {
+            name: track.name.clone(),
+            artists: track.artists.iter().map(|x| x.name.clone()).collect(),
+            album: Some(track.album.name.clone()),
+            duration_ms: track.duration_ms,
+            popularity: Some(track.popularity),
+            added_at: Some(saved_track.added_at),
+            uri: Some(track.uri.clone()),
+            isrc: track.external_ids.get("isrc").cloned(),
+        }
+    }
+    pub fn from_play_history(play_history: &PlayHistory) -> ExportTrack {
+        let track = &play_history.track;
+        ExportTrack {
+            name: track.name.clone(),
+            artists: track.artists.iter().map(|x| x.name.clone()).collect(),
+            duration_ms: track.duration_ms,
+            added_at: Some(play_history.played_at),
+            uri: Some(track.uri.clone()),
+            ..ExportTrack::default()
+        }
+    }
+    pub fn from_history_entry(entry: &HistoryEntry) -> ExportTrack {
+        ExportTrack {
+            name: entry.track_name.clone(),
+            artists: entry.artists.clone(),
+            album: Some(entry.album.clone()),
+            duration_ms: entry.duration_ms,
+            added_at: Some(entry.started_at),
+            uri: entry
+                .track_id
+                .as_ref()
+                .map(|id| format!("spotify:track:{}", id)),
+            ..ExportTrack::default()
+        }
+    }
+    fn text(&self, column: Column) -> String {
+        match column {
+            Column::Name => self.name.clone(),
+            Column::Artists => self.artists.join("; "),
+            Column::Album => self.album.clone().unwrap_or_default(),
+            Column::Duration => {
+                let sec = self.duration_ms / 1000;
+                format!("{}:{:02}", sec / 60, sec % 60)
+            }
+            Column::Popularity => self.popularity.map(|x| x.to_string()).unwrap_or_default(),
+            Column::AddedAt => self.added_at.map(|x| x.to_rfc3339()).unwrap_or_default(),
+            Column::Uri => self.uri.clone().unwrap_or_default(),
+            Column::Isrc => self.isrc.clone().unwrap_or_default(),
+        }
+    }
+    fn json(&self, column: Column) -> (String, serde_json::Value) {
+        match column {
+            Column::Artists => (column.name().to_string(), serde_json::json!(self.artists)),
+            Column::Duration => (
+                "duration_ms".to_string(),
+                serde_json::json!(self.duration_ms),
+            ),
+            Column::Popularity => (
+                column.name().to_string(),
+                serde_json::json!(self.popularity),
+            ),
+            Column::Album | Column::AddedAt | Column::Uri | Column::Isrc
+                if self.text(column).is_empty() =>
+            {
+                (column.name().to_string(), serde_json::Value::Null)
+            }
+            column => (
+                column.name().to_string(),
+                serde_json::json!(self.text(column)),
+            ),
+        }
+    }
+}
+
+/// The rows of a list in `data` (or the whole recorded history).
+pub fn tracks_from(source: Source, data: &SpotifyData) -> Result<Vec<ExportTrack>, failure::Error> {
+    Ok(match source {
+        Source::Liked => data
+            .saved_tracks
+            .iter()
+            .map(ExportTrack::from_saved_track)
+            .collect(),
+        Source::Recent => data
+            .recent_play_histories
+            .iter()
+            .flatten()
+            .map(ExportTrack::from_play_history)
+            .collect(),
+        Source::History => {
+            let listening_history =
+                ListeningHistory::new(crate::config::data_dir().join("history"));
+            let mut tracks = vec![];
+            for month in listening_history.months() {
+                for entry in listening_history.load_month(&month)?.iter() {
+                    tracks.push(ExportTrack::from_history_entry(entry));
+                }
+            }
+            tracks
+        }
+    })
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn render(tracks: &[ExportTrack], columns: &[Column], format: Format) -> String {
+    match format {
+        Format::Csv => {
+            let mut lines = vec![columns
+                .iter()
+                .map(|x| x.name())
+                .collect::<Vec<_>>()
+                .join(",")];
+            for track in tracks {
+                lines.push(
+                    columns
+                        .iter()
+                        .map(|column| csv_field(&track.text(*column)))
+                        .collect::<Vec<String>>()
+                        .join(","),
+                );
+            }
+            lines.join("\n") + "\n"
+        }
+        Format::Json => {
+            let rows: Vec<serde_json::Map<String, serde_json::Value>> = tracks
+                .iter()
+                .map(|track| columns.iter().map(|column| track.json(*column)).collect())
+                .collect();
+            serde_json::to_string_pretty(&rows).unwrap_or_default() + "\n"
+        }
+        Format::M3u => {
+            let mut lines = vec!["#EXTM3U".to_string()];
+            for track in tracks.iter().filter(|x| x.uri.is_some()) {
+                lines.push(format!(
+                    "#EXTINF:{},{} - {}",
+                    track.duration_ms / 1000,
+                    track.artists.join(", "),
+                    track.name
+                ));
+                lines.push(track.uri.clone().unwrap());
+            }
+            lines.join("\n") + "\n"
+        }
+    }
+}
+
+/// Writes `tracks` to `path` in `format`, or the format of its extension (CSV by default).
+pub fn write(
+    path: &Path,
+    tracks: &[ExportTrack],
+    columns: &[Column],
+    format: Option<Format>,
+) -> io::Result<()> {
+    let format = format
+        .or_else(|| Format::from_path(path))
+        .unwrap_or(Format::Csv);
+    fs::write(path, render(tracks, columns, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(name: &str, artists: &[&str]) -> ExportTrack {
+        ExportTrack {
+            name: name.to_string(),
+            artists: artists.iter().map(|x| x.to_string()).collect(),
+            duration_ms: 185_000,
+            uri: Some("spotify:track:1".to_string()),
+            ..ExportTrack::default()
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("12\" single"), "\"12\"\" single\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn renders_csv() {
+        let tracks = [track("Hello, World", &["A", "B"]), track("Say \"hi\"", &[])];
+        let columns = [
+            Column::Name,
+            Column::Artists,
+            Column::Duration,
+            Column::Album,
+        ];
+        assert_eq!(
+            render(&tracks, &columns, Format::Csv),
+            "name,artists,duration,album\n\
+             \"Hello, World\",A; B,3:05,\n\
+             \"Say \"\"hi\"\"\",,3:05,\n"
+        );
+        assert_eq!(
+            render(&[], &columns, Format::Csv),
+            "name,artists,duration,album\n"
+        );
+    }
+
+    #[test]
+    fn renders_json_and_m3u() {
+        let tracks = [track("Song", &["A", "B"])];
+        let json: serde_json::Value = serde_json::from_str(&render(
+            &tracks,
+            &[
+                Column::Name,
+                Column::Artists,
+                Column::Duration,
+                Column::Isrc,
+            ],
+            Format::Json,
+        ))
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "name": "Song",
+                "artists": ["A", "B"],
+                "duration_ms": 185_000,
+                "isrc": null,
+            }])
+        );
+        //tracks without a URI cannot be played from a playlist
+        let local = ExportTrack {
+            uri: None,
+            ..track("Local", &[])
+        };
+        assert_eq!(
+            render(&[tracks[0].clone(), local], &[], Format::M3u),
+            "#EXTM3U\n#EXTINF:185,A, B - Song\nspotify:track:1\n"
+        );
+    }
+
+    #[test]
+    fn columns_and_formats() {
+        assert_eq!(
+            parse_columns(" Name, added_at,,uri").unwrap(),
+            vec![Column::Name, Column::AddedAt, Column::Uri]
+        );
+        assert_eq!(
+            parse_columns("name,rating").unwrap_err(),
+            "unknown column: rating"
+        );
+        assert_eq!(Format::from_path(Path::new("out.M3U8")), Some(Format::M3u));
+        assert_eq!(Format::from_path(Path::new("out")), None);
+    }
+}
@@ -0,0 +1,351 @@
+use std::cmp;
+use unicode_width::UnicodeWidthChar;
+
+//fzf-like scores: every matched character counts, more so at the start of a word or right
+//after the previous match, and gaps between matches cost a little
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 8;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP: i64 = 1;
+
+/// Text in runs of matched (`true`) and other characters, for highlighting.
+pub type Segments = Vec<(String, bool)>;
+
+/// The score of a row and the matched characters of each of its fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    //character (not byte) positions
+    pub positions: Vec<Vec<usize>>,
+}
+
+impl FuzzyMatch {
+    //the segments of field `index`, which was `text` when matched
+    pub fn segments(&self, index: usize, text: &str) -> Segments {
+        segments(
+            text,
+            self.positions
+                .get(index)
+                .map(|x| x.as_slice())
+                .unwrap_or(&[]),
+        )
+    }
+}
+
+/// The filter of every list: space separated terms, each matching the characters of one of
+/// the fields in order, as fzf does. An upper case letter makes it case sensitive.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FuzzyFilter {
+    terms: Vec<Vec<char>>,
+    case_sensitive: bool,
+}
+
+impl FuzzyFilter {
+    pub fn new(filter: &str) -> FuzzyFilter {
+        let case_sensitive = filter.chars().any(|x| x.is_uppercase());
+        FuzzyFilter {
+            terms: filter
+                .split_whitespace()
+                .map(|term| term.chars().map(|x| fold_case(x, case_sensitive)).collect())
+                .collect(),
+            case_sensitive,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+    /// The match of a row with `fields` (e.g. the track, artists and album), None if a term
+    /// matches none of them.
+    pub fn matches(&self, fields: &[&str]) -> Option<FuzzyMatch> {
+        let fields: Vec<Vec<char>> = fields
+            .iter()
+            .map(|x| {
+                x.chars()
+                    .map(|c| fold_case(c, self.case_sensitive))
+                    .collect()
+            })
+            .collect();
+        let mut result = FuzzyMatch {
+            score: 0,
+            positions: vec![vec![]; fields.len()],
+        };
+        for term in self.terms.iter() {
+            let (score, index, positions) = fields
+                .iter()
+                .enumerate()
+                .filter_map(|(index, field)| {
+                    match_term(term, field).map(|(score, positions)| (score, index, positions))
+                })
+                .max_by_key(|(score, index, _)| (*score, cmp::Reverse(*index)))?;
+            result.score += score;
+            result.positions[index].extend(positions);
+        }
+        for positions in result.positions.iter_mut() {
+            positions.sort_unstable();
+            positions.dedup();
+        }
+        Some(result)
+    }
+    /// The rows matching the filter, best first; rows of the same score keep their order,
+    /// and all rows are kept in order while the filter is empty.
+    pub fn rank<T, F>(&self, rows: Vec<T>, fields: F) -> Vec<(T, FuzzyMatch)>
+    where
+        F: Fn(&T) -> Vec<String>,
+    {
+        let mut rows: Vec<(T, FuzzyMatch)> = rows
+            .into_iter()
+            .filter_map(|row| {
+                if self.is_empty() {
+                    return Some((row, FuzzyMatch::default()));
+                }
+                let fields = fields(&row);
+                let fields: Vec<&str> = fields.iter().map(|x| x.as_str()).collect();
+                let fuzzy_match = self.matches(&fields)?;
+                Some((row, fuzzy_match))
+            })
+            .collect();
+        rows.sort_by_key(|(_, x)| cmp::Reverse(x.score));
+        rows
+    }
+}
+
+fn fold_case(c: char, case_sensitive: bool) -> char {
+    if case_sensitive {
+        c
+    } else {
+        //one character for one, so that positions stay the same
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+fn is_boundary(text: &[char], position: usize) -> bool {
+    position == 0 || !text[position - 1].is_alphanumeric()
+}
+
+fn score(text: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    for (i, position) in positions.iter().enumerate() {
+        score += SCORE_MATCH;
+        if is_boundary(text, *position) {
+            score += BONUS_BOUNDARY;
+        }
+        if i > 0 {
+            let gap = (position - positions[i - 1] - 1) as i64;
+            if gap == 0 {
+                score += BONUS_CONSECUTIVE;
+            } else {
+                score -= PENALTY_GAP_START + PENALTY_GAP * (gap - 1);
+            }
+        }
+    }
+    score
+}
+
+//the best of the shortest matches starting at each occurrence of the first character
+fn match_term(term: &[char], text: &[char]) -> Option<(i64, Vec<usize>)> {
+    let first = *term.first()?;
+    let mut best: Option<(i64, Vec<usize>)> = None;
+    for start in (0..text.len()).filter(|x| text[*x] == first) {
+        //forward to where the whole term is matched
+        let mut end = None;
+        let mut t = 0;
+        for (i, c) in text.iter().enumerate().skip(start) {
+            if *c == term[t] {
+                t += 1;
+                if t == term.len() {
+                    end = Some(i);
+                    break;
+                }
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            //no later start can match either
+            None => break,
+        };
+        //back from the end, which moves the matches as close to it as possible
+        let mut positions = Vec::with_capacity(term.len());
+        let mut t = term.len();
+        for i in (start..=end).rev() {
+            if t > 0 && text[i] == term[t - 1] {
+                t -= 1;
+                positions.push(i);
+            }
+        }
+        positions.reverse();
+        let score = score(text, &positions);
+        if best.as_ref().is_none_or(|(best, _)| score > *best) {
+            best = Some((score, positions));
+        }
+    }
+    best
+}
+
+/// `text` split into the runs of characters at `positions` and the others.
+pub fn segments(text: &str, positions: &[usize]) -> Segments {
+    let mut segments: Segments = vec![];
+    for (i, c) in text.chars().enumerate() {
+        let matched = positions.contains(&i);
+        match segments.last_mut() {
+            Some((segment, last_matched)) if *last_matched == matched => segment.push(c),
+            _ => segments.push((c.to_string(), matched)),
+        }
+    }
+    segments
+}
+
+/// Cuts `segments` to `width` columns, or pads them with spaces.
+pub fn fit(segments: Segments, width: usize) -> Segments {
+    let mut result: Segments = vec![];
+    let mut used = 0;
+    'segments: for (text, matched) in segments {
+        let mut fitted = String::new();
+        for c in text.chars() {
+            let c_width = c.width().unwrap_or(0);
+            if used + c_width > width {
+                if !fitted.is_empty() {
+                    result.push((fitted, matched));
+                }
+                break 'segments;
+            }
+            used += c_width;
+            fitted.push(c);
+        }
+        if !fitted.is_empty() {
+            result.push((fitted, matched));
+        }
+    }
+    if used < width {
+        result.push((" ".repeat(width - used), false));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn segment(text: &str, matched: bool) -> (String, bool) {
+        (text.to_string(), matched)
+    }
+
+    #[test]
+    fn scores_word_starts_and_consecutive_matches() {
+        //16 for each match, 8 for the start of "ok" and 8 for "k" right after it
+        assert_eq!(
+            match_term(&chars("ok"), &chars("ok computer")),
+            Some((48, vec![0, 1]))
+        );
+        //"c" starts a word, but the gap of two costs 3 + 1
+        assert_eq!(
+            match_term(&chars("oc"), &chars("ok computer")),
+            Some((44, vec![0, 3]))
+        );
+        assert_eq!(match_term(&chars("z"), &chars("ok computer")), None);
+    }
+
+    #[test]
+    fn keeps_the_best_occurrence_of_a_term() {
+        assert_eq!(
+            match_term(&chars("ab"), &chars("xab ab")),
+            Some((48, vec![4, 5]))
+        );
+        //back from the end, so the matches are as close together as they can be
+        assert_eq!(
+            match_term(&chars("ab"), &chars("aab")),
+            Some((40, vec![1, 2]))
+        );
+    }
+
+    #[test]
+    fn matches_each_term_in_one_of_the_fields() {
+        let filter = FuzzyFilter::new("rad ok");
+        let fuzzy_match = filter.matches(&["OK Computer", "Radiohead"]).unwrap();
+        assert_eq!(fuzzy_match.score, 48 + 72);
+        assert_eq!(fuzzy_match.positions, vec![vec![0, 1], vec![0, 1, 2]]);
+        assert_eq!(filter.matches(&["OK Computer", "Blur"]), None);
+        //the first of the fields matching as well
+        let fuzzy_match = FuzzyFilter::new("a").matches(&["a", "a"]).unwrap();
+        assert_eq!(fuzzy_match.positions, vec![vec![0], vec![]]);
+    }
+
+    #[test]
+    fn an_upper_case_letter_makes_the_filter_case_sensitive() {
+        assert!(FuzzyFilter::new("ok").matches(&["OK Computer"]).is_some());
+        assert!(FuzzyFilter::new("Ok").matches(&["OK Computer"]).is_none());
+        assert!(FuzzyFilter::new("Ok").matches(&["Ok Go"]).is_some());
+    }
+
+    #[test]
+    fn ranks_the_best_matches_first() {
+        let rows = vec!["Karma Police", "Lucky", "Airbag", "Let Down"];
+        let fields = |x: &&str| vec![x.to_string()];
+        let ranked: Vec<&str> = FuzzyFilter::new("a")
+            .rank(rows.clone(), fields)
+            .into_iter()
+            .map(|(x, _)| x)
+            .collect();
+        //"Airbag" starts with the match; the others of the same score keep their order
+        assert_eq!(ranked, vec!["Airbag", "Karma Police"]);
+        let ranked: Vec<&str> = FuzzyFilter::new("")
+            .rank(rows.clone(), fields)
+            .into_iter()
+            .map(|(x, _)| x)
+            .collect();
+        assert_eq!(ranked, rows);
+    }
+
+    #[test]
+    fn highlights_the_matched_characters() {
+        let fuzzy_match = FuzzyFilter::new("rad")
+            .matches(&["OK Computer", "Radiohead"])
+            .unwrap();
+        assert_eq!(
+            fuzzy_match.segments(1, "Radiohead"),
+            vec![segment("Rad", true), segment("iohead", false)]
+        );
+        assert_eq!(
+            fuzzy_match.segments(0, "OK Computer"),
+            vec![segment("OK Computer", false)]
+        );
+        assert_eq!(
+            fuzzy_match.segments(2, "Radiohead"),
+            vec![segment("Radiohead", false)]
+        );
+    }
+
+    #[test]
+    fn splits_text_at_character_positions() {
+        assert_eq!(
+            segments("Sigur Rós", &[7]),
+            vec![
+                segment("Sigur R", false),
+                segment("ó", true),
+                segment("s", false)
+            ]
+        );
+        assert_eq!(segments("", &[0]), vec![]);
+    }
+
+    #[test]
+    fn fits_segments_to_a_width() {
+        assert_eq!(
+            fit(vec![segment("abc", true), segment("def", false)], 4),
+            vec![segment("abc", true), segment("d", false)]
+        );
+        assert_eq!(
+            fit(vec![segment("ab", false)], 4),
+            vec![segment("ab", false), segment("  ", false)]
+        );
+        //a wide character that does not fit is padded over
+        assert_eq!(
+            fit(vec![segment("日本", false)], 3),
+            vec![segment("日", false), segment(" ", false)]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::command::{KeyBinding, KeyContext, COMMANDS, KEYMAP};
+use crate::layout;
+use crate::layout::Clear;
+use crate::query;
+
+use std::cmp;
+use termion::event::Key;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Borders, List, Text, Widget};
+
+const GROUPS: [KeyContext; 3] = [KeyContext::Global, KeyContext::List, KeyContext::Input];
+//how much of the terminal the help covers
+const HELP_PERCENT: u16 = 80;
+
+/// The `?` overlay listing every key binding, `:` command and filter term; it scrolls like
+/// a list and `/` searches it.
+#[derive(Clone, Debug, Default)]
+pub struct Help {
+    pub active: bool,
+    pub search: String,
+    pub searching: bool,
+    pub scroll: usize,
+}
+
+impl Help {
+    pub fn open(&mut self) {
+        *self = Help {
+            active: true,
+            ..Help::default()
+        };
+    }
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+    fn matches(&self, text: &str) -> bool {
+        text.to_lowercase().contains(&self.search.to_lowercase())
+    }
+    fn group_lines(&self, title: &str, rows: Vec<(String, &str)>) -> Vec<Text<'static>> {
+        let rows: Vec<String> = rows
+            .into_iter()
+            .filter(|(keys, description)| self.matches(keys) || self.matches(description))
+            .map(|(keys, description)| format!("  {:<36} {}", keys, description))
+            .collect();
+        if rows.is_empty() {
+            return vec![];
+        }
+        let mut lines = vec![Text::styled(
+            title.to_string(),
+            Style::default().fg(Color::Cyan).modifier(Modifier::BOLD),
+        )];
+        lines.extend(rows.into_iter().map(Text::raw));
+        lines.push(Text::raw(""));
+        lines
+    }
+    //the groups of the keymap, the commands and the filter syntax, without the rows not matching the search
+    pub fn lines(&self) -> Vec<Text<'static>> {
+        let mut lines = vec![];
+        for context in GROUPS.iter() {
+            let rows = KEYMAP
+                .iter()
+                .filter(|x| x.context == *context)
+                .map(|x: &KeyBinding| (x.keys_text(), x.description))
+                .collect();
+            lines.extend(self.group_lines(context.name(), rows));
+        }
+        let rows = COMMANDS
+            .iter()
+            .map(|x| {
+                (
+                    format!(":{} {}", x.name, x.args).trim_end().to_string(),
+                    x.description,
+                )
+            })
+            .collect();
+        lines.extend(self.group_lines("Commands", rows));
+        let rows = query::SYNTAX
+            .iter()
+            .map(|(syntax, description)| (syntax.to_string(), *description))
+            .collect();
+        lines.extend(self.group_lines("Filter", rows));
+        lines
+    }
+    pub fn area(size: Rect) -> Rect {
+        layout::popup(size, HELP_PERCENT)
+    }
+    //the lines shown below the border and the search line
+    fn height(size: Rect) -> usize {
+        Help::area(size).height.saturating_sub(3) as usize
+    }
+    /// Handles `key` while the help is open; `size` is the size of the terminal.
+    pub fn key(&mut self, key: Key, size: Rect) {
+        if self.searching {
+            match key {
+                Key::Esc => {
+                    self.searching = false;
+                    self.search.clear();
+                }
+                Key::Char('\n') => self.searching = false,
+                Key::Backspace if self.search.is_empty() => self.searching = false,
+                Key::Backspace => {
+                    self.search.pop();
+                }
+                Key::Char(c) => self.search.push(c),
+                _ => {}
+            }
+            self.scroll = 0;
+            return;
+        }
+        let page = cmp::max(Help::height(size), 1);
+        let last = self.lines().len().saturating_sub(page);
+        self.scroll = match key {
+            Key::Esc | Key::Char('q') | Key::Char('?') => {
+                self.close();
+                return;
+            }
+            Key::Char('/') => {
+                self.searching = true;
+                self.search.clear();
+                0
+            }
+            Key::Down | Key::Char('j') => self.scroll + 1,
+            Key::Up | Key::Char('k') => self.scroll.saturating_sub(1),
+            Key::PageDown | Key::Ctrl('f') | Key::Char(' ') => self.scroll + page,
+            Key::PageUp | Key::Ctrl('b') => self.scroll.saturating_sub(page),
+            Key::Ctrl('d') => self.scroll + page / 2,
+            Key::Ctrl('u') => self.scroll.saturating_sub(page / 2),
+            Key::Home | Key::Char('g') => 0,
+            Key::End | Key::Char('G') => last,
+            _ => self.scroll,
+        };
+        self.scroll = cmp::min(self.scroll, last);
+    }
+    pub fn render(&self, buf: &mut Buffer, size: Rect) {
+        let area = Help::area(size);
+        Clear.draw(area, buf);
+        let search = if self.searching || !self.search.is_empty() {
+            Text::styled(
+                format!("/{}", self.search),
+                Style::default().fg(Color::Yellow),
+            )
+        } else {
+            Text::styled(
+                "/: search, j k PgDn PgUp: scroll, Esc: close",
+                Style::default().fg(Color::DarkGray),
+            )
+        };
+        let lines = self.lines();
+        let lines = if lines.is_empty() {
+            vec![Text::styled(
+                "nothing matches",
+                Style::default().fg(Color::DarkGray),
+            )]
+        } else {
+            lines
+        };
+        let items = std::iter::once(search)
+            .chain(lines.into_iter().skip(self.scroll))
+            .take(Help::height(size) + 1);
+        List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Help"))
+            .draw(area, buf);
+    }
+}
@@ -0,0 +1,190 @@
+extern crate chrono;
+extern crate rspotify;
+extern crate serde_json;
+
+use self::chrono::{DateTime, Utc};
+use self::rspotify::model::context::CurrentlyPlaybackContext;
+use self::rspotify::model::PlayingItem;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+//a track that stops this close to its end counts as played through
+const SKIP_MARGIN_MS: u32 = 10_000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub track_id: Option<String>,
+    pub track_name: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub duration_ms: u32,
+    pub context_uri: Option<String>,
+    pub device_name: String,
+    pub started_at: DateTime<Utc>,
+    pub played_ms: u32,
+    pub skipped: bool,
+}
+
+/// Listening history stored as one JSON Lines file per month, e.g. `2019-12.jsonl`.
+#[derive(Clone, Debug)]
+pub struct ListeningHistory {
+    pub dir: PathBuf,
+}
+
+impl ListeningHistory {
+    pub fn new(dir: PathBuf) -> ListeningHistory {
+        ListeningHistory { dir }
+    }
+    pub fn month_path(&self, month: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", month))
+    }
+    pub fn append(&self, entry: &HistoryEntry) -> Result<(), failure::Error> {
+        fs::create_dir_all(&self.dir)?;
+        let month = entry.started_at.format("%Y-%m").to_string();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.month_path(&month))?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+    //e.g. ["2019-11", "2019-12"] (oldest first)
+    pub fn months(&self) -> Vec<String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries
+            .filter_map(|x| x.ok())
+            .filter_map(|x| {
+                let path = x.path();
+                if path.extension().and_then(|x| x.to_str()) != Some("jsonl") {
+                    return None;
+                }
+                path.file_stem()
+                    .and_then(|x| x.to_str())
+                    .map(|x| x.to_string())
+            })
+            .sorted()
+            .collect()
+    }
+    pub fn load_month(&self, month: &str) -> Result<Vec<HistoryEntry>, failure::Error> {
+        let file = fs::File::open(self.month_path(month))?;
+        let mut entries = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => log::warn!("broken history entry in {}: {}", month, e),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Turns `CurrentPlayBack` polls into `HistoryEntry`s, one per track change.
+#[derive(Clone, Debug, Default)]
+pub struct HistoryRecorder {
+    current: Option<HistoryEntry>,
+    last_progress_ms: u32,
+    last_polled_at: Option<Instant>,
+    last_is_playing: bool,
+}
+
+impl HistoryRecorder {
+    pub fn new() -> HistoryRecorder {
+        HistoryRecorder::default()
+    }
+    //returns the previous entry once a different track starts (or playback disappears)
+    pub fn update(
+        &mut self,
+        current_playback: Option<&CurrentlyPlaybackContext>,
+    ) -> Option<HistoryEntry> {
+        let now = Instant::now();
+        let playing_track = current_playback.and_then(|x| match x.item.as_ref() {
+            Some(PlayingItem::Track(track)) => Some((x, track)),
+            _ => None,
+        });
+
+        self.count_played(now);
+
+        let same_track = match (self.current.as_ref(), playing_track) {
+            (Some(current), Some((_, track))) => {
+                current.track_id == track.id && current.track_name == track.name
+            }
+            _ => false,
+        };
+        let finished = if same_track {
+            None
+        } else {
+            self.take_finished()
+        };
+
+        match playing_track {
+            Some((playback, track)) => {
+                if self.current.is_none() {
+                    self.current = Some(HistoryEntry {
+                        track_id: track.id.clone(),
+                        track_name: track.name.clone(),
+                        artists: track.artists.iter().map(|x| x.name.clone()).collect(),
+                        album: track.album.name.clone(),
+                        duration_ms: track.duration_ms,
+                        context_uri: playback.context.as_ref().map(|x| x.uri.clone()),
+                        device_name: playback.device.name.clone(),
+                        started_at: Utc::now()
+                            - chrono::Duration::milliseconds(
+                                playback.progress_ms.unwrap_or(0) as i64
+                            ),
+                        played_ms: 0,
+                        skipped: false,
+                    });
+                }
+                self.last_progress_ms = playback.progress_ms.unwrap_or(0);
+                self.last_is_playing = playback.is_playing;
+            }
+            None => {
+                self.last_progress_ms = 0;
+                self.last_is_playing = false;
+            }
+        }
+        self.last_polled_at = Some(now);
+        finished
+    }
+    //returns the entry of the track still playing, e.g. when quitting
+    pub fn finish(&mut self) -> Option<HistoryEntry> {
+        let now = Instant::now();
+        self.count_played(now);
+        if self.last_is_playing {
+            if let Some(last_polled_at) = self.last_polled_at {
+                self.last_progress_ms += now.duration_since(last_polled_at).as_millis() as u32;
+            }
+        }
+        let finished = self.take_finished();
+        *self = HistoryRecorder::default();
+        finished
+    }
+    //count wall-clock time between two polls that were both playing
+    fn count_played(&mut self, now: Instant) {
+        if let Some(current) = self.current.as_mut() {
+            if self.last_is_playing {
+                if let Some(last_polled_at) = self.last_polled_at {
+                    current.played_ms += now.duration_since(last_polled_at).as_millis() as u32;
+                }
+            }
+        }
+    }
+    fn take_finished(&mut self) -> Option<HistoryEntry> {
+        self.current.take().map(|mut entry| {
+            entry.played_ms = std::cmp::min(entry.played_ms, entry.duration_ms);
+            entry.skipped = self.last_progress_ms + SKIP_MARGIN_MS < entry.duration_ms;
+            entry
+        })
+    }
+}
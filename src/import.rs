@@ -0,0 +1,542 @@
+extern crate rspotify;
+
+use crate::query;
+
+use self::rspotify::model::track::FullTrack;
+use itertools::Itertools;
+use std::cmp;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+//a track with a lower score is not accepted by default
+const MEDIUM_SCORE: f64 = 0.6;
+const HIGH_SCORE: f64 = 0.85;
+//how many search results are kept as alternatives
+pub const SEARCH_LIMIT: u32 = 5;
+
+/// A line of the imported file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportEntry {
+    pub artist: String,
+    pub title: String,
+    pub duration_ms: Option<u32>,
+}
+
+impl ImportEntry {
+    //"Artist - Title", "Artist – Title" or only "Title"
+    fn from_text(text: &str) -> Option<ImportEntry> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        for separator in [" – ", " — ", " - "].iter() {
+            if let Some(index) = text.find(separator) {
+                return Some(ImportEntry {
+                    artist: text[..index].trim().to_string(),
+                    title: text[index + separator.len()..].trim().to_string(),
+                    duration_ms: None,
+                });
+            }
+        }
+        Some(ImportEntry {
+            title: text.to_string(),
+            ..ImportEntry::default()
+        })
+    }
+    pub fn search_query(&self) -> String {
+        format!("{} {}", normalize(&self.title), normalize(&self.artist))
+            .trim()
+            .to_string()
+    }
+}
+
+fn parse_m3u(content: &str) -> Vec<ImportEntry> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("#EXTINF:"))
+        .filter_map(|info| {
+            let (seconds, text) = match info.find(',') {
+                Some(index) => (&info[..index], &info[index + 1..]),
+                None => ("", info),
+            };
+            let mut entry = ImportEntry::from_text(text)?;
+            entry.duration_ms = seconds
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|x| *x > 0)
+                .map(|x| x as u32 * 1000);
+            Some(entry)
+        })
+        .collect()
+}
+
+//fields of a CSV line, with "quoted, fields" and "" as an escaped quote
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|x| x.trim().to_string()).collect()
+}
+
+//"3:25" or milliseconds (as exported by `spoterm export`)
+fn parse_duration(text: &str) -> Option<u32> {
+    let mut parts = text.split(':');
+    let first: u32 = parts.next()?.trim().parse().ok()?;
+    match parts.next() {
+        Some(seconds) => Some((first * 60 + seconds.trim().parse::<u32>().ok()?) * 1000),
+        None => Some(first),
+    }
+}
+
+fn parse_csv(content: &str) -> Vec<ImportEntry> {
+    let mut lines = content.lines().filter(|x| !x.trim().is_empty());
+    let header = match lines.next() {
+        Some(header) => csv_fields(&header.to_lowercase()),
+        None => return vec![],
+    };
+    let column = |names: &[&str]| header.iter().position(|x| names.contains(&x.as_str()));
+    let artist = column(&["artist", "artists", "artist name", "artist name(s)"]);
+    let title = column(&["title", "name", "track", "track name", "song"]);
+    let duration = column(&["duration", "duration_ms", "duration (ms)", "length"]);
+    if title.is_none() {
+        //no header: "Artist - Title" or "Artist,Title" lines
+        return content
+            .lines()
+            .filter_map(|line| {
+                let fields = csv_fields(line);
+                match fields.len() {
+                    0 | 1 => ImportEntry::from_text(line),
+                    _ => Some(ImportEntry {
+                        artist: fields[0].clone(),
+                        title: fields[1].clone(),
+                        duration_ms: None,
+                    }),
+                }
+            })
+            .collect();
+    }
+    lines
+        .filter_map(|line| {
+            let fields = csv_fields(line);
+            let field = |index: Option<usize>| index.and_then(|x| fields.get(x)).cloned();
+            let title = field(title).filter(|x| !x.is_empty())?;
+            Some(ImportEntry {
+                //"; " is how `spoterm export` joins several artists
+                artist: field(artist).unwrap_or_default().replace("; ", ", "),
+                title,
+                duration_ms: field(duration).and_then(|x| parse_duration(&x)),
+            })
+        })
+        .collect()
+}
+
+/// Reads CSV (by the extension), M3U (by the extension or `#EXTM3U`) or "Artist - Title" lines.
+pub fn parse_import_file(path: &Path) -> io::Result<Vec<ImportEntry>> {
+    let content = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    Ok(
+        if extension == "m3u" || extension == "m3u8" || content.starts_with("#EXTM3U") {
+            parse_m3u(&content)
+        } else if extension == "csv" {
+            parse_csv(&content)
+        } else {
+            content
+                .lines()
+                .filter(|x| !x.trim_start().starts_with('#'))
+                .filter_map(ImportEntry::from_text)
+                .collect()
+        },
+    )
+}
+
+//lowercase words without "(Remastered)", "[Live]", "- 2011 Remaster", "feat. ..." and punctuation
+fn normalize(text: &str) -> String {
+    let text = text.split(" - ").next().unwrap_or("");
+    let mut result = String::new();
+    let mut depth: i32 = 0;
+    for c in text.to_lowercase().chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = cmp::max(depth - 1, 0),
+            _ if depth > 0 => {}
+            c if c.is_alphanumeric() => result.push(c),
+            _ => result.push(' '),
+        }
+    }
+    let words: Vec<&str> = result.split_whitespace().collect();
+    let end = words
+        .iter()
+        .position(|x| *x == "feat" || *x == "ft" || *x == "featuring")
+        .unwrap_or(words.len());
+    words[..end].join(" ")
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let cost = if x == y { 0 } else { 1 };
+            current.push(cmp::min(
+                cmp::min(previous[j + 1] + 1, current[j] + 1),
+                previous[j] + cost,
+            ));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// 0.0 (different) to 1.0 (the same after normalizing); the better of the edit distance
+/// and the share of common words, so word order and extra words cost less.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let a_chars: Vec<char> = a.chars().collect();
+    let b_chars: Vec<char> = b.chars().collect();
+    let max_len = cmp::max(a_chars.len(), b_chars.len()) as f64;
+    let edit = 1.0 - levenshtein(&a_chars, &b_chars) as f64 / max_len;
+    let a_words: Vec<&str> = a.split_whitespace().collect();
+    let b_words: Vec<&str> = b.split_whitespace().collect();
+    let common = a_words.iter().filter(|x| b_words.contains(x)).count() as f64;
+    let words = 2.0 * common / (a_words.len() + b_words.len()) as f64;
+    edit.max(words)
+}
+
+/// How well `track` matches `entry`: title 60%, artist 30% and duration 10%,
+/// reweighted over what the entry has.
+pub fn score(entry: &ImportEntry, track: &FullTrack) -> f64 {
+    let mut total = 0.6 * similarity(&entry.title, &track.name);
+    let mut weight = 0.6;
+    if !entry.artist.is_empty() {
+        let artists: Vec<String> = track.artists.iter().map(|x| x.name.clone()).collect();
+        let artist = artists
+            .iter()
+            .map(|x| similarity(&entry.artist, x))
+            .fold(similarity(&entry.artist, &artists.join(" ")), f64::max);
+        total += 0.3 * artist;
+        weight += 0.3;
+    }
+    if let Some(duration_ms) = entry.duration_ms {
+        //full score within 2 seconds, none beyond 30
+        let diff_sec = (duration_ms as f64 - track.duration_ms as f64).abs() / 1000.0;
+        total += 0.1 * (1.0 - ((diff_sec - 2.0) / 28.0).clamp(0.0, 1.0));
+        weight += 0.1;
+    }
+    total / weight
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Confidence {
+    Searching,
+    Failed,
+    NotFound,
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn name(self) -> &'static str {
+        match self {
+            Confidence::Searching => "...",
+            Confidence::Failed => "failed",
+            Confidence::NotFound => "none",
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportItem {
+    pub entry: ImportEntry,
+    //best first
+    pub candidates: Vec<(FullTrack, f64)>,
+    pub selected: usize,
+    pub accepted: bool,
+    pub searched: bool,
+    //the search failed; `:import retry` searches again
+    pub failed: bool,
+}
+
+impl ImportItem {
+    pub fn selected_candidate(&self) -> Option<&(FullTrack, f64)> {
+        self.candidates.get(self.selected)
+    }
+    pub fn confidence(&self) -> Confidence {
+        if self.failed {
+            return Confidence::Failed;
+        }
+        if !self.searched {
+            return Confidence::Searching;
+        }
+        match self.selected_candidate() {
+            None => Confidence::NotFound,
+            Some((_, score)) if *score >= HIGH_SCORE => Confidence::High,
+            Some((_, score)) if *score >= MEDIUM_SCORE => Confidence::Medium,
+            Some(_) => Confidence::Low,
+        }
+    }
+    //cycles through the alternatives
+    pub fn select_next(&mut self) {
+        if !self.candidates.is_empty() {
+            self.selected = (self.selected + 1) % self.candidates.len();
+        }
+    }
+    pub fn select_previous(&mut self) {
+        if !self.candidates.is_empty() {
+            self.selected = (self.selected + self.candidates.len() - 1) % self.candidates.len();
+        }
+    }
+}
+
+/// The entries of an imported file and their search results, reviewed in the Import view.
+#[derive(Clone, Debug)]
+pub struct ImportSession {
+    pub path: String,
+    pub items: Vec<ImportItem>,
+    //a new one whenever the items change, see query::RankCache
+    pub revision: u64,
+}
+
+impl ImportSession {
+    pub fn new(path: String, entries: Vec<ImportEntry>) -> ImportSession {
+        ImportSession {
+            revision: query::new_revision(),
+            path,
+            items: entries
+                .into_iter()
+                .map(|entry| ImportItem {
+                    entry,
+                    candidates: vec![],
+                    selected: 0,
+                    accepted: false,
+                    searched: false,
+                    failed: false,
+                })
+                .collect(),
+        }
+    }
+    pub fn search_queries(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .map(|x| x.entry.search_query())
+            .unique()
+            .collect()
+    }
+    /// Scores the search results of `query`; medium and high matches are accepted.
+    pub fn set_search_result(&mut self, query: &str, tracks: &[FullTrack]) {
+        for item in self
+            .items
+            .iter_mut()
+            .filter(|x| !x.searched && x.entry.search_query() == query)
+        {
+            let mut candidates: Vec<(FullTrack, f64)> = tracks
+                .iter()
+                .map(|track| (track.clone(), score(&item.entry, track)))
+                .collect();
+            candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(cmp::Ordering::Equal));
+            item.candidates = candidates;
+            item.selected = 0;
+            item.searched = true;
+            item.accepted = matches!(item.confidence(), Confidence::High | Confidence::Medium);
+        }
+        self.revision = query::new_revision();
+    }
+    pub fn set_search_failure(&mut self, query: &str) {
+        for item in self
+            .items
+            .iter_mut()
+            .filter(|x| !x.searched && x.entry.search_query() == query)
+        {
+            item.failed = true;
+        }
+        self.revision = query::new_revision();
+    }
+    //the queries of the failed searches, which are searching again
+    pub fn retry_failed(&mut self) -> Vec<String> {
+        let queries = self
+            .items
+            .iter()
+            .filter(|x| x.failed)
+            .map(|x| x.entry.search_query())
+            .unique()
+            .collect();
+        for item in self.items.iter_mut() {
+            item.failed = false;
+        }
+        self.revision = query::new_revision();
+        queries
+    }
+    pub fn accepted_track_ids(&self) -> Vec<String> {
+        let mut track_ids: Vec<String> = self
+            .items
+            .iter()
+            .filter(|x| x.accepted)
+            .filter_map(|x| x.selected_candidate())
+            .filter_map(|(track, _)| track.id.clone())
+            .collect();
+        //the same track may be listed twice
+        let mut seen = std::collections::HashSet::new();
+        track_ids.retain(|x| seen.insert(x.clone()));
+        track_ids
+    }
+    pub fn searching(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|x| !x.searched && !x.failed)
+            .count()
+    }
+    pub fn failed(&self) -> usize {
+        self.items.iter().filter(|x| x.failed).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(artist: &str, title: &str, duration_ms: Option<u32>) -> ImportEntry {
+        ImportEntry {
+            artist: artist.to_string(),
+            title: title.to_string(),
+            duration_ms,
+        }
+    }
+
+    #[test]
+    fn parses_text_lines() {
+        assert_eq!(
+            ImportEntry::from_text(" Queen - Bohemian Rhapsody "),
+            Some(entry("Queen", "Bohemian Rhapsody", None))
+        );
+        //the first separator splits, the rest belongs to the title
+        assert_eq!(
+            ImportEntry::from_text("Beatles – Yesterday - Remastered"),
+            Some(entry("Beatles", "Yesterday - Remastered", None))
+        );
+        assert_eq!(
+            ImportEntry::from_text("Untitled"),
+            Some(entry("", "Untitled", None))
+        );
+        assert_eq!(ImportEntry::from_text("  "), None);
+    }
+
+    #[test]
+    fn parses_m3u() {
+        let content = "#EXTM3U\n\
+                       #EXTINF:215,Queen - Bohemian Rhapsody\n\
+                       music/queen.mp3\n\
+                       #EXTINF:-1,Stream\n\
+                       #EXTINF:Untimed\n";
+        assert_eq!(
+            parse_m3u(content),
+            vec![
+                entry("Queen", "Bohemian Rhapsody", Some(215_000)),
+                entry("", "Stream", None),
+                entry("", "Untimed", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_csv_fields() {
+        assert_eq!(
+            csv_fields(r#"a,"b, c","say ""hi""", d ,"#),
+            vec!["a", "b, c", "say \"hi\"", "d", ""]
+        );
+        assert_eq!(parse_duration("3:25"), Some(205_000));
+        assert_eq!(parse_duration("205000"), Some(205_000));
+        assert_eq!(parse_duration("3:xx"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn parses_csv() {
+        //as exported by `spoterm export` and other tools
+        let content = "Track Name,Artist Name(s),Duration (ms)\n\
+                       \"Hello, World\",A; B,185000\n\
+                       \n\
+                       ,Nobody,1000\n\
+                       Short,C,3:05\n";
+        assert_eq!(
+            parse_csv(content),
+            vec![
+                entry("A, B", "Hello, World", Some(185_000)),
+                entry("C", "Short", Some(185_000)),
+            ]
+        );
+        //without a header
+        assert_eq!(
+            parse_csv("Queen,Bohemian Rhapsody\nBeatles - Yesterday\n"),
+            vec![
+                entry("Queen", "Bohemian Rhapsody", None),
+                entry("Beatles", "Yesterday", None),
+            ]
+        );
+        assert_eq!(parse_csv(""), vec![]);
+    }
+
+    #[test]
+    fn similarity_ignores_versions_and_features() {
+        assert_eq!(similarity("Yesterday - Remastered 2009", "yesterday"), 1.0);
+        assert_eq!(similarity("Hello (Live) [Bonus]", "Hello!"), 1.0);
+        assert_eq!(similarity("Song feat. Someone", "Song"), 1.0);
+        assert_eq!(similarity("Love Me Do", "Do Love Me"), 1.0);
+        assert_eq!(similarity("", "Song"), 0.0);
+        assert_eq!(similarity("abcd", "wxyz"), 0.0);
+        let close = similarity("Bohemian Rhapsody", "Bohemian Rapsody");
+        assert!(close > 0.9 && close < 1.0, "{}", close);
+        assert!(similarity("Bohemian Rhapsody", "Another One") < 0.5);
+    }
+
+    #[test]
+    fn failed_searches_are_retried() {
+        let mut session = ImportSession::new(
+            "list.txt".to_string(),
+            vec![
+                entry("Queen", "Bohemian Rhapsody", None),
+                entry("Queen", "Bohemian Rhapsody (Live)", None),
+                entry("Beatles", "Yesterday", None),
+            ],
+        );
+        assert_eq!(
+            session.search_queries(),
+            vec!["bohemian rhapsody queen", "yesterday beatles"]
+        );
+        session.set_search_failure("bohemian rhapsody queen");
+        assert_eq!(session.items[0].confidence(), Confidence::Failed);
+        assert_eq!((session.searching(), session.failed()), (1, 2));
+        assert_eq!(session.retry_failed(), vec!["bohemian rhapsody queen"]);
+        assert_eq!((session.searching(), session.failed()), (3, 0));
+        session.set_search_result("yesterday beatles", &[]);
+        assert_eq!(session.items[2].confidence(), Confidence::NotFound);
+        assert!(session.accepted_track_ids().is_empty());
+    }
+}
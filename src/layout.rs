@@ -0,0 +1,171 @@
+use std::cmp;
+use tui::buffer::Buffer;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::widgets::Widget;
+
+//below this the UI only asks for a bigger terminal
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 15;
+//the player and the devices move to a column on the right from this width
+const SIDE_PANEL_MIN_WIDTH: u16 = 140;
+const SIDE_PANEL_WIDTH: u16 = 50;
+//the album art is square, in cells twice as high as wide; below this height it is left out
+const COVER_MIN_HEIGHT: u16 = 3;
+//the text of the player beside the album art, which is below it in the side panel
+const PLAYER_TEXT_MIN_WIDTH: u16 = 80;
+const SIDE_COVER_HEIGHT: u16 = 12;
+const DEVICES_MIN_HEIGHT: u16 = 3;
+
+/// Where each part of the UI goes for the current terminal size; the list gets the rest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AppLayout {
+    pub menu: Rect,
+    pub player: Rect,
+    //inside the borders of the player, beside or above the album art
+    pub player_text: Rect,
+    pub cover: Option<Rect>,
+    pub filter: Rect,
+    pub content: Rect,
+    //only on wide terminals
+    pub devices: Option<Rect>,
+    //the toasts, a line at the bottom
+    pub status: Rect,
+}
+
+impl AppLayout {
+    pub fn new(size: Rect) -> Option<AppLayout> {
+        if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+            return None;
+        }
+        //margins only where there is room to spare
+        let margin = if size.width >= 160 && size.height >= 45 {
+            2
+        } else if size.width >= 100 && size.height >= 30 {
+            1
+        } else {
+            0
+        };
+        let area = size.inner(margin);
+        let status = Rect::new(area.x, area.bottom() - 1, area.width, 1);
+        let area = Rect::new(area.x, area.y, area.width, area.height - 1);
+        if area.width < SIDE_PANEL_MIN_WIDTH {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Length(3),
+                        Constraint::Length(5),
+                        Constraint::Length(3),
+                        Constraint::Min(3),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+            return Some(AppLayout {
+                menu: rows[0],
+                player: rows[1],
+                player_text: rows[1].inner(1),
+                cover: None,
+                filter: rows[2],
+                content: rows[3],
+                devices: None,
+                status,
+            });
+        }
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(0), Constraint::Length(SIDE_PANEL_WIDTH)].as_ref())
+            .split(area);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(3),
+                    Constraint::Length(3),
+                    Constraint::Min(3),
+                ]
+                .as_ref(),
+            )
+            .split(columns[0]);
+        let side_rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(10), Constraint::Min(3)].as_ref())
+            .split(columns[1]);
+        Some(AppLayout {
+            menu: rows[0],
+            filter: rows[1],
+            content: rows[2],
+            player: side_rows[0],
+            player_text: side_rows[0].inner(1),
+            cover: None,
+            devices: Some(side_rows[1]),
+            status,
+        })
+    }
+    /// Makes room for the album art: on the left of the player, or in the side panel below
+    /// the text (taking rows from the devices). Left out where there is no room for it.
+    pub fn with_cover(mut self) -> Self {
+        let inner = self.player.inner(1);
+        match self.devices.as_mut() {
+            Some(devices) => {
+                let height = cmp::min(
+                    SIDE_COVER_HEIGHT,
+                    devices.height.saturating_sub(DEVICES_MIN_HEIGHT),
+                );
+                if height < COVER_MIN_HEIGHT || inner.width < height * 2 {
+                    return self;
+                }
+                self.player.height += height;
+                devices.y += height;
+                devices.height -= height;
+                self.cover = Some(Rect::new(
+                    inner.x + (inner.width - height * 2) / 2,
+                    inner.bottom(),
+                    height * 2,
+                    height,
+                ));
+            }
+            None => {
+                let width = inner.height * 2;
+                if inner.height < COVER_MIN_HEIGHT
+                    || inner.width < width + 1 + PLAYER_TEXT_MIN_WIDTH
+                {
+                    return self;
+                }
+                self.cover = Some(Rect::new(inner.x, inner.y, width, inner.height));
+                self.player_text = Rect::new(
+                    inner.x + width + 1,
+                    inner.y,
+                    inner.width - width - 1,
+                    inner.height,
+                );
+            }
+        }
+        self
+    }
+}
+
+/// A popup of `percent` of `size` in both directions, in the middle of it.
+pub fn popup(size: Rect, percent: u16) -> Rect {
+    let width = size.width * percent / 100;
+    let height = size.height * percent / 100;
+    Rect::new(
+        size.x + (size.width - width) / 2,
+        size.y + (size.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Blanks its area, so that a popup does not show the views below it.
+pub struct Clear;
+
+impl Widget for Clear {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                buf.get_mut(x, y).reset();
+            }
+        }
+    }
+}
@@ -1,5 +1,27 @@
+pub mod cache;
+pub mod cli;
+pub mod command;
 pub mod config;
+pub mod cover;
+pub mod daemon;
+pub mod details;
 pub mod event;
+pub mod export;
+pub mod fuzzy;
+pub mod help;
+pub mod history;
+pub mod import;
+pub mod layout;
+pub mod lyrics;
+pub mod message;
+#[cfg(feature = "mpris")]
+pub mod mpris;
+pub mod query;
+pub mod record;
+pub mod scrobble;
+pub mod snapshot;
 pub mod spoterm;
 pub mod spotify;
+pub mod status;
+pub mod table;
 pub mod ui;
@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LyricLine {
+    //None for plain-text lyrics
+    pub time_ms: Option<u32>,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Lyrics {
+    pub lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+    /// Parses LRC (`[mm:ss.xx]text`, several time tags per line and `[offset:ms]` are allowed).
+    /// Content without any time tag is kept as plain text.
+    pub fn parse(content: &str) -> Lyrics {
+        let mut offset_ms: i64 = 0;
+        let mut synced_lines = vec![];
+        let mut plain_lines = vec![];
+        for line in content.lines() {
+            let mut rest = line.trim();
+            let mut times = vec![];
+            while rest.starts_with('[') {
+                let end = match rest.find(']') {
+                    Some(end) => end,
+                    None => break,
+                };
+                let tag = &rest[1..end];
+                if let Some(time_ms) = Lyrics::parse_time_tag(tag) {
+                    times.push(time_ms);
+                } else if let Some(offset) = tag.strip_prefix("offset:") {
+                    offset_ms = offset.trim().parse().unwrap_or(0);
+                } else if !tag.contains(':') {
+                    //not a tag at all (e.g. "[Chorus]")
+                    break;
+                }
+                rest = rest[end + 1..].trim_start();
+            }
+            if times.is_empty() {
+                if rest.len() == line.trim().len() {
+                    plain_lines.push(line.trim_end().to_string());
+                }
+                continue;
+            }
+            for time_ms in times {
+                synced_lines.push((time_ms, rest.to_string()));
+            }
+        }
+        if synced_lines.is_empty() {
+            //drop the blank lines surrounding plain lyrics
+            while plain_lines.last().is_some_and(|x| x.is_empty()) {
+                plain_lines.pop();
+            }
+            let first = plain_lines
+                .iter()
+                .position(|x| !x.is_empty())
+                .unwrap_or(plain_lines.len());
+            return Lyrics {
+                lines: plain_lines
+                    .into_iter()
+                    .skip(first)
+                    .map(|text| LyricLine {
+                        time_ms: None,
+                        text,
+                    })
+                    .collect(),
+            };
+        }
+        synced_lines.sort_by_key(|x| x.0);
+        Lyrics {
+            lines: synced_lines
+                .into_iter()
+                //a positive offset shows lyrics earlier
+                .map(|(time_ms, text)| LyricLine {
+                    time_ms: Some(std::cmp::max(time_ms as i64 - offset_ms, 0) as u32),
+                    text,
+                })
+                .collect(),
+        }
+    }
+    //"01:23.45" or "01:23" or "01:23:45" (hundredths after a colon)
+    fn parse_time_tag(tag: &str) -> Option<u32> {
+        let mut parts = tag.splitn(2, ':');
+        let minutes: u32 = parts.next()?.trim().parse().ok()?;
+        let seconds = parts.next()?.trim().replacen(':', ".", 1);
+        let seconds: f64 = seconds.parse().ok()?;
+        if !(0.0..60.0).contains(&seconds) {
+            return None;
+        }
+        Some(minutes * 60 * 1000 + (seconds * 1000.0).round() as u32)
+    }
+    pub fn is_synced(&self) -> bool {
+        self.lines.iter().any(|x| x.time_ms.is_some())
+    }
+    //index of the line being sung at progress_ms
+    pub fn current_line(&self, progress_ms: u32) -> Option<usize> {
+        if !self.is_synced() {
+            return None;
+        }
+        self.lines
+            .iter()
+            .rposition(|x| x.time_ms.is_some_and(|time_ms| time_ms <= progress_ms))
+    }
+}
+
+//lowercase and strip characters that can not appear in file names
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|x| !"/\\:*?\"<>|".contains(*x))
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Looks for `<track id>.lrc`, `<artist> - <title>.lrc` or `<title>.lrc` (or `.txt`) in `dir`,
+/// ignoring case.
+pub fn find_lyrics_file(
+    dir: &Path,
+    track_id: Option<&str>,
+    artist: &str,
+    title: &str,
+) -> Option<PathBuf> {
+    let mut candidates = vec![];
+    if let Some(track_id) = track_id {
+        candidates.push(track_id.to_lowercase());
+    }
+    candidates.push(normalize(&format!("{} - {}", artist, title)));
+    candidates.push(normalize(title));
+
+    let files: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| x.is_file())
+        .collect();
+    for extension in ["lrc", "txt"].iter() {
+        for candidate in candidates.iter() {
+            let found = files.iter().find(|path| {
+                let same_extension = path
+                    .extension()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| x.eq_ignore_ascii_case(extension));
+                let same_stem = path
+                    .file_stem()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| normalize(x) == *candidate);
+                same_extension && same_stem
+            });
+            if let Some(found) = found {
+                return Some(found.clone());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(lyrics: &Lyrics) -> Vec<(u32, &str)> {
+        lyrics
+            .lines
+            .iter()
+            .map(|x| (x.time_ms.unwrap(), x.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn time_tags() {
+        assert_eq!(Lyrics::parse_time_tag("01:23.45"), Some(83_450));
+        assert_eq!(Lyrics::parse_time_tag("1:23.456"), Some(83_456));
+        assert_eq!(Lyrics::parse_time_tag("01:23"), Some(83_000));
+        assert_eq!(Lyrics::parse_time_tag("01:23:45"), Some(83_450));
+        assert_eq!(Lyrics::parse_time_tag("00:60.00"), None);
+        assert_eq!(Lyrics::parse_time_tag("ar:Artist"), None);
+        assert_eq!(Lyrics::parse_time_tag("12"), None);
+    }
+
+    #[test]
+    fn parses_lrc() {
+        let lyrics = Lyrics::parse(
+            "[ar:Artist]\n\
+             [ti:Title]\n\
+             [00:12.00]First line\n\
+             [00:05.50] Intro\n\
+             [00:20.00]\n",
+        );
+        assert!(lyrics.is_synced());
+        assert_eq!(
+            synced(&lyrics),
+            vec![(5_500, "Intro"), (12_000, "First line"), (20_000, "")]
+        );
+        assert_eq!(lyrics.current_line(0), None);
+        assert_eq!(lyrics.current_line(12_000), Some(1));
+        assert_eq!(lyrics.current_line(60_000), Some(2));
+    }
+
+    #[test]
+    fn several_time_tags_on_a_line() {
+        let lyrics = Lyrics::parse("[00:10.00][00:30.00]Chorus\n[00:20.00]Verse\n");
+        assert_eq!(
+            synced(&lyrics),
+            vec![(10_000, "Chorus"), (20_000, "Verse"), (30_000, "Chorus")]
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        //a positive offset shows the lyrics earlier, but not before the start
+        let lyrics = Lyrics::parse("[offset:+500]\n[00:00.20]a\n[00:01.00]b\n");
+        assert_eq!(synced(&lyrics), vec![(0, "a"), (500, "b")]);
+        let lyrics = Lyrics::parse("[offset:-250]\n[00:01.00]a\n");
+        assert_eq!(synced(&lyrics), vec![(1_250, "a")]);
+        let lyrics = Lyrics::parse("[offset:soon]\n[00:01.00]a\n");
+        assert_eq!(synced(&lyrics), vec![(1_000, "a")]);
+    }
+
+    #[test]
+    fn malformed_tags() {
+        //lines without a valid time are dropped from synced lyrics
+        let lyrics = Lyrics::parse(
+            "[00:99.00]Too many seconds\n\
+             [Chorus]\n\
+             [00:01.00 unclosed\n\
+             [00:02.00]Kept\n",
+        );
+        assert_eq!(synced(&lyrics), vec![(2_000, "Kept")]);
+        //and plain lyrics keep "[Chorus]" but not the metadata
+        let lyrics = Lyrics::parse("[ar:Artist]\n\n[Chorus]\nLine one\n\nLine two\n\n");
+        assert!(!lyrics.is_synced());
+        assert_eq!(lyrics.current_line(1_000), None);
+        let texts: Vec<&str> = lyrics.lines.iter().map(|x| x.text.as_str()).collect();
+        assert_eq!(texts, vec!["[Chorus]", "Line one", "", "Line two"]);
+    }
+}
@@ -15,9 +15,6 @@ use termion::input::MouseTerminal;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
 use tui::backend::TermionBackend;
-use tui::layout::{Constraint, Direction, Layout};
-use tui::style::{Color, Style};
-use tui::widgets::{Block, Borders, List, Paragraph, Tabs, Text, Widget};
 use tui::Terminal;
 
 use log::LevelFilter;
@@ -25,9 +22,16 @@ use log4rs::append::file::FileAppender;
 use log4rs::config;
 use log4rs::config::Appender;
 use log4rs::encode::pattern::PatternEncoder;
-use spoterm::config::UserConfig;
+use spoterm::cache::LibraryCache;
+use spoterm::cli;
+use spoterm::command::{parse_command, Action};
+use spoterm::config::{cache_dir, data_dir, UserConfig};
+use spoterm::cover::{CoverService, Covers, Graphics, Protocol};
+use spoterm::daemon::{socket_path, Daemon, DaemonClient};
 use spoterm::event;
-use spoterm::spoterm::SpotermClient;
+use spoterm::record;
+use spoterm::scrobble::ScrobbleService;
+use spoterm::spoterm::{Screen, SpotermClient};
 use spoterm::spotify::SpotifyService;
 
 //Authorization Scopes
@@ -83,7 +87,7 @@ fn init_spoterm_config_if_needed() -> Result<(), failure::Error> {
     Ok(())
 }
 
-fn get_spotify_client_id_and_secret() -> Result<(String, String), Box<dyn std::error::Error>> {
+fn read_user_config() -> Result<UserConfig, Box<dyn std::error::Error>> {
     //read config from file
     let config = dirs::home_dir()
         .expect("can not find home directory")
@@ -91,17 +95,24 @@ fn get_spotify_client_id_and_secret() -> Result<(String, String), Box<dyn std::e
         .join("config.toml");
     let config_content = fs::read_to_string(config.to_str().expect("can not read config file"))?;
     let user_config: UserConfig = toml::from_str(&config_content)?;
-
-    Ok((
-        user_config.profile.client_id,
-        user_config.profile.client_secret,
-    ))
+    Ok(user_config)
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    init_spoterm_config_if_needed()?;
+fn spotify_oauth(user_config: &UserConfig) -> rspotify::oauth2::SpotifyOAuth {
+    let spoterm_cache = dirs::home_dir()
+        .expect("can not find home directory")
+        .join(".spoterm")
+        .join(".spotify_token_cache.json");
+    rspotify::oauth2::SpotifyOAuth::default()
+        .scope(&SCOPES.join(" "))
+        .client_id(&user_config.profile.client_id)
+        .client_secret(&user_config.profile.client_secret)
+        .redirect_uri("http://localhost:8888/callback")
+        .cache_path(spoterm_cache)
+        .build()
+}
 
+fn init_logger() -> Result<(), Box<dyn std::error::Error>> {
     let logfile = FileAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{d} - {m}{n}")))
         .build("log/output.log")?;
@@ -113,28 +124,172 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .build(LevelFilter::Info),
         )?;
     log4rs::init_config(config)?;
+    Ok(())
+}
 
-    let (client_id, client_secret) = get_spotify_client_id_and_secret()?;
-    let spoterm_cache = dirs::home_dir()
-        .expect("can not find home directory")
-        .join(".spoterm")
-        .join(".spotify_token_cache.json");
-    let mut oauth = rspotify::oauth2::SpotifyOAuth::default()
-        .scope(&SCOPES.join(" "))
-        .client_id(&client_id)
-        .client_secret(&client_secret)
-        .redirect_uri("http://localhost:8888/callback")
-        .cache_path(spoterm_cache)
-        .build();
+async fn start_scrobble_service_if_configured(
+    user_config: &UserConfig,
+    spoterm: SpotermClient,
+) -> Result<SpotermClient, Box<dyn std::error::Error>> {
+    let listenbrainz = match user_config.listenbrainz.as_ref() {
+        Some(listenbrainz) => listenbrainz,
+        None => return Ok(spoterm),
+    };
+    let scrobble = ScrobbleService::new(
+        listenbrainz.url.clone(),
+        listenbrainz.token.clone(),
+        data_dir().join("listenbrainz_queue.json"),
+    );
+    let spoterm = spoterm.scrobble_event_tx(scrobble.scrobble_event_tx.clone());
+    scrobble.run().await?;
+    Ok(spoterm)
+}
 
-    let token_info = rspotify::util::get_token(&mut oauth).await.unwrap();
+//the album art of the player, unless [cover] turns it off
+async fn start_cover_service(
+    user_config: &UserConfig,
+    spoterm: SpotermClient,
+) -> Result<SpotermClient, Box<dyn std::error::Error>> {
+    let protocol = user_config.cover.protocol();
+    if protocol == Protocol::Off {
+        return Ok(spoterm);
+    }
+    let cover = CoverService::default();
+    let spoterm = spoterm.covers(Covers::new(protocol, &cover));
+    cover.run().await?;
+    Ok(spoterm)
+}
 
+//`spoterm daemon`: owns the session and the polling until it is killed
+async fn run_daemon() -> Result<i32, Box<dyn std::error::Error>> {
+    let path = socket_path();
+    let listener = match Daemon::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(cli::EXIT_FAILURE);
+        }
+    };
+    init_logger()?;
+    let user_config = read_user_config()?;
+    let mut oauth = spotify_oauth(&user_config);
+    let token_info = match rspotify::util::get_token(&mut oauth).await {
+        Some(token_info) => token_info,
+        None => {
+            eprintln!("failed to get an access token");
+            return Ok(cli::EXIT_FAILURE);
+        }
+    };
     let (tx, rx) = crossbeam::channel::unbounded();
-    let spotify = SpotifyService::new(token_info, oauth).api_result_tx(tx.clone());
-    let api_event_tx = spotify.api_event_tx.clone();
-    let mut spoterm = SpotermClient::new(rx.clone(), api_event_tx.clone());
-
+    let spotify = SpotifyService::new(token_info.clone(), oauth.clone()).api_result_tx(tx.clone());
+    let spoterm = SpotermClient::new(rx, spotify.api_event_tx.clone(), &user_config)
+        .library_cache(LibraryCache::new(cache_dir().join("library.json")));
+    let spoterm = start_scrobble_service_if_configured(&user_config, spoterm).await?;
+    let request_tx = spotify.api_request_tx.clone();
     spotify.run().await?;
+    let cli = cli::Cli::new(SpotifyService::new(token_info, oauth))
+        .status_format(user_config.status.format.clone());
+
+    eprintln!("listening on {}", path.display());
+    Daemon::new(listener, path, spoterm, request_tx, cli)
+        .run()
+        .await?;
+    Ok(cli::EXIT_OK)
+}
+
+//runs a subcommand such as `spoterm pause` without the terminal UI
+async fn run_cli(
+    args: &[String],
+    command: cli::Command,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    if command == cli::Command::Help {
+        println!("{}", cli::USAGE);
+        return Ok(cli::EXIT_OK);
+    }
+    init_spoterm_config_if_needed()?;
+    if command == cli::Command::Daemon {
+        return run_daemon().await;
+    }
+    //a running daemon already has a session
+    if let Ok(mut daemon) = DaemonClient::connect() {
+        return Ok(daemon.run_cli(args, &command)?);
+    }
+    let user_config = read_user_config()?;
+    let mut oauth = spotify_oauth(&user_config);
+    let token_info = match rspotify::util::get_token(&mut oauth).await {
+        Some(token_info) => token_info,
+        None => {
+            eprintln!("failed to get an access token");
+            return Ok(cli::EXIT_FAILURE);
+        }
+    };
+    let mut cli = cli::Cli::new(SpotifyService::new(token_info, oauth))
+        .status_format(user_config.status.format.clone());
+    Ok(cli.run(command).await)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut record_path = None;
+    let mut replay_path = None;
+    match cli::parse_args(&args) {
+        Ok(Some(cli::Command::Record(path))) => record_path = Some(path),
+        Ok(Some(cli::Command::Replay(path))) => replay_path = Some(path),
+        Ok(Some(command)) => std::process::exit(run_cli(&args, command).await?),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}\n\n{}", e.message, cli::USAGE);
+            std::process::exit(e.exit_code);
+        }
+    }
+
+    init_spoterm_config_if_needed()?;
+    init_logger()?;
+
+    let user_config = read_user_config()?;
+
+    //attach to a running daemon, which polls, records the history and scrobbles for us
+    let daemon = match replay_path {
+        Some(_) => None,
+        None => DaemonClient::connect().and_then(|x| x.attach()).ok(),
+    };
+    let attached = daemon.is_some();
+    let spoterm = match (replay_path.as_ref(), daemon) {
+        //nothing is written while replaying
+        (Some(path), _) => {
+            let (api_event_tx, rx) = record::replay(path)?;
+            SpotermClient::new(rx, api_event_tx, &user_config).read_only()
+        }
+        (None, Some((api_event_tx, rx))) => {
+            let (api_event_tx, rx) = match record_path.as_ref() {
+                Some(path) => record::record(path, api_event_tx, rx)?,
+                None => (api_event_tx, rx),
+            };
+            SpotermClient::new(rx, api_event_tx, &user_config)
+        }
+        (None, None) => {
+            let mut oauth = spotify_oauth(&user_config);
+            let token_info = rspotify::util::get_token(&mut oauth).await.unwrap();
+
+            let (tx, rx) = crossbeam::channel::unbounded();
+            let spotify = SpotifyService::new(token_info, oauth).api_result_tx(tx.clone());
+            let (api_event_tx, rx) = match record_path.as_ref() {
+                Some(path) => record::record(path, spotify.api_event_tx.clone(), rx)?,
+                None => (spotify.api_event_tx.clone(), rx),
+            };
+            let spoterm = SpotermClient::new(rx, api_event_tx, &user_config)
+                .library_cache(LibraryCache::new(cache_dir().join("library.json")));
+
+            spotify.run().await?;
+            start_scrobble_service_if_configured(&user_config, spoterm).await?
+        }
+    };
+    //a replay does not touch the network, so it has no album art
+    let mut spoterm = match replay_path {
+        Some(_) => spoterm,
+        None => start_cover_service(&user_config, spoterm).await?,
+    };
 
     spoterm.request_device();
     spoterm.request_current_user_recently_played();
@@ -149,144 +304,144 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.hide_cursor()?;
 
+    #[cfg(feature = "mpris")]
+    let mut mpris = match spoterm::mpris::Mpris::new() {
+        Ok(mpris) => Some(mpris),
+        Err(e) => {
+            log::error!("failed to start MPRIS: {}", e);
+            None
+        }
+    };
+
     let event_handler = event::EventHandler::new();
+    let mut graphics = Graphics::new(spoterm.covers.protocol);
     loop {
+        #[cfg(feature = "mpris")]
+        {
+            if let Some(mpris) = mpris.as_mut() {
+                mpris.sync(&mut spoterm);
+            }
+        }
         let content_ui = &mut spoterm.contents.uis[spoterm.selected_menu_tab_id];
         content_ui.set_data(&spoterm.spotify_data);
         content_ui.set_filter(spoterm.contents.filter.clone());
-        if spoterm.contents.input_mode {
-            match event_handler.next()? {
-                event::Event::KeyInput(key) => match key {
-                    Key::Char('\n') | Key::Esc => {
-                        spoterm.contents.input_mode = false;
+        match event_handler.next()? {
+            event::Event::KeyInput(key) => {
+                if spoterm.help.active {
+                    spoterm.help.key(key, terminal.size()?);
+                } else if spoterm.messages.active {
+                    spoterm.messages.key(key, terminal.size()?);
+                } else if spoterm.details.active {
+                    spoterm.details.key(key);
+                } else if spoterm.command_line.active {
+                    match key {
+                        Key::Esc => {
+                            spoterm.command_line.close();
+                        }
+                        Key::Char('\n') => {
+                            let line = spoterm.command_line.submit();
+                            match parse_command(&line) {
+                                Ok(Action::Quit) => break,
+                                Ok(action) => {
+                                    if let Err(e) = spoterm.dispatch(action) {
+                                        spoterm.messages.warn(e);
+                                    }
+                                }
+                                Err(e) if !e.is_empty() => {
+                                    spoterm.messages.warn(e);
+                                }
+                                Err(_) => {}
+                            }
+                        }
+                        Key::Char('\t') => {
+                            let arguments =
+                                spoterm.command_arguments(spoterm.command_line.command_name());
+                            spoterm.command_line.complete(arguments);
+                        }
+                        Key::Char(c) => {
+                            spoterm.command_line.push(c);
+                        }
+                        Key::Backspace => {
+                            if spoterm.command_line.input.is_empty() {
+                                spoterm.command_line.close();
+                            } else {
+                                spoterm.command_line.pop();
+                            }
+                        }
+                        Key::Up => {
+                            spoterm.command_line.history_previous();
+                        }
+                        Key::Down => {
+                            spoterm.command_line.history_next();
+                        }
+                        _ => {}
                     }
-                    Key::Char(c) => {
-                        spoterm.contents.filter.push(c);
+                } else if spoterm.contents.input_mode {
+                    match key {
+                        Key::Char('\n') | Key::Esc => {
+                            spoterm.contents.input_mode = false;
+                        }
+                        Key::Char(c) => {
+                            spoterm.contents.filter.push(c);
+                        }
+                        Key::Backspace => {
+                            spoterm.contents.filter.pop();
+                        }
+                        _ => {}
                     }
-                    Key::Backspace => {
-                        spoterm.contents.filter.pop();
+                } else {
+                    match spoterm.key_prefix.action(key) {
+                        Some(Action::Quit) => break,
+                        Some(action) => {
+                            if let Err(e) = spoterm.dispatch(action) {
+                                spoterm.messages.warn(e);
+                            }
+                        }
+                        None => {}
                     }
-                    _ => {}
-                },
-                event::Event::Tick => {
-                    spoterm.fetch_api_result();
-                    spoterm.set_selected_device()?;
                 }
-                event::Event::APIUpdate => {
-                    spoterm.request_device();
-                    spoterm.request_current_playback();
-                    spoterm.request_current_user_recently_played();
-                    spoterm.request_current_user_saved_tracks();
-                    spoterm.request_check_unknown_saved_tracks();
-                } // _ => {}
             }
-        } else {
-            match event_handler.next()? {
-                event::Event::KeyInput(key) => match key {
-                    Key::Char('q') => {
-                        break;
-                    }
-                    Key::Char('p') | Key::Char(' ') => {
-                        spoterm.pause();
-                        spoterm.request_current_playback();
-                    }
-                    Key::Char('/') => {
-                        spoterm.contents.input_mode = true;
-                    }
-                    Key::Down | Key::Char('j') => {
-                        content_ui.key_down();
-                    }
-                    Key::Up | Key::Char('k') => {
-                        content_ui.key_up();
-                    }
-                    Key::Char('f') => {
-                        spoterm.request_save_current_playback();
-                    }
-                    Key::Char('+') => {
-                        spoterm.request_volume(true);
-                    }
-                    Key::Char('-') => {
-                        spoterm.request_volume(false);
-                    }
-                    Key::Char('S') => {
-                        spoterm.shuffle();
-                        spoterm.request_current_playback();
-                    }
-                    Key::Char('r') => {
-                        spoterm.request_repeat();
-                    }
-                    Key::Char('>') => {
-                        spoterm.request_next_track();
-                        spoterm.request_current_playback();
-                    }
-                    Key::Char('<') => {
-                        spoterm.request_seek_to_zero_or_previous_track();
-                        spoterm.request_current_playback();
-                    }
-                    Key::Char('\n') => {
-                        content_ui.key_enter();
-                    }
-                    Key::Right | Key::Char('l') => {
-                        spoterm.move_to_next_menu_tab();
-                    }
-                    Key::Left | Key::Char('h') => {
-                        spoterm.move_to_previous_menu_tab();
-                    }
-                    _ => {}
-                },
-                event::Event::Tick => {
-                    spoterm.fetch_api_result();
-                    spoterm.set_selected_device()?;
+            //the same layout as the last frame, unless the terminal was resized since
+            event::Event::Mouse(mouse) => {
+                if let Some(layout) = spoterm.layout(terminal.size()?) {
+                    spoterm.mouse(mouse, &layout);
                 }
-                event::Event::APIUpdate => {
+            }
+            event::Event::Tick => {
+                spoterm.fetch_api_result();
+                spoterm.check_ab_loop();
+                spoterm.set_selected_device()?;
+            }
+            event::Event::APIUpdate => {
+                if !attached {
                     spoterm.request_device();
                     spoterm.request_current_playback();
                     spoterm.request_current_user_recently_played();
                     spoterm.request_current_user_saved_tracks();
-                    spoterm.request_check_unknown_saved_tracks();
-                } // _ => {}
+                }
+                spoterm.request_check_unknown_saved_tracks();
+            } // _ => {}
+        }
+        //kitty and sixel images are drawn after the frame, and not over the popups
+        let size = terminal.size()?;
+        let cover = spoterm.playing_cover();
+        let popup = spoterm.help.active || spoterm.messages.active || spoterm.details.active;
+        let cover_area = spoterm.layout(size).and_then(|x| x.cover);
+        let graphics_cover = match (cover.as_ref(), cover_area) {
+            (Some((album_id, image)), Some(area)) if !popup => {
+                Some((album_id.as_str(), image.as_ref(), area))
             }
+            _ => None,
+        };
+        if graphics.must_clear(graphics_cover.map(|x| x.2), size) {
+            terminal.resize(size)?;
         }
-        let filter = spoterm.contents.filter.clone();
         terminal.draw(|mut f| {
             let size = f.size();
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(5)
-                .constraints(
-                    [
-                        Constraint::Length(3),
-                        Constraint::Length(5),
-                        Constraint::Length(3),
-                        Constraint::Length(3),
-                    ]
-                    .as_ref(),
-                )
-                .split(size);
-
-            Tabs::default()
-                .block(Block::default().borders(Borders::ALL).title("Menu"))
-                .titles(&spoterm.menu_tabs)
-                .select(spoterm.selected_menu_tab_id)
-                .style(Style::default().fg(Color::Cyan))
-                .highlight_style(Style::default().fg(Color::Red))
-                .render(&mut f, chunks[0]);
-            List::new(spoterm.player_items().into_iter())
-                .block(Block::default().borders(Borders::ALL).title("Player"))
-                .render(&mut f, chunks[1]);
-
-            let filter_title = if spoterm.contents.input_mode {
-                "Filter(Entering.... Quit: Enter)"
-            } else {
-                "Filter(Filter Mode: /)"
-            };
-            Paragraph::new([Text::raw(filter)].iter())
-                .style(Style::default().fg(Color::White))
-                .block(Block::default().borders(Borders::ALL).title(filter_title))
-                .render(&mut f, chunks[2]);
-
-            spoterm.contents.uis[spoterm.selected_menu_tab_id].render(&mut f, chunks[3]);
+            f.render(&mut Screen(&mut spoterm), size);
         })?;
+        graphics.show(terminal.backend_mut(), graphics_cover, size)?;
     }
+    spoterm.flush_history();
     Ok(())
 }
@@ -0,0 +1,206 @@
+extern crate chrono;
+
+use crate::layout;
+use crate::layout::Clear;
+
+use self::chrono::{DateTime, Local};
+use std::cmp;
+use std::time::{Duration, Instant};
+use termion::event::Key;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Borders, List, Text, Widget};
+
+//older messages are dropped from the log
+const LOG_LIMIT: usize = 200;
+//how much of the terminal the log covers
+const LOG_PERCENT: u16 = 70;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+    fn color(self) -> Color {
+        match self {
+            Level::Info => Color::Green,
+            Level::Warn => Color::Yellow,
+            Level::Error => Color::Red,
+        }
+    }
+    //how long the toast stays in the status line
+    fn toast_duration(self) -> Duration {
+        match self {
+            Level::Info => Duration::from_secs(3),
+            Level::Warn => Duration::from_secs(5),
+            Level::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub level: Level,
+    pub text: String,
+    pub at: DateTime<Local>,
+    //the same message again is counted instead of added (e.g. a failing poll)
+    pub repeated: usize,
+    shown_at: Instant,
+}
+
+impl Message {
+    pub fn line(&self) -> String {
+        let repeated = match self.repeated {
+            0 => String::new(),
+            n => format!(" (×{})", n + 1),
+        };
+        format!("{}{}", self.text, repeated)
+    }
+}
+
+/// Feedback of actions and API requests: the newest message is a toast in the status line
+/// for a while, and all of them are kept in the log opened with `m`.
+#[derive(Clone, Debug, Default)]
+pub struct Messages {
+    pub log: Vec<Message>,
+    pub active: bool,
+    pub scroll: usize,
+}
+
+impl Messages {
+    pub fn push(&mut self, level: Level, text: String) {
+        match level {
+            Level::Info => log::info!("{}", text),
+            Level::Warn => log::warn!("{}", text),
+            Level::Error => log::error!("{}", text),
+        }
+        if let Some(last) = self.log.last_mut() {
+            if last.level == level && last.text == text {
+                last.repeated += 1;
+                last.at = Local::now();
+                last.shown_at = Instant::now();
+                return;
+            }
+        }
+        self.log.push(Message {
+            level,
+            text,
+            at: Local::now(),
+            repeated: 0,
+            shown_at: Instant::now(),
+        });
+        if self.log.len() > LOG_LIMIT {
+            self.log.remove(0);
+        }
+    }
+    pub fn info<T: Into<String>>(&mut self, text: T) {
+        self.push(Level::Info, text.into());
+    }
+    pub fn warn<T: Into<String>>(&mut self, text: T) {
+        self.push(Level::Warn, text.into());
+    }
+    pub fn error<T: Into<String>>(&mut self, text: T) {
+        self.push(Level::Error, text.into());
+    }
+    //the newest message while it is shown
+    pub fn toast(&self) -> Option<&Message> {
+        self.log
+            .last()
+            .filter(|x| x.shown_at.elapsed() < x.level.toast_duration())
+    }
+    /// The status line: the toast, or how to open the log.
+    pub fn status_text(&self) -> Text<'static> {
+        match self.toast() {
+            Some(message) => Text::styled(
+                format!(" {}: {}", message.level.name(), message.line()),
+                Style::default().fg(message.level.color()),
+            ),
+            None => Text::styled(
+                format!(" Messages: m ({})", self.log.len()),
+                Style::default().fg(Color::DarkGray),
+            ),
+        }
+    }
+    pub fn open(&mut self) {
+        self.active = true;
+        self.scroll = 0;
+    }
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+    fn area(size: Rect) -> Rect {
+        layout::popup(size, LOG_PERCENT)
+    }
+    //the messages shown inside the borders
+    fn height(size: Rect) -> usize {
+        Messages::area(size).height.saturating_sub(2) as usize
+    }
+    /// Handles `key` while the log is open; `size` is the size of the terminal.
+    pub fn key(&mut self, key: Key, size: Rect) {
+        let page = cmp::max(Messages::height(size), 1);
+        let last = self.log.len().saturating_sub(page);
+        self.scroll = match key {
+            Key::Esc | Key::Char('q') | Key::Char('m') => {
+                self.close();
+                return;
+            }
+            Key::Down | Key::Char('j') => self.scroll + 1,
+            Key::Up | Key::Char('k') => self.scroll.saturating_sub(1),
+            Key::PageDown | Key::Ctrl('f') | Key::Char(' ') => self.scroll + page,
+            Key::PageUp | Key::Ctrl('b') => self.scroll.saturating_sub(page),
+            Key::Home | Key::Char('g') => 0,
+            Key::End | Key::Char('G') => last,
+            _ => self.scroll,
+        };
+        self.scroll = cmp::min(self.scroll, last);
+    }
+    //newest first
+    pub fn render(&self, buf: &mut Buffer, size: Rect) {
+        let area = Messages::area(size);
+        Clear.draw(area, buf);
+        let items: Vec<Text> = self
+            .log
+            .iter()
+            .rev()
+            .skip(self.scroll)
+            .map(|message| {
+                Text::styled(
+                    format!(
+                        "{} {:<5} {}",
+                        message.at.format("%H:%M:%S"),
+                        message.level.name(),
+                        message.line()
+                    ),
+                    Style::default().fg(message.level.color()),
+                )
+            })
+            .collect();
+        let items = if items.is_empty() {
+            vec![Text::styled(
+                "no messages yet",
+                Style::default().fg(Color::DarkGray),
+            )]
+        } else {
+            items
+        };
+        List::new(items.into_iter())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Messages (newest first; j k: scroll, Esc: close)")
+                    .title_style(Style::default().modifier(Modifier::BOLD)),
+            )
+            .draw(area, buf);
+    }
+}
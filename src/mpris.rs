@@ -0,0 +1,437 @@
+extern crate crossbeam;
+extern crate rspotify;
+extern crate zbus;
+
+use self::rspotify::model::PlayingItem;
+use self::rspotify::senum::RepeatState;
+use self::zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use self::zbus::{dbus_interface, SignalContext};
+use crate::spoterm::{SpotermClient, SpotifyData};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.spoterm";
+//position jumps larger than this between updates are reported with the Seeked signal
+const SEEK_TOLERANCE_US: i64 = 2_000_000;
+
+pub enum MprisCommand {
+    PlayPause,
+    Play,
+    Pause,
+    Next,
+    Previous,
+    //offset in microseconds
+    Seek(i64),
+    //track id, position in microseconds
+    SetPosition(String, i64),
+    Volume(f64),
+    Shuffle(bool),
+    LoopStatus(RepeatState),
+    OpenUri(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct TrackMetadata {
+    track_id: String,
+    title: String,
+    artists: Vec<String>,
+    album: String,
+    album_artists: Vec<String>,
+    length_us: i64,
+    art_url: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct PlayerState {
+    playback_status: String,
+    loop_status: String,
+    shuffle: bool,
+    volume: f64,
+    position_us: i64,
+    metadata: Option<TrackMetadata>,
+}
+
+impl Default for PlayerState {
+    fn default() -> Self {
+        PlayerState {
+            playback_status: "Stopped".to_string(),
+            loop_status: "None".to_string(),
+            shuffle: false,
+            volume: 0.0,
+            position_us: 0,
+            metadata: None,
+        }
+    }
+}
+
+impl PlayerState {
+    fn from_spotify_data(data: &SpotifyData) -> PlayerState {
+        let current_playback = match data.current_playback.as_ref() {
+            Some(current_playback) => current_playback,
+            None => return PlayerState::default(),
+        };
+        let metadata = match current_playback.item.as_ref() {
+            Some(PlayingItem::Track(track)) => Some(TrackMetadata {
+                //object paths only allow [A-Za-z0-9_]
+                track_id: format!(
+                    "{}/Track/{}",
+                    OBJECT_PATH,
+                    track.id.clone().unwrap_or_else(|| "local".to_string())
+                ),
+                title: track.name.clone(),
+                artists: track.artists.iter().map(|x| x.name.clone()).collect(),
+                album: track.album.name.clone(),
+                album_artists: track.album.artists.iter().map(|x| x.name.clone()).collect(),
+                length_us: track.duration_ms as i64 * 1000,
+                art_url: track.album.images.first().map(|x| x.url.clone()),
+                url: track.external_urls.get("spotify").cloned(),
+            }),
+            _ => None,
+        };
+        let playback_status = if metadata.is_none() {
+            "Stopped"
+        } else if current_playback.is_playing {
+            "Playing"
+        } else {
+            "Paused"
+        };
+        let loop_status = match current_playback.repeat_state {
+            RepeatState::Off => "None",
+            RepeatState::Track => "Track",
+            RepeatState::Context => "Playlist",
+        };
+        PlayerState {
+            playback_status: playback_status.to_string(),
+            loop_status: loop_status.to_string(),
+            shuffle: current_playback.shuffle_state,
+            volume: current_playback.device.volume_percent as f64 / 100.0,
+            position_us: data.current_progress_ms().unwrap_or(0) as i64 * 1000,
+            metadata,
+        }
+    }
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut map = HashMap::new();
+        let metadata = match self.metadata.as_ref() {
+            Some(metadata) => metadata,
+            None => {
+                map.insert(
+                    "mpris:trackid".to_string(),
+                    Value::from(
+                        ObjectPath::try_from("/org/mpris/MediaPlayer2/TrackList/NoTrack").unwrap(),
+                    )
+                    .into(),
+                );
+                return map;
+            }
+        };
+        if let Ok(track_id) = ObjectPath::try_from(metadata.track_id.as_str()) {
+            map.insert("mpris:trackid".to_string(), Value::from(track_id).into());
+        }
+        map.insert(
+            "mpris:length".to_string(),
+            Value::from(metadata.length_us).into(),
+        );
+        map.insert(
+            "xesam:title".to_string(),
+            Value::from(metadata.title.clone()).into(),
+        );
+        map.insert(
+            "xesam:artist".to_string(),
+            Value::from(metadata.artists.clone()).into(),
+        );
+        map.insert(
+            "xesam:album".to_string(),
+            Value::from(metadata.album.clone()).into(),
+        );
+        map.insert(
+            "xesam:albumArtist".to_string(),
+            Value::from(metadata.album_artists.clone()).into(),
+        );
+        if let Some(art_url) = metadata.art_url.as_ref() {
+            map.insert(
+                "mpris:artUrl".to_string(),
+                Value::from(art_url.clone()).into(),
+            );
+        }
+        if let Some(url) = metadata.url.as_ref() {
+            map.insert("xesam:url".to_string(), Value::from(url.clone()).into());
+        }
+        map
+    }
+}
+
+struct MediaPlayer2;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer2 {
+    fn raise(&self) {}
+    fn quit(&self) {}
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+    #[dbus_interface(property)]
+    fn identity(&self) -> String {
+        "spoterm".to_string()
+    }
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec!["spotify".to_string()]
+    }
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+struct Player {
+    tx: crossbeam::channel::Sender<MprisCommand>,
+    state: PlayerState,
+}
+
+impl Player {
+    fn send(&self, command: MprisCommand) {
+        if self.tx.send(command).is_err() {
+            log::error!("spoterm is not receiving MPRIS commands");
+        }
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.send(MprisCommand::Next);
+    }
+    fn previous(&self) {
+        self.send(MprisCommand::Previous);
+    }
+    fn pause(&self) {
+        self.send(MprisCommand::Pause);
+    }
+    fn play_pause(&self) {
+        self.send(MprisCommand::PlayPause);
+    }
+    fn stop(&self) {
+        self.send(MprisCommand::Pause);
+    }
+    fn play(&self) {
+        self.send(MprisCommand::Play);
+    }
+    fn seek(&self, offset: i64) {
+        self.send(MprisCommand::Seek(offset));
+    }
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        self.send(MprisCommand::SetPosition(track_id.to_string(), position));
+    }
+    fn open_uri(&self, uri: String) {
+        self.send(MprisCommand::OpenUri(uri));
+    }
+    #[dbus_interface(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> String {
+        self.state.playback_status.clone()
+    }
+    #[dbus_interface(property)]
+    fn loop_status(&self) -> String {
+        self.state.loop_status.clone()
+    }
+    #[dbus_interface(property)]
+    fn set_loop_status(&mut self, loop_status: String) {
+        let state = match loop_status.as_str() {
+            "Track" => RepeatState::Track,
+            "Playlist" => RepeatState::Context,
+            _ => RepeatState::Off,
+        };
+        self.send(MprisCommand::LoopStatus(state));
+    }
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+    #[dbus_interface(property)]
+    fn set_rate(&mut self, _rate: f64) {}
+    #[dbus_interface(property)]
+    fn shuffle(&self) -> bool {
+        self.state.shuffle
+    }
+    #[dbus_interface(property)]
+    fn set_shuffle(&mut self, shuffle: bool) {
+        self.send(MprisCommand::Shuffle(shuffle));
+    }
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        self.state.metadata()
+    }
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        self.state.volume
+    }
+    #[dbus_interface(property)]
+    fn set_volume(&mut self, volume: f64) {
+        self.send(MprisCommand::Volume(volume));
+    }
+    //not signalled on change, clients poll it
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        self.state.position_us
+    }
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        self.state.metadata.is_some()
+    }
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// `org.mpris.MediaPlayer2.Player` on the session bus (`$DBUS_SESSION_BUS_ADDRESS`).
+pub struct Mpris {
+    connection: zbus::blocking::Connection,
+    rx: crossbeam::channel::Receiver<MprisCommand>,
+    state: PlayerState,
+}
+
+impl Mpris {
+    pub fn new() -> Result<Mpris, failure::Error> {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let player = Player {
+            tx,
+            state: PlayerState::default(),
+        };
+        let connection = zbus::blocking::ConnectionBuilder::session()?
+            .serve_at(OBJECT_PATH, MediaPlayer2)?
+            .serve_at(OBJECT_PATH, player)?
+            .build()?;
+        //another spoterm may already own the name
+        if connection.request_name(BUS_NAME).is_err() {
+            connection.request_name(format!("{}.instance{}", BUS_NAME, std::process::id()))?;
+        }
+        Ok(Mpris {
+            connection,
+            rx,
+            state: PlayerState::default(),
+        })
+    }
+    //apply the commands received over D-Bus and publish the current playback
+    pub fn sync(&mut self, spoterm: &mut SpotermClient) {
+        for command in self.rx.try_iter() {
+            Mpris::perform(command, spoterm);
+        }
+        if let Err(e) = self.publish(&spoterm.spotify_data) {
+            log::error!("failed to update MPRIS properties: {}", e);
+        }
+    }
+    fn perform(command: MprisCommand, spoterm: &mut SpotermClient) {
+        let is_playing = spoterm
+            .spotify_data
+            .current_playback
+            .as_ref()
+            .is_some_and(|x| x.is_playing);
+        match command {
+            MprisCommand::PlayPause => spoterm.pause(),
+            MprisCommand::Play if !is_playing => spoterm.pause(),
+            MprisCommand::Pause if is_playing => spoterm.pause(),
+            MprisCommand::Play | MprisCommand::Pause => {}
+            MprisCommand::Next => spoterm.request_next_track(),
+            MprisCommand::Previous => spoterm.request_previous_track(),
+            MprisCommand::Seek(offset_us) => {
+                let progress_ms = spoterm.spotify_data.current_progress_ms().unwrap_or(0) as i64;
+                spoterm.request_seek(std::cmp::max(progress_ms + offset_us / 1000, 0) as u32);
+            }
+            MprisCommand::SetPosition(track_id, position_us) => {
+                //ignored unless it is about the current track (as the spec says)
+                let current_track_id = spoterm
+                    .spotify_data
+                    .current_playing_track_id()
+                    .map(|x| format!("{}/Track/{}", OBJECT_PATH, x));
+                if current_track_id.as_ref() == Some(&track_id) && position_us >= 0 {
+                    spoterm.request_seek((position_us / 1000) as u32);
+                }
+            }
+            MprisCommand::Volume(volume) => {
+                let volume = volume.clamp(0.0, 1.0);
+                spoterm.request_volume_percent((volume * 100.0).round() as u8);
+            }
+            MprisCommand::Shuffle(shuffle) => spoterm.request_shuffle(shuffle),
+            MprisCommand::LoopStatus(state) => spoterm.request_repeat_state(state),
+            MprisCommand::OpenUri(uri) => spoterm.request_start_playback_uri(uri),
+        }
+        spoterm.request_current_playback();
+    }
+    fn publish(&mut self, data: &SpotifyData) -> Result<(), failure::Error> {
+        let state = PlayerState::from_spotify_data(data);
+        if state == self.state {
+            return Ok(());
+        }
+        let iface_ref = self
+            .connection
+            .object_server()
+            .interface::<_, Player>(OBJECT_PATH)?;
+        let mut player = iface_ref.get_mut();
+        let ctxt = iface_ref.signal_context();
+        let previous = std::mem::replace(&mut player.state, state.clone());
+        zbus::block_on(async {
+            if previous.playback_status != state.playback_status {
+                player.playback_status_changed(ctxt).await?;
+            }
+            if previous.loop_status != state.loop_status {
+                player.loop_status_changed(ctxt).await?;
+            }
+            if previous.shuffle != state.shuffle {
+                player.shuffle_changed(ctxt).await?;
+            }
+            if previous.volume != state.volume {
+                player.volume_changed(ctxt).await?;
+            }
+            if previous.metadata != state.metadata {
+                player.metadata_changed(ctxt).await?;
+                player.can_seek_changed(ctxt).await?;
+            } else {
+                //progress only moves a little between two ticks; anything else is a seek
+                if (state.position_us - previous.position_us).abs() > SEEK_TOLERANCE_US {
+                    Player::seeked(ctxt, state.position_us).await?;
+                }
+            }
+            Ok::<(), zbus::Error>(())
+        })?;
+        self.state = state;
+        Ok(())
+    }
+}
@@ -0,0 +1,1067 @@
+extern crate chrono;
+
+use crate::export::ExportTrack;
+use crate::fuzzy::{FuzzyFilter, FuzzyMatch};
+use crate::history::HistoryEntry;
+use crate::import::ImportItem;
+use crate::snapshot::SnapshotTrack;
+use crate::table;
+
+use self::chrono::{DateTime, Datelike, Local, NaiveDate, Utc};
+use rspotify::model::playing::PlayHistory;
+use rspotify::model::track::{FullTrack, SavedTrack};
+use std::cell::RefCell;
+use std::fmt;
+use std::num::{IntErrorKind, ParseIntError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tui::style::{Color, Style};
+use tui::widgets::Text;
+
+/// The syntax of the filter, for the help.
+pub const SYNTAX: [(&str, &str); 8] = [
+    ("words", "fuzzy match of track, artists or album"),
+    ("\"a phrase\"", "track, artists or album contain the phrase"),
+    (
+        "artist:radiohead",
+        "a field contains the text (artist, album, track)",
+    ),
+    (
+        "year:<2000",
+        "compare a field (year, duration, popularity, added) with < <= > >= =",
+    ),
+    (
+        "dur:>5m added:2020-05",
+        "durations as 3:30, 5m or 90s; dates as YYYY[-MM[-DD]]",
+    ),
+    ("-album:live", "rows not matching the term"),
+    ("a OR b, a | b", "rows matching either term"),
+    ("(a OR b) c", "groups terms"),
+];
+
+/// A field of a track a query can name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Artist,
+    Album,
+    Track,
+    Year,
+    Duration,
+    Popularity,
+    Added,
+}
+
+impl Field {
+    pub fn parse(name: &str) -> Option<Field> {
+        match name.to_lowercase().as_str() {
+            "artist" | "artists" | "ar" => Some(Field::Artist),
+            "album" | "al" => Some(Field::Album),
+            "track" | "title" | "name" => Some(Field::Track),
+            "year" | "y" => Some(Field::Year),
+            "duration" | "dur" => Some(Field::Duration),
+            "popularity" | "pop" => Some(Field::Popularity),
+            "added" | "date" => Some(Field::Added),
+            _ => None,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Artist => "artist",
+            Field::Album => "album",
+            Field::Track => "track",
+            Field::Year => "year",
+            Field::Duration => "duration",
+            Field::Popularity => "popularity",
+            Field::Added => "added",
+        }
+    }
+    fn is_text(self) -> bool {
+        matches!(self, Field::Artist | Field::Album | Field::Track)
+    }
+}
+
+/// What a query is evaluated against.
+pub trait Queryable {
+    //the fields bare words are matched against, e.g. the track, artists and album
+    fn filter_fields(&self) -> Vec<String>;
+    //the values of a text field; empty when unknown
+    fn text(&self, field: Field) -> Vec<String>;
+    //the year, the duration in seconds, the popularity or the day added (from the common
+    //era); None when unknown
+    fn number(&self, field: Field) -> Option<i64>;
+}
+
+impl<T: Queryable + ?Sized> Queryable for &T {
+    fn filter_fields(&self) -> Vec<String> {
+        (**self).filter_fields()
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        (**self).text(field)
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        (**self).number(field)
+    }
+}
+
+fn day(date_time: &DateTime<Utc>) -> i64 {
+    date_time
+        .with_timezone(&Local)
+        .date_naive()
+        .num_days_from_ce() as i64
+}
+
+impl Queryable for FullTrack {
+    fn filter_fields(&self) -> Vec<String> {
+        table::filter_fields(self)
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        match field {
+            Field::Artist => self.artists.iter().map(|x| x.name.clone()).collect(),
+            Field::Album => vec![self.album.name.clone()],
+            Field::Track => vec![self.name.clone()],
+            _ => vec![],
+        }
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        match field {
+            //"1997-05-21", "1997-05" or "1997"
+            Field::Year => self
+                .album
+                .release_date
+                .as_ref()
+                .and_then(|x| x.get(..4))
+                .and_then(|x| x.parse().ok()),
+            Field::Duration => Some((self.duration_ms / 1000) as i64),
+            Field::Popularity => Some(self.popularity as i64),
+            _ => None,
+        }
+    }
+}
+
+impl Queryable for SavedTrack {
+    fn filter_fields(&self) -> Vec<String> {
+        self.track.filter_fields()
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        self.track.text(field)
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        match field {
+            Field::Added => Some(day(&self.added_at)),
+            field => self.track.number(field),
+        }
+    }
+}
+
+//a played track has no album; it was added when played
+impl Queryable for PlayHistory {
+    fn filter_fields(&self) -> Vec<String> {
+        vec![self.track.name.clone(), self.text(Field::Artist).join(", ")]
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        match field {
+            Field::Artist => self.track.artists.iter().map(|x| x.name.clone()).collect(),
+            Field::Track => vec![self.track.name.clone()],
+            _ => vec![],
+        }
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        match field {
+            Field::Duration => Some((self.track.duration_ms / 1000) as i64),
+            Field::Added => Some(day(&self.played_at)),
+            _ => None,
+        }
+    }
+}
+
+impl Queryable for HistoryEntry {
+    fn filter_fields(&self) -> Vec<String> {
+        vec![
+            self.track_name.clone(),
+            self.artists.join(", "),
+            self.album.clone(),
+        ]
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        match field {
+            Field::Artist => self.artists.clone(),
+            Field::Album => vec![self.album.clone()],
+            Field::Track => vec![self.track_name.clone()],
+            _ => vec![],
+        }
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        match field {
+            Field::Duration => Some((self.duration_ms / 1000) as i64),
+            Field::Added => Some(day(&self.started_at)),
+            _ => None,
+        }
+    }
+}
+
+impl Queryable for SnapshotTrack {
+    fn filter_fields(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.artists.join(", "),
+            self.album.clone(),
+        ]
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        match field {
+            Field::Artist => self.artists.clone(),
+            Field::Album => vec![self.album.clone()],
+            Field::Track => vec![self.name.clone()],
+            _ => vec![],
+        }
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        match field {
+            Field::Added => Some(day(&self.added_at)),
+            _ => None,
+        }
+    }
+}
+
+//the entry and its selected match
+impl Queryable for ImportItem {
+    fn filter_fields(&self) -> Vec<String> {
+        let entry = if self.entry.artist.is_empty() {
+            self.entry.title.clone()
+        } else {
+            format!("{} - {}", self.entry.artist, self.entry.title)
+        };
+        let found = match self.selected_candidate() {
+            Some((track, _)) => {
+                format!("{} — {}", track.name, track.text(Field::Artist).join(", "))
+            }
+            None => String::new(),
+        };
+        vec![entry, found]
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        let mut texts = match field {
+            Field::Artist if !self.entry.artist.is_empty() => vec![self.entry.artist.clone()],
+            Field::Track => vec![self.entry.title.clone()],
+            _ => vec![],
+        };
+        if let Some((track, _)) = self.selected_candidate() {
+            texts.extend(track.text(field));
+        }
+        texts
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        match self.selected_candidate() {
+            Some((track, _)) => track.number(field),
+            None if field == Field::Duration => self.entry.duration_ms.map(|x| (x / 1000) as i64),
+            None => None,
+        }
+    }
+}
+
+impl Queryable for ExportTrack {
+    fn filter_fields(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.artists.join(", "),
+            self.album.clone().unwrap_or_default(),
+        ]
+    }
+    fn text(&self, field: Field) -> Vec<String> {
+        match field {
+            Field::Artist => self.artists.clone(),
+            Field::Album => self.album.iter().cloned().collect(),
+            Field::Track => vec![self.name.clone()],
+            _ => vec![],
+        }
+    }
+    fn number(&self, field: Field) -> Option<i64> {
+        match field {
+            Field::Duration => Some((self.duration_ms / 1000) as i64),
+            Field::Popularity => self.popularity.map(|x| x as i64),
+            Field::Added => self.added_at.as_ref().map(day),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    //a value is a range, e.g. the days of `2020-05` or the seconds of `3:30`
+    fn test(self, value: i64, start: i64, end: i64) -> bool {
+        match self {
+            Op::Eq => start <= value && value < end,
+            Op::Lt => value < start,
+            Op::Le => value < end,
+            Op::Gt => value >= end,
+            Op::Ge => value >= start,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    //a bare word, matched fuzzily
+    Fuzzy(String),
+    Phrase(String),
+    Text(Field, String),
+    //the value is in [start, end) for Op::Eq
+    Range(Field, Op, i64, i64),
+}
+
+fn contains(texts: &[String], text: &str) -> bool {
+    let text = text.to_lowercase();
+    texts.iter().any(|x| x.to_lowercase().contains(&text))
+}
+
+impl Expr {
+    fn matches(&self, target: &dyn Queryable) -> bool {
+        match self {
+            Expr::And(exprs) => exprs.iter().all(|x| x.matches(target)),
+            Expr::Or(exprs) => exprs.iter().any(|x| x.matches(target)),
+            Expr::Not(expr) => !expr.matches(target),
+            Expr::Fuzzy(word) => {
+                let fields = target.filter_fields();
+                let fields: Vec<&str> = fields.iter().map(|x| x.as_str()).collect();
+                FuzzyFilter::new(word).matches(&fields).is_some()
+            }
+            Expr::Phrase(phrase) => contains(&target.filter_fields(), phrase),
+            Expr::Text(field, text) => contains(&target.text(*field), text),
+            Expr::Range(field, op, start, end) => target
+                .number(*field)
+                .is_some_and(|x| op.test(x, *start, *end)),
+        }
+    }
+}
+
+/// A syntax error at a character of the query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new<T: Into<String>>(position: usize, message: T) -> QueryError {
+        QueryError {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Or,
+    Not,
+    Term(Expr),
+}
+
+fn is_word_end(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == '"'
+}
+
+//the text up to the closing quote of the one at `start`, and the position after it
+fn read_phrase(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    match chars[start + 1..].iter().position(|x| *x == '"') {
+        Some(len) => Ok((
+            chars[start + 1..start + 1 + len].iter().collect(),
+            start + len + 2,
+        )),
+        None => Err(QueryError::new(start, "unterminated quote")),
+    }
+}
+
+//why the value of a condition was not a number
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NumberError {
+    Invalid,
+    TooLarge,
+}
+
+fn parse_int(text: &str) -> Result<i64, NumberError> {
+    text.parse().map_err(|e: ParseIntError| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => NumberError::TooLarge,
+        _ => NumberError::Invalid,
+    })
+}
+
+fn parse_number(field: Field, text: &str) -> Result<(i64, i64), NumberError> {
+    let value = match field {
+        Field::Year | Field::Popularity => parse_int(text)?,
+        Field::Duration => parse_duration(text)?,
+        Field::Added => return parse_date(text).ok_or(NumberError::Invalid),
+        _ => return Err(NumberError::Invalid),
+    };
+    Ok((value, value.checked_add(1).ok_or(NumberError::TooLarge)?))
+}
+
+//seconds of `3:30`, `5m`, `1m30s`, `90s` or `90`
+fn parse_duration(text: &str) -> Result<i64, NumberError> {
+    if let Some((minutes, seconds)) = text.split_once(':') {
+        let seconds = parse_int(seconds)?;
+        if !(0..60).contains(&seconds) {
+            return Err(NumberError::Invalid);
+        }
+        return parse_int(minutes)?
+            .checked_mul(60)
+            .and_then(|x| x.checked_add(seconds))
+            .ok_or(NumberError::TooLarge);
+    }
+    match parse_int(text) {
+        Err(NumberError::Invalid) => {}
+        seconds => return seconds,
+    }
+    let mut total: i64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' | 's' if !number.is_empty() => {
+                let unit = match c {
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                total = parse_int(&number)?
+                    .checked_mul(unit)
+                    .and_then(|x| total.checked_add(x))
+                    .ok_or(NumberError::TooLarge)?;
+                number.clear();
+            }
+            _ => return Err(NumberError::Invalid),
+        }
+    }
+    if number.is_empty() {
+        Ok(total)
+    } else {
+        Err(NumberError::Invalid)
+    }
+}
+
+//the days of `2020`, `2020-05` or `2020-05-17`
+fn parse_date(text: &str) -> Option<(i64, i64)> {
+    let parts: Vec<u32> = text
+        .split('-')
+        .map(|x| x.parse().ok())
+        .collect::<Option<_>>()?;
+    let (start, end) = match parts.as_slice() {
+        [year] => (
+            NaiveDate::from_ymd_opt(*year as i32, 1, 1)?,
+            NaiveDate::from_ymd_opt(*year as i32 + 1, 1, 1)?,
+        ),
+        [year, month] => {
+            let start = NaiveDate::from_ymd_opt(*year as i32, *month, 1)?;
+            let end = if *month == 12 {
+                NaiveDate::from_ymd_opt(*year as i32 + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(*year as i32, month + 1, 1)?
+            };
+            (start, end)
+        }
+        [year, month, day] => {
+            let start = NaiveDate::from_ymd_opt(*year as i32, *month, *day)?;
+            (start, start.succ_opt()?)
+        }
+        _ => return None,
+    };
+    Some((
+        start.num_days_from_ce() as i64,
+        end.num_days_from_ce() as i64,
+    ))
+}
+
+//`value` of `field:value`, which starts at `position`
+fn parse_condition(field: Field, value: &str, position: usize) -> Result<Expr, QueryError> {
+    if field.is_text() {
+        return Ok(Expr::Text(field, value.to_string()));
+    }
+    let (op, number) = if let Some(x) = value.strip_prefix("<=") {
+        (Op::Le, x)
+    } else if let Some(x) = value.strip_prefix(">=") {
+        (Op::Ge, x)
+    } else if let Some(x) = value.strip_prefix('<') {
+        (Op::Lt, x)
+    } else if let Some(x) = value.strip_prefix('>') {
+        (Op::Gt, x)
+    } else if let Some(x) = value.strip_prefix('=') {
+        (Op::Eq, x)
+    } else {
+        (Op::Eq, value)
+    };
+    let number_position = position + value.chars().count() - number.chars().count();
+    let expected = match field {
+        Field::Duration => "a duration as 3:30, 5m or 90s",
+        Field::Added => "a date as YYYY, YYYY-MM or YYYY-MM-DD",
+        _ => "a number",
+    };
+    if number.is_empty() {
+        return Err(QueryError::new(
+            number_position,
+            format!("missing {} after {}:{}", expected, field.name(), value),
+        ));
+    }
+    match parse_number(field, number) {
+        Ok((start, end)) => Ok(Expr::Range(field, op, start, end)),
+        Err(NumberError::Invalid) => Err(QueryError::new(
+            number_position,
+            format!("expected {}, not {}", expected, number),
+        )),
+        Err(NumberError::TooLarge) => Err(QueryError::new(
+            number_position,
+            format!("{} is too large for {}", number, field.name()),
+        )),
+    }
+}
+
+fn tokenize(chars: &[char]) -> Result<Vec<(usize, Token)>, QueryError> {
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let token = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                Token::Open
+            }
+            ')' => {
+                i += 1;
+                Token::Close
+            }
+            '|' => {
+                i += 1;
+                Token::Or
+            }
+            '-' => {
+                i += 1;
+                Token::Not
+            }
+            '"' => {
+                let (phrase, next) = read_phrase(chars, i)?;
+                i = next;
+                Token::Term(Expr::Phrase(phrase))
+            }
+            _ => {
+                while i < chars.len() && !is_word_end(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.find(':') {
+                    _ if word == "OR" => Token::Or,
+                    Some(index) => {
+                        let name = &word[..index];
+                        let field = Field::parse(name).ok_or_else(|| {
+                            QueryError::new(
+                                start,
+                                format!(
+                                    "unknown field {} (artist, album, track, year, duration, popularity, added)",
+                                    name
+                                ),
+                            )
+                        })?;
+                        let value_position = start + name.chars().count() + 1;
+                        let mut value = word[index + 1..].to_string();
+                        if value.is_empty() && i < chars.len() && chars[i] == '"' {
+                            let (phrase, next) = read_phrase(chars, i)?;
+                            value = phrase;
+                            i = next;
+                        }
+                        if value.trim().is_empty() {
+                            return Err(QueryError::new(
+                                value_position,
+                                format!("missing value after {}:", name),
+                            ));
+                        }
+                        Token::Term(parse_condition(field, &value, value_position)?)
+                    }
+                    None => Token::Term(Expr::Fuzzy(word)),
+                }
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    //the position of the end of the query
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, x)| x)
+    }
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(x, _)| *x)
+    }
+    //terms separated by OR
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut exprs = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+    //terms up to OR, ) or the end
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut exprs = vec![];
+        while let Some(token) = self.peek() {
+            if *token == Token::Or || *token == Token::Close {
+                break;
+            }
+            exprs.push(self.unary()?);
+        }
+        match exprs.len() {
+            0 => Err(QueryError::new(self.position(), "missing a term")),
+            1 => Ok(exprs.remove(0)),
+            _ => Ok(Expr::And(exprs)),
+        }
+    }
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        let position = self.position();
+        let token = match self.tokens.get(self.next) {
+            Some((_, token)) => token.clone(),
+            None => return Err(QueryError::new(position, "missing a term")),
+        };
+        self.next += 1;
+        match token {
+            Token::Not => Ok(Expr::Not(Box::new(self.unary()?))),
+            Token::Open => {
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(QueryError::new(self.position(), "missing )"));
+                }
+                self.next += 1;
+                Ok(expr)
+            }
+            Token::Term(expr) => Ok(expr),
+            Token::Close | Token::Or => Err(QueryError::new(position, "missing a term")),
+        }
+    }
+}
+
+/// The filter of the views: bare words rank the rows by a fuzzy match as FuzzyFilter does,
+/// and the other terms (`artist:radiohead year:<2000 -album:live`) keep or drop rows.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    expr: Option<Expr>,
+    pub fuzzy: FuzzyFilter,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let chars: Vec<char> = text.chars().collect();
+        let tokens = tokenize(&chars)?;
+        if tokens.is_empty() {
+            return Ok(Query::default());
+        }
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: chars.len(),
+        };
+        let expr = parser.or()?;
+        if parser.next < parser.tokens.len() {
+            return Err(QueryError::new(parser.position(), "unmatched )"));
+        }
+        //the bare words outside groups rank and highlight the rows
+        let exprs = match expr {
+            Expr::And(exprs) => exprs,
+            expr => vec![expr],
+        };
+        let (words, mut exprs): (Vec<Expr>, Vec<Expr>) =
+            exprs.into_iter().partition(|x| matches!(x, Expr::Fuzzy(_)));
+        let words: Vec<String> = words
+            .into_iter()
+            .filter_map(|x| match x {
+                Expr::Fuzzy(word) => Some(word),
+                _ => None,
+            })
+            .collect();
+        let expr = match exprs.len() {
+            0 => None,
+            1 => exprs.pop(),
+            _ => Some(Expr::And(exprs)),
+        };
+        Ok(Query {
+            expr,
+            fuzzy: FuzzyFilter::new(&words.join(" ")),
+        })
+    }
+    /// The query of the filter box; an invalid one keeps every row while its error is shown.
+    pub fn new(text: &str) -> Query {
+        Query::parse(text).unwrap_or_default()
+    }
+    pub fn matches(&self, target: &dyn Queryable) -> bool {
+        self.matches_terms(target) && {
+            let fields = target.filter_fields();
+            let fields: Vec<&str> = fields.iter().map(|x| x.as_str()).collect();
+            self.fuzzy.is_empty() || self.fuzzy.matches(&fields).is_some()
+        }
+    }
+    //the terms other than the ranking words
+    pub fn matches_terms(&self, target: &dyn Queryable) -> bool {
+        self.expr.as_ref().is_none_or(|x| x.matches(target))
+    }
+    /// The indexes of the rows matching the query, ranked as FuzzyFilter::rank does.
+    pub fn rank<T: Queryable>(&self, rows: &[T]) -> Vec<(usize, FuzzyMatch)> {
+        let indexes = (0..rows.len())
+            .filter(|x| self.matches_terms(&rows[*x]))
+            .collect();
+        self.fuzzy.rank(indexes, |x| rows[*x].filter_fields())
+    }
+}
+
+static REVISION: AtomicU64 = AtomicU64::new(1);
+
+/// A revision no rows had before, so that rows replaced by others (e.g. a new import
+/// session) are never taken for them.
+pub fn new_revision() -> u64 {
+    REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+//the filter and the revision ranked for, and the indexes of the matching rows
+type Ranked = (String, u64, Vec<(usize, FuzzyMatch)>);
+
+/// The ranked rows of a view, kept while neither the filter nor the revision of the rows
+/// change, as the views ask for them on every key and frame.
+#[derive(Clone, Debug, Default)]
+pub struct RankCache {
+    ranked: RefCell<Option<Ranked>>,
+}
+
+impl RankCache {
+    /// The indexes of the rows matching `filter`, best first; `rows` is called only when
+    /// they are ranked again.
+    pub fn rank<T, F>(&self, filter: &str, revision: u64, rows: F) -> Vec<(usize, FuzzyMatch)>
+    where
+        T: Queryable,
+        F: FnOnce() -> Vec<T>,
+    {
+        let mut ranked = self.ranked.borrow_mut();
+        match ranked.as_ref() {
+            Some((cached_filter, cached_revision, rows))
+                if cached_filter == filter && *cached_revision == revision =>
+            {
+                rows.clone()
+            }
+            _ => {
+                let rows = Query::new(filter).rank(&rows());
+                *ranked = Some((filter.to_string(), revision, rows.clone()));
+                rows
+            }
+        }
+    }
+}
+
+/// The filter as shown in its box: an error is marked from where it was found.
+pub fn filter_text(filter: &str) -> Vec<Text<'static>> {
+    match Query::parse(filter) {
+        Ok(_) => vec![Text::raw(filter.to_string())],
+        Err(e) => {
+            let error = Style::default().fg(Color::Red);
+            let (valid, invalid): (String, String) = (
+                filter.chars().take(e.position).collect(),
+                filter.chars().skip(e.position).collect(),
+            );
+            vec![
+                Text::raw(valid),
+                Text::styled(invalid, error),
+                Text::styled(format!("  ✗ {}", e), error),
+            ]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    //a track with only the numbers the conditions compare
+    struct Numbers {
+        year: i64,
+        duration: i64,
+    }
+
+    impl Queryable for Numbers {
+        fn filter_fields(&self) -> Vec<String> {
+            vec![]
+        }
+        fn text(&self, _field: Field) -> Vec<String> {
+            vec![]
+        }
+        fn number(&self, field: Field) -> Option<i64> {
+            match field {
+                Field::Year => Some(self.year),
+                Field::Duration => Some(self.duration),
+                _ => None,
+            }
+        }
+    }
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn expr(query: &str) -> Option<Expr> {
+        Query::parse(query).unwrap().expr
+    }
+
+    fn fuzzy(word: &str) -> Expr {
+        Expr::Fuzzy(word.to_string())
+    }
+
+    fn text(field: Field, text: &str) -> Expr {
+        Expr::Text(field, text.to_string())
+    }
+
+    fn error(query: &str) -> QueryError {
+        Query::parse(query).unwrap_err()
+    }
+
+    fn days(year: i32, month: u32, day: u32) -> i64 {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .num_days_from_ce() as i64
+    }
+
+    #[test]
+    fn tokenizes_groups_operators_phrases_and_conditions() {
+        let tokens = tokenize(&chars("(a | -b) OR \"x y\" artist:\"Sigur Rós\"")).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, Token::Open),
+                (1, Token::Term(fuzzy("a"))),
+                (3, Token::Or),
+                (5, Token::Not),
+                (6, Token::Term(fuzzy("b"))),
+                (7, Token::Close),
+                (9, Token::Or),
+                (12, Token::Term(Expr::Phrase("x y".to_string()))),
+                (18, Token::Term(text(Field::Artist, "Sigur Rós"))),
+            ]
+        );
+        assert_eq!(
+            tokenize(&chars("a \"b")).unwrap_err(),
+            QueryError::new(2, "unterminated quote")
+        );
+        assert_eq!(tokenize(&chars("a year:")).unwrap_err().position, 7);
+        assert_eq!(tokenize(&chars("a genre:rock")).unwrap_err().position, 2);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            expr("artist:a album:b | track:c"),
+            Some(Expr::Or(vec![
+                Expr::And(vec![text(Field::Artist, "a"), text(Field::Album, "b")]),
+                text(Field::Track, "c"),
+            ]))
+        );
+        assert_eq!(
+            expr("artist:a (album:b OR track:c)"),
+            Some(Expr::And(vec![
+                text(Field::Artist, "a"),
+                Expr::Or(vec![text(Field::Album, "b"), text(Field::Track, "c")]),
+            ]))
+        );
+    }
+
+    #[test]
+    fn negates_terms_and_groups() {
+        assert_eq!(
+            expr("-album:live -(a | b)"),
+            Some(Expr::And(vec![
+                Expr::Not(Box::new(text(Field::Album, "live"))),
+                Expr::Not(Box::new(Expr::Or(vec![fuzzy("a"), fuzzy("b")]))),
+            ]))
+        );
+    }
+
+    #[test]
+    fn bare_words_outside_groups_rank_the_rows() {
+        let query = Query::parse("karma artist:radiohead police").unwrap();
+        assert_eq!(query.expr, Some(text(Field::Artist, "radiohead")));
+        assert_eq!(query.fuzzy, FuzzyFilter::new("karma police"));
+        let query = Query::parse("karma | police").unwrap();
+        assert!(query.fuzzy.is_empty());
+    }
+
+    #[test]
+    fn reports_where_the_query_is_incomplete() {
+        assert_eq!(error("(a b"), QueryError::new(4, "missing )"));
+        assert_eq!(error("a) b"), QueryError::new(1, "unmatched )"));
+        assert_eq!(error("a |"), QueryError::new(3, "missing a term"));
+        assert_eq!(error("| a"), QueryError::new(0, "missing a term"));
+        assert_eq!(error("a -"), QueryError::new(3, "missing a term"));
+    }
+
+    #[test]
+    fn parses_ranges_of_numbers_durations_and_dates() {
+        assert_eq!(
+            expr("year:<2000"),
+            Some(Expr::Range(Field::Year, Op::Lt, 2000, 2001))
+        );
+        assert_eq!(
+            expr("dur:>=3:30"),
+            Some(Expr::Range(Field::Duration, Op::Ge, 210, 211))
+        );
+        assert_eq!(
+            expr("dur:1h1m30s"),
+            Some(Expr::Range(Field::Duration, Op::Eq, 3690, 3691))
+        );
+        assert_eq!(
+            expr("added:2020-12"),
+            Some(Expr::Range(
+                Field::Added,
+                Op::Eq,
+                days(2020, 12, 1),
+                days(2021, 1, 1)
+            ))
+        );
+        assert_eq!(
+            error("dur:3:75"),
+            QueryError::new(4, "expected a duration as 3:30, 5m or 90s, not 3:75")
+        );
+        assert_eq!(
+            error("year:<"),
+            QueryError::new(6, "missing a number after year:<")
+        );
+    }
+
+    #[test]
+    fn numbers_too_large_are_errors() {
+        assert_eq!(
+            error("year:9223372036854775807"),
+            QueryError::new(5, "9223372036854775807 is too large for year")
+        );
+        assert_eq!(
+            error("dur:>153722867280912931m"),
+            QueryError::new(5, "153722867280912931m is too large for duration")
+        );
+        assert_eq!(
+            error("dur:99999999999999999999"),
+            QueryError::new(4, "99999999999999999999 is too large for duration")
+        );
+        assert_eq!(
+            error("dur:153722867280912931:00"),
+            QueryError::new(4, "153722867280912931:00 is too large for duration")
+        );
+    }
+
+    #[test]
+    fn compares_the_numbers_of_a_row() {
+        let row = |year, duration| Numbers { year, duration };
+        let query = Query::new("year:<2000");
+        assert!(query.matches(&row(1999, 0)));
+        assert!(!query.matches(&row(2000, 0)));
+        let query = Query::new("year:>1999 dur:<=3:30");
+        assert!(query.matches(&row(2000, 210)));
+        assert!(!query.matches(&row(2000, 211)));
+        assert!(!query.matches(&row(1999, 210)));
+        let query = Query::new("year:1997 | -dur:<60");
+        assert!(query.matches(&row(1997, 30)));
+        assert!(query.matches(&row(2001, 60)));
+        assert!(!query.matches(&row(2001, 59)));
+    }
+
+    #[test]
+    fn marks_the_invalid_part_of_the_filter() {
+        let parts = |filter: &str| -> Vec<(String, bool)> {
+            filter_text(filter)
+                .into_iter()
+                .map(|x| match x {
+                    Text::Raw(text) => (text.to_string(), false),
+                    Text::Styled(text, style) => (text.to_string(), style.fg == Color::Red),
+                })
+                .collect()
+        };
+        assert_eq!(parts("artist:a b"), vec![("artist:a b".to_string(), false)]);
+        assert_eq!(
+            parts("a (b"),
+            vec![
+                ("a (b".to_string(), false),
+                (String::new(), true),
+                ("  ✗ missing ) at column 5".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            parts("a year:x"),
+            vec![
+                ("a year:".to_string(), false),
+                ("x".to_string(), true),
+                ("  ✗ expected a number, not x at column 8".to_string(), true),
+            ]
+        );
+    }
+
+    fn track(name: &str, artist: &str, album: &str) -> SnapshotTrack {
+        SnapshotTrack {
+            id: name.to_string(),
+            name: name.to_string(),
+            artists: vec![artist.to_string()],
+            album: album.to_string(),
+            added_at: Utc::now(),
+            available: true,
+        }
+    }
+
+    #[test]
+    fn ranks_again_only_when_the_filter_or_the_revision_changes() {
+        let tracks = [
+            track("Airbag", "Radiohead", "OK Computer"),
+            track("Karma Police", "Radiohead", "OK Computer"),
+        ];
+        let cache = RankCache::default();
+        let ranked = Cell::new(0);
+        let rank = |filter: &str, revision: u64| -> Vec<usize> {
+            let rows = || {
+                ranked.set(ranked.get() + 1);
+                tracks.iter().collect()
+            };
+            cache
+                .rank(filter, revision, rows)
+                .into_iter()
+                .map(|(x, _)| x)
+                .collect()
+        };
+        assert_eq!(rank("karma", 1), vec![1]);
+        assert_eq!(rank("karma", 1), vec![1]);
+        assert_eq!(ranked.get(), 1);
+        assert_eq!(rank("air", 1), vec![0]);
+        assert_eq!(rank("air", 2), vec![0]);
+        assert_eq!(ranked.get(), 3);
+    }
+}
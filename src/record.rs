@@ -0,0 +1,249 @@
+extern crate chrono;
+extern crate crossbeam;
+extern crate serde_json;
+
+use crate::spotify::{SpotifyAPIEvent, SpotifyAPIResult};
+
+use self::chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A line of a recording: an event the client sent or a result it received.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordEntry {
+    pub at: DateTime<Utc>,
+    //since the recording started
+    pub elapsed_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<SpotifyAPIEvent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<SpotifyAPIResult>,
+}
+
+struct Recorder {
+    file: LineWriter<fs::File>,
+    started_at: Instant,
+}
+
+impl Recorder {
+    fn write(&mut self, entry: serde_json::Value) {
+        if let Err(e) = writeln!(self.file, "{}", entry) {
+            log::error!("failed to record: {}", e);
+        }
+    }
+    fn entry(&self, name: &str, value: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "at": Utc::now(),
+            "elapsed_ms": self.started_at.elapsed().as_millis() as u64,
+            name: value,
+        })
+    }
+}
+
+/// Writes everything passing between `SpotermClient` and the API to `path` (JSON Lines);
+/// the returned channels are used in place of `tx` and `rx`.
+pub fn record(
+    path: &Path,
+    tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
+    rx: crossbeam::channel::Receiver<SpotifyAPIResult>,
+) -> std::io::Result<(
+    crossbeam::channel::Sender<SpotifyAPIEvent>,
+    crossbeam::channel::Receiver<SpotifyAPIResult>,
+)> {
+    let recorder = Arc::new(Mutex::new(Recorder {
+        file: LineWriter::new(fs::File::create(path)?),
+        started_at: Instant::now(),
+    }));
+    let (event_tx, event_rx) = crossbeam::channel::unbounded::<SpotifyAPIEvent>();
+    let (result_tx, result_rx) = crossbeam::channel::unbounded();
+    {
+        let recorder = recorder.clone();
+        thread::spawn(move || {
+            for event in event_rx {
+                if let Ok(value) = serde_json::to_value(&event) {
+                    let mut recorder = recorder.lock().unwrap();
+                    let entry = recorder.entry("event", value);
+                    recorder.write(entry);
+                }
+                if tx.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    thread::spawn(move || {
+        for result in rx {
+            if let Ok(value) = serde_json::to_value(&result) {
+                let mut recorder = recorder.lock().unwrap();
+                let entry = recorder.entry("result", value);
+                recorder.write(entry);
+            }
+            if result_tx.send(result).is_err() {
+                return;
+            }
+        }
+    });
+    Ok((event_tx, result_rx))
+}
+
+pub fn load(path: &Path) -> Result<Vec<RecordEntry>, failure::Error> {
+    let file = fs::File::open(path)?;
+    let mut entries = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => log::warn!("broken recording line {}: {}", i + 1, e),
+        }
+    }
+    Ok(entries)
+}
+
+/// The time of a replay, in milliseconds since it started.
+pub trait Clock: Send + 'static {
+    fn elapsed_ms(&self) -> u64;
+    //returns once elapsed_ms() has reached `ms`
+    fn wait_until(&self, ms: u64);
+}
+
+pub struct WallClock {
+    started_at: Instant,
+}
+
+impl WallClock {
+    pub fn new() -> WallClock {
+        WallClock {
+            started_at: Instant::now(),
+        }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        WallClock::new()
+    }
+}
+
+impl Clock for WallClock {
+    fn elapsed_ms(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+    fn wait_until(&self, ms: u64) {
+        let at = Duration::from_millis(ms);
+        if let Some(wait) = at.checked_sub(self.started_at.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// A clock moved by hand, e.g. to step through a replay in tests.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    now: Arc<(Mutex<u64>, Condvar)>,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+    pub fn set(&self, ms: u64) {
+        let (now, changed) = &*self.now;
+        *now.lock().unwrap() = ms;
+        changed.notify_all();
+    }
+}
+
+impl Clock for ManualClock {
+    fn elapsed_ms(&self) -> u64 {
+        *self.now.0.lock().unwrap()
+    }
+    fn wait_until(&self, ms: u64) {
+        let (now, changed) = &*self.now;
+        let mut now = now.lock().unwrap();
+        while *now < ms {
+            now = changed.wait(now).unwrap();
+        }
+    }
+}
+
+/// The results of a recording, handed out once the replay reaches their recorded time.
+pub struct Replay {
+    results: VecDeque<(u64, SpotifyAPIResult)>,
+}
+
+impl Replay {
+    pub fn new(entries: Vec<RecordEntry>) -> Replay {
+        Replay {
+            results: entries
+                .into_iter()
+                .filter_map(|x| Some((x.elapsed_ms, x.result?)))
+                .collect(),
+        }
+    }
+    pub fn load(path: &Path) -> Result<Replay, failure::Error> {
+        Ok(Replay::new(load(path)?))
+    }
+    //when the next result was recorded
+    pub fn next_at(&self) -> Option<u64> {
+        self.results.front().map(|x| x.0)
+    }
+    //the results recorded up to `elapsed_ms`, in order
+    pub fn due(&mut self, elapsed_ms: u64) -> Vec<SpotifyAPIResult> {
+        let mut due = vec![];
+        while self.next_at().is_some_and(|x| x <= elapsed_ms) {
+            due.extend(self.results.pop_front().map(|x| x.1));
+        }
+        due
+    }
+}
+
+/// Sends the results of a recording at their recorded times, instead of the API.
+/// The events of the client are only logged.
+pub fn replay(
+    path: &Path,
+) -> Result<
+    (
+        crossbeam::channel::Sender<SpotifyAPIEvent>,
+        crossbeam::channel::Receiver<SpotifyAPIResult>,
+    ),
+    failure::Error,
+> {
+    Ok(replay_with(Replay::load(path)?, WallClock::new()))
+}
+
+/// `replay` timed by `clock`.
+pub fn replay_with<C: Clock>(
+    mut replay: Replay,
+    clock: C,
+) -> (
+    crossbeam::channel::Sender<SpotifyAPIEvent>,
+    crossbeam::channel::Receiver<SpotifyAPIResult>,
+) {
+    let (event_tx, event_rx) = crossbeam::channel::unbounded::<SpotifyAPIEvent>();
+    let (result_tx, result_rx) = crossbeam::channel::unbounded();
+    thread::spawn(move || {
+        for event in event_rx {
+            log::debug!("replay: ignored {:?}", event);
+        }
+    });
+    thread::spawn(move || {
+        while let Some(at) = replay.next_at() {
+            clock.wait_until(at);
+            for result in replay.due(clock.elapsed_ms()) {
+                if result_tx.send(result).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    (event_tx, result_rx)
+}
@@ -0,0 +1,315 @@
+extern crate crossbeam;
+extern crate reqwest;
+extern crate rspotify;
+extern crate serde_json;
+
+use self::rspotify::model::context::CurrentlyPlaybackContext;
+use self::rspotify::model::PlayingItem;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+//ListenBrainz counts a play after half the track or 4 minutes, whichever comes first
+const MAX_LISTEN_THRESHOLD_MS: u32 = 4 * 60 * 1000;
+//a jump back this far while playing the same track is treated as a replay (e.g. repeat one)
+const REPLAY_REWIND_MS: u32 = 5000;
+//how often queued listens are retried when nothing else is submitted
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+//the API accepts at most 1000 listens per request
+const MAX_LISTENS_PER_REQUEST: usize = 1000;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Listen {
+    pub listened_at: Option<u64>,
+    pub artist_name: String,
+    pub track_name: String,
+    pub release_name: Option<String>,
+    pub duration_ms: u32,
+    pub spotify_id: Option<String>,
+}
+
+impl Listen {
+    fn payload(&self) -> serde_json::Value {
+        let mut additional_info = serde_json::json!({
+            "duration_ms": self.duration_ms,
+            "submission_client": "spoterm",
+            "submission_client_version": env!("CARGO_PKG_VERSION"),
+            "music_service": "spotify.com",
+        });
+        if let Some(spotify_id) = self.spotify_id.as_ref() {
+            additional_info["spotify_id"] =
+                serde_json::json!(format!("https://open.spotify.com/track/{}", spotify_id));
+        }
+        let mut track_metadata = serde_json::json!({
+            "artist_name": self.artist_name,
+            "track_name": self.track_name,
+            "additional_info": additional_info,
+        });
+        if let Some(release_name) = self.release_name.as_ref() {
+            track_metadata["release_name"] = serde_json::json!(release_name);
+        }
+        let mut payload = serde_json::json!({ "track_metadata": track_metadata });
+        if let Some(listened_at) = self.listened_at {
+            payload["listened_at"] = serde_json::json!(listened_at);
+        }
+        payload
+    }
+}
+
+enum SubmitError {
+    //the server refused the listens, sending them again would not help
+    Rejected(failure::Error),
+    //a network error, a server error or rate limiting
+    Failed(failure::Error),
+}
+
+impl<T: Into<failure::Error>> From<T> for SubmitError {
+    fn from(e: T) -> SubmitError {
+        SubmitError::Failed(e.into())
+    }
+}
+
+pub enum ScrobbleEvent {
+    PlayingNow(Listen),
+    Listen(Listen),
+}
+
+/// Decides from `CurrentPlayBack` polls when a "playing now" update and a listen should be sent.
+#[derive(Clone, Debug, Default)]
+pub struct ScrobbleDetector {
+    listen: Option<Listen>,
+    played_ms: u32,
+    submitted: bool,
+    last_progress_ms: u32,
+    last_polled_at: Option<Instant>,
+    last_is_playing: bool,
+}
+
+impl ScrobbleDetector {
+    pub fn new() -> ScrobbleDetector {
+        ScrobbleDetector::default()
+    }
+    pub fn update(
+        &mut self,
+        current_playback: Option<&CurrentlyPlaybackContext>,
+    ) -> Vec<ScrobbleEvent> {
+        let now = Instant::now();
+        let mut events = vec![];
+        let (playback, track) = match current_playback.and_then(|x| match x.item.as_ref() {
+            Some(PlayingItem::Track(track)) => Some((x, track)),
+            _ => None,
+        }) {
+            Some(playing) => playing,
+            None => {
+                *self = ScrobbleDetector::default();
+                return events;
+            }
+        };
+        let progress_ms = playback.progress_ms.unwrap_or(0);
+
+        let same_track = match self.listen.as_ref() {
+            Some(listen) => listen.spotify_id == track.id && listen.track_name == track.name,
+            None => false,
+        };
+        let replayed =
+            same_track && self.submitted && progress_ms + REPLAY_REWIND_MS < self.last_progress_ms;
+        if same_track && !replayed {
+            if self.last_is_playing {
+                if let Some(last_polled_at) = self.last_polled_at {
+                    self.played_ms += now.duration_since(last_polled_at).as_millis() as u32;
+                }
+            }
+        } else {
+            let listened_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                .saturating_sub(progress_ms as u64 / 1000);
+            let listen = Listen {
+                listened_at: Some(listened_at),
+                artist_name: track
+                    .artists
+                    .iter()
+                    .map(|x| x.name.clone())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                track_name: track.name.clone(),
+                release_name: Some(track.album.name.clone()),
+                duration_ms: track.duration_ms,
+                spotify_id: track.id.clone(),
+            };
+            if playback.is_playing {
+                events.push(ScrobbleEvent::PlayingNow(Listen {
+                    listened_at: None,
+                    ..listen.clone()
+                }));
+            }
+            self.listen = Some(listen);
+            self.played_ms = 0;
+            self.submitted = false;
+        }
+
+        let threshold_ms = std::cmp::min(track.duration_ms / 2, MAX_LISTEN_THRESHOLD_MS);
+        if !self.submitted && self.played_ms >= threshold_ms {
+            if let Some(listen) = self.listen.as_ref() {
+                events.push(ScrobbleEvent::Listen(listen.clone()));
+                self.submitted = true;
+            }
+        }
+        self.last_progress_ms = progress_ms;
+        self.last_polled_at = Some(now);
+        self.last_is_playing = playback.is_playing;
+        events
+    }
+}
+
+/// Submits listens to a ListenBrainz-compatible server.
+/// Listens that could not be submitted are kept in `queue_path` and retried later,
+/// those the server rejected are moved to `rejected_path`.
+pub struct ScrobbleService {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub token: String,
+    pub queue_path: PathBuf,
+    pub rejected_path: PathBuf,
+    pub scrobble_event_tx: crossbeam::channel::Sender<ScrobbleEvent>,
+    pub scrobble_event_rx: crossbeam::channel::Receiver<ScrobbleEvent>,
+}
+
+impl ScrobbleService {
+    pub fn new(url: String, token: String, queue_path: PathBuf) -> ScrobbleService {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        ScrobbleService {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            rejected_path: queue_path.with_extension("rejected.json"),
+            queue_path,
+            scrobble_event_tx: tx,
+            scrobble_event_rx: rx,
+        }
+    }
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        let rx = self.scrobble_event_rx.clone();
+
+        tokio::spawn(async move {
+            //waiting for a listen blocks, so not on a thread of the runtime
+            loop {
+                let event_rx = rx.clone();
+                let event = match tokio::task::spawn_blocking(move || {
+                    event_rx.recv_timeout(RETRY_INTERVAL)
+                })
+                .await
+                {
+                    Ok(event) => event,
+                    Err(_) => return,
+                };
+                match event {
+                    Ok(ScrobbleEvent::PlayingNow(listen)) => {
+                        //"playing now" is only interesting while it is true, so it is never queued
+                        if let Err(SubmitError::Rejected(e)) | Err(SubmitError::Failed(e)) =
+                            self.submit("playing_now", &[listen]).await
+                        {
+                            log::warn!("failed to submit playing now: {}", e);
+                        }
+                    }
+                    Ok(ScrobbleEvent::Listen(listen)) => {
+                        let mut queue = load_listens(&self.queue_path);
+                        queue.push(listen);
+                        save_listens(&self.queue_path, &queue);
+                        self.flush_queue().await;
+                    }
+                    Err(crossbeam::channel::RecvTimeoutError::Timeout) => {
+                        self.flush_queue().await;
+                    }
+                    Err(crossbeam::channel::RecvTimeoutError::Disconnected) => {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+    async fn submit(&self, listen_type: &str, listens: &[Listen]) -> Result<(), SubmitError> {
+        let body = serde_json::json!({
+            "listen_type": listen_type,
+            "payload": listens.iter().map(|x| x.payload()).collect::<Vec<_>>(),
+        });
+        let response = self
+            .client
+            .post(&format!("{}/1/submit-listens", self.url))
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let e = failure::format_err!("{}: {}", status, text);
+            if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err(SubmitError::Rejected(e));
+            }
+            return Err(SubmitError::Failed(e));
+        }
+        Ok(())
+    }
+    //stops at the first failure, a rejected listen is set aside so it does not block the rest
+    async fn flush_queue(&self) {
+        let mut queue = load_listens(&self.queue_path);
+        //after a rejected batch the listens are sent one at a time to find the rejected ones
+        let mut batch_size = MAX_LISTENS_PER_REQUEST;
+        while !queue.is_empty() {
+            let size = std::cmp::min(queue.len(), batch_size);
+            let listen_type = if size == 1 { "single" } else { "import" };
+            match self.submit(listen_type, &queue[..size]).await {
+                Ok(()) => {}
+                Err(SubmitError::Rejected(e)) if size > 1 => {
+                    log::warn!(
+                        "{} listens were rejected, sending them one at a time: {}",
+                        size,
+                        e
+                    );
+                    batch_size = 1;
+                    continue;
+                }
+                Err(SubmitError::Rejected(e)) => {
+                    log::error!(
+                        "a listen was rejected (moved to {}): {}",
+                        self.rejected_path.display(),
+                        e
+                    );
+                    let mut rejected = load_listens(&self.rejected_path);
+                    rejected.push(queue[0].clone());
+                    save_listens(&self.rejected_path, &rejected);
+                }
+                Err(SubmitError::Failed(e)) => {
+                    log::warn!("failed to submit {} listens (queued): {}", queue.len(), e);
+                    return;
+                }
+            }
+            queue.drain(..size);
+            save_listens(&self.queue_path, &queue);
+        }
+    }
+}
+
+fn load_listens(path: &Path) -> Vec<Listen> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default()
+}
+
+fn save_listens(path: &Path, listens: &[Listen]) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .map_err(failure::Error::from)
+        .and_then(|_| Ok(serde_json::to_string(listens)?))
+        .and_then(|x| Ok(fs::write(path, x)?));
+    if let Err(e) = result {
+        log::error!("failed to save the listens to {}: {}", path.display(), e);
+    }
+}
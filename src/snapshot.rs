@@ -0,0 +1,206 @@
+extern crate chrono;
+extern crate rspotify;
+extern crate serde_json;
+
+use crate::query;
+
+use self::chrono::{DateTime, Local, Utc};
+use self::rspotify::model::track::SavedTrack;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A liked song as it was when the snapshot was taken.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotTrack {
+    pub id: String,
+    pub name: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub added_at: DateTime<Utc>,
+    //false once Spotify lists it without markets (e.g. a licensing removal)
+    pub available: bool,
+}
+
+impl SnapshotTrack {
+    pub fn from_saved_track(saved_track: &SavedTrack) -> Option<SnapshotTrack> {
+        let track = &saved_track.track;
+        Some(SnapshotTrack {
+            id: track.id.clone()?,
+            name: track.name.clone(),
+            artists: track.artists.iter().map(|x| x.name.clone()).collect(),
+            album: track.album.name.clone(),
+            added_at: saved_track.added_at,
+            available: track
+                .is_playable
+                .unwrap_or(!track.available_markets.is_empty())
+                && track.restrictions.is_none(),
+        })
+    }
+}
+
+/// Liked Songs saved once a day as `2020-01-31.json` (the last sync of the day wins).
+#[derive(Clone, Debug)]
+pub struct Snapshots {
+    pub dir: PathBuf,
+    //bumped whenever a snapshot is saved, shared by the clones
+    revision: Arc<AtomicU64>,
+}
+
+impl Snapshots {
+    pub fn new(dir: PathBuf) -> Snapshots {
+        Snapshots {
+            dir,
+            revision: Arc::new(AtomicU64::new(0)),
+        }
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+    pub fn path(&self, date: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", date))
+    }
+    //e.g. ["2020-01-30", "2020-01-31"] (oldest first)
+    pub fn dates(&self) -> Vec<String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries
+            .filter_map(|x| x.ok())
+            .filter_map(|x| {
+                let path = x.path();
+                if path.extension().and_then(|x| x.to_str()) != Some("json") {
+                    return None;
+                }
+                path.file_stem()
+                    .and_then(|x| x.to_str())
+                    .map(|x| x.to_string())
+            })
+            .sorted()
+            .collect()
+    }
+    pub fn today() -> String {
+        Local::now().format("%Y-%m-%d").to_string()
+    }
+    pub fn load(&self, date: &str) -> Result<Vec<SnapshotTrack>, failure::Error> {
+        let content = fs::read_to_string(self.path(date))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+    pub fn save(&self, date: &str, saved_tracks: &[SavedTrack]) -> Result<(), failure::Error> {
+        fs::create_dir_all(&self.dir)?;
+        let tracks: Vec<SnapshotTrack> = saved_tracks
+            .iter()
+            .filter_map(SnapshotTrack::from_saved_track)
+            .collect();
+        fs::write(self.path(date), serde_json::to_string(&tracks)?)?;
+        self.revision.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+    /// The snapshot whose date starts with `date` (e.g. "2020-01"), the latest if several do.
+    pub fn find(&self, date: &str) -> Option<String> {
+        self.dates().into_iter().rev().find(|x| x.starts_with(date))
+    }
+    /// Diffs the snapshots matching `from` and `to`; by default `to` is the latest one and
+    /// `from` the one before it.
+    pub fn diff(&self, from: Option<&str>, to: Option<&str>) -> Result<SnapshotDiff, String> {
+        let latest = from.is_none() && to.is_none();
+        let dates = self.dates();
+        let to = match to {
+            Some(to) => self.find(to).ok_or(format!("no snapshot of {}", to))?,
+            None => dates.last().cloned().ok_or("no snapshots yet")?,
+        };
+        let from = match from {
+            Some(from) => self.find(from).ok_or(format!("no snapshot of {}", from))?,
+            None => dates
+                .iter()
+                .rev()
+                .find(|x| **x < to)
+                .cloned()
+                .ok_or("only one snapshot so far")?,
+        };
+        let mut diff = SnapshotDiff::new(self, &from, &to)
+            .map_err(|e| format!("failed to load the snapshots: {}", e))?;
+        diff.latest = latest;
+        Ok(diff)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change {
+    Removed,
+    Added,
+    Unavailable,
+}
+
+impl Change {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Change::Removed => "-",
+            Change::Added => "+",
+            Change::Unavailable => "!",
+        }
+    }
+}
+
+/// What changed in Liked Songs between the snapshots `from` and `to`.
+#[derive(Clone, Debug)]
+pub struct SnapshotDiff {
+    pub from: String,
+    pub to: String,
+    //removed first, then added and unavailable
+    pub changes: Vec<(Change, SnapshotTrack)>,
+    //the latest two snapshots, compared again when a new one is saved
+    pub latest: bool,
+    //see query::RankCache
+    pub revision: u64,
+}
+
+impl SnapshotDiff {
+    pub fn new(
+        snapshots: &Snapshots,
+        from: &str,
+        to: &str,
+    ) -> Result<SnapshotDiff, failure::Error> {
+        let old = snapshots.load(from)?;
+        let new = snapshots.load(to)?;
+        let new_by_id: HashMap<&str, &SnapshotTrack> =
+            new.iter().map(|x| (x.id.as_str(), x)).collect();
+        let old_by_id: HashMap<&str, &SnapshotTrack> =
+            old.iter().map(|x| (x.id.as_str(), x)).collect();
+        let mut changes = vec![];
+        for track in old.iter() {
+            if !new_by_id.contains_key(track.id.as_str()) {
+                changes.push((Change::Removed, track.clone()));
+            }
+        }
+        for track in new.iter() {
+            match old_by_id.get(track.id.as_str()) {
+                None => changes.push((Change::Added, track.clone())),
+                Some(old_track) if old_track.available && !track.available => {
+                    changes.push((Change::Unavailable, track.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        changes.sort_by_key(|(change, _)| match change {
+            Change::Removed => 0,
+            Change::Added => 1,
+            Change::Unavailable => 2,
+        });
+        Ok(SnapshotDiff {
+            from: from.to_string(),
+            to: to.to_string(),
+            changes,
+            latest: false,
+            revision: query::new_revision(),
+        })
+    }
+    pub fn count(&self, change: Change) -> usize {
+        self.changes.iter().filter(|(x, _)| *x == change).count()
+    }
+}
@@ -2,24 +2,59 @@ extern crate failure;
 extern crate hostname;
 extern crate itertools;
 extern crate rspotify;
+extern crate serde_json;
 extern crate unicode_width;
 
+use crate::cache::LibraryCache;
+use crate::command::{Action, Batch, CommandLine, KeyPrefix};
+use crate::config;
+use crate::config::UserConfig;
+use crate::cover::{Covers, HalfBlocks, Image};
+use crate::details::Details;
+use crate::export;
+use crate::help::Help;
+use crate::history::{HistoryRecorder, ListeningHistory};
+use crate::import;
+use crate::import::ImportSession;
+use crate::layout::AppLayout;
+use crate::message::Messages;
+use crate::query;
+use crate::scrobble::{ScrobbleDetector, ScrobbleEvent};
+use crate::snapshot::{SnapshotDiff, Snapshots};
 use crate::spotify::{SpotifyAPIEvent, SpotifyAPIResult};
-use crate::ui::{Contents, LikedSongs, RecentPlayed};
+use crate::table::TrackTable;
+use crate::ui;
+use crate::ui::{Contents, History, Import, LibraryDiff, LikedSongs, Lyrics, RecentPlayed};
 
+use self::itertools::Itertools;
 use self::rspotify::model::context::CurrentlyPlaybackContext;
-use self::rspotify::model::track::SavedTrack;
+use self::rspotify::model::track::{FullTrack, SavedTrack};
+use self::unicode_width::UnicodeWidthStr;
 use crate::spoterm::SaveState::UNKNOWN;
 
 use rspotify::model::device::Device;
+use rspotify::model::page::Page;
 use rspotify::model::playing::PlayHistory;
 use rspotify::senum::RepeatState;
+use serde::{Deserialize, Serialize};
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use termion::event::{MouseButton, MouseEvent};
+use tui::buffer::Buffer;
+use tui::layout::Rect;
 use tui::style::{Color, Style};
-use tui::widgets::Text;
+use tui::widgets::{Block, Borders, List, Paragraph, Tabs, Text, Widget};
 
-#[derive(Clone, Debug)]
+//in characters, at the column given by SpotermClient::progress_bar_position
+const PROGRESS_BAR_WIDTH: u16 = 20;
+//two clicks on the same cell within this are a double-click
+const DOUBLE_CLICK_MS: u64 = 400;
+//rows moved by a notch of the mouse wheel
+const WHEEL_ROWS: usize = 3;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SaveState {
     SAVED,
     UNSAVED,
@@ -29,7 +64,46 @@ pub enum SaveState {
     UNKNOWN,
 }
 
-#[derive(Clone, Debug)]
+/// A top-level field of `SpotifyData`, each with a revision bumped whenever it changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    Devices,
+    SavedTracks,
+    RecentPlayHistories,
+    CurrentPlayback,
+    SelectedDevice,
+    SaveStateTrackIds,
+    SavedTracksTotal,
+}
+
+impl Field {
+    pub const ALL: [Field; 7] = [
+        Field::Devices,
+        Field::SavedTracks,
+        Field::RecentPlayHistories,
+        Field::CurrentPlayback,
+        Field::SelectedDevice,
+        Field::SaveStateTrackIds,
+        Field::SavedTracksTotal,
+    ];
+    //the name of the field in JSON
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Devices => "devices",
+            Field::SavedTracks => "saved_tracks",
+            Field::RecentPlayHistories => "recent_play_histories",
+            Field::CurrentPlayback => "current_playback",
+            Field::SelectedDevice => "selected_device",
+            Field::SaveStateTrackIds => "save_state_track_ids",
+            Field::SavedTracksTotal => "saved_tracks_total",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL.iter().cloned().find(|x| x.name() == name)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SpotifyData {
     pub devices: Option<Vec<Device>>,
     pub saved_tracks: Vec<SavedTrack>,
@@ -37,6 +111,14 @@ pub struct SpotifyData {
     pub current_playback: Option<CurrentlyPlaybackContext>,
     pub selected_device: Option<Device>,
     pub save_state_track_ids: HashMap<String, SaveState>,
+    //the total of Liked Songs reported by the last synced page
+    pub saved_tracks_total: Option<u32>,
+    //when current_playback was received (for interpolating progress_ms)
+    #[serde(skip)]
+    pub playback_updated_at: Option<Instant>,
+    //see SpotifyData::touch
+    #[serde(skip)]
+    revisions: HashMap<Field, u64>,
 }
 
 impl SpotifyData {
@@ -48,8 +130,120 @@ impl SpotifyData {
             current_playback: None,
             selected_device: None,
             save_state_track_ids: HashMap::new(),
+            saved_tracks_total: None,
+            playback_updated_at: None,
+            revisions: HashMap::new(),
+        }
+    }
+    //to be called after changing a field, so that daemon clients and caches see it changed
+    pub fn touch(&mut self, field: Field) {
+        *self.revisions.entry(field).or_insert(0) += 1;
+    }
+    pub fn revision(&self, field: Field) -> u64 {
+        self.revisions.get(&field).cloned().unwrap_or(0)
+    }
+    pub fn field_value(&self, field: Field) -> serde_json::Value {
+        let value = match field {
+            Field::Devices => serde_json::to_value(&self.devices),
+            Field::SavedTracks => serde_json::to_value(&self.saved_tracks),
+            Field::RecentPlayHistories => serde_json::to_value(&self.recent_play_histories),
+            Field::CurrentPlayback => serde_json::to_value(&self.current_playback),
+            Field::SelectedDevice => serde_json::to_value(&self.selected_device),
+            Field::SaveStateTrackIds => serde_json::to_value(&self.save_state_track_ids),
+            Field::SavedTracksTotal => serde_json::to_value(self.saved_tracks_total),
+        };
+        value.unwrap_or(serde_json::Value::Null)
+    }
+    //top-level fields as JSON, for daemon clients
+    pub fn to_fields(&self) -> serde_json::Map<String, serde_json::Value> {
+        Field::ALL
+            .iter()
+            .map(|x| (x.name().to_string(), self.field_value(*x)))
+            .collect()
+    }
+    pub fn update_fields(
+        &mut self,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<(), serde_json::Error> {
+        for (name, value) in fields {
+            let field = match Field::from_name(&name) {
+                Some(field) => field,
+                None => continue,
+            };
+            match field {
+                Field::Devices => self.devices = serde_json::from_value(value)?,
+                Field::SavedTracks => self.saved_tracks = serde_json::from_value(value)?,
+                Field::RecentPlayHistories => {
+                    self.recent_play_histories = serde_json::from_value(value)?
+                }
+                Field::CurrentPlayback => {
+                    self.current_playback = serde_json::from_value(value)?;
+                    self.playback_updated_at = Some(Instant::now());
+                }
+                Field::SelectedDevice => self.selected_device = serde_json::from_value(value)?,
+                Field::SaveStateTrackIds => {
+                    self.save_state_track_ids = serde_json::from_value(value)?
+                }
+                Field::SavedTracksTotal => self.saved_tracks_total = serde_json::from_value(value)?,
+            }
+            self.touch(field);
+        }
+        Ok(())
+    }
+    pub fn current_playing_track_id(&self) -> Option<String> {
+        if let Some(current_playback) = self.current_playback.as_ref() {
+            if let Some(rspotify::model::PlayingItem::Track(playing_track)) =
+                current_playback.item.as_ref()
+            {
+                return playing_track.id.clone();
+            }
+        }
+        None
+    }
+    //progress_ms is polled once a second, so advance it by the time elapsed since the last poll
+    pub fn current_progress_ms(&self) -> Option<u32> {
+        let current_playback = self.current_playback.as_ref()?;
+        let progress_ms = current_playback.progress_ms?;
+        if !current_playback.is_playing {
+            return Some(progress_ms);
+        }
+        let elapsed_ms = self
+            .playback_updated_at
+            .map(|x| x.elapsed().as_millis() as u32)
+            .unwrap_or(0);
+        let progress_ms = progress_ms + elapsed_ms;
+        if let Some(rspotify::model::PlayingItem::Track(playing_track)) =
+            current_playback.item.as_ref()
+        {
+            return Some(cmp::min(progress_ms, playing_track.duration_ms));
         }
+        Some(progress_ms)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ABLoop {
+    pub track_id: Option<String>,
+    pub a_ms: Option<u32>,
+    pub b_ms: Option<u32>,
+}
+
+impl ABLoop {
+    pub fn is_empty(&self) -> bool {
+        self.a_ms.is_none() && self.b_ms.is_none()
     }
+    pub fn clear(&mut self) {
+        *self = ABLoop::default();
+    }
+}
+
+//a chain of Liked Songs pages, each requested when the previous one arrives
+struct SavedTracksSync {
+    //pages at other offsets are from a chain given up on, and are dropped
+    next_offset: u32,
+    requested_at: Instant,
+    //the pages fetched so far by a full resync
+    full: Option<Vec<SavedTrack>>,
 }
 
 pub struct SpotermClient {
@@ -57,46 +251,218 @@ pub struct SpotermClient {
     pub rx: crossbeam::channel::Receiver<SpotifyAPIResult>,
     //data from api
     pub spotify_data: SpotifyData,
+    pub ab_loop: ABLoop,
+    pub listening_history: ListeningHistory,
+    pub history_recorder: HistoryRecorder,
+    pub scrobble_event_tx: Option<crossbeam::channel::Sender<ScrobbleEvent>>,
+    pub scrobble_detector: ScrobbleDetector,
+    //replies (e.g. search results) are passed on instead of handled, see daemon::Daemon
+    pub relay_tx: Option<crossbeam::channel::Sender<SpotifyAPIResult>>,
+    pub library_cache: Option<LibraryCache>,
+    //nothing is written to the history or snapshots (when replaying a recording)
+    pub read_only: bool,
+    //the paging of Liked Songs in flight, one at a time
+    saved_tracks_sync: Option<SavedTracksSync>,
+    //a snapshot of Liked Songs is saved after each sync that changed it
+    pub snapshots: Snapshots,
+    //the `:diff` shown in the Library Diff view
+    pub snapshot_diff: Arc<Mutex<Option<SnapshotDiff>>>,
+    //the `:import` being reviewed, shared with the Import view
+    pub import: Arc<Mutex<Option<ImportSession>>>,
+    //how many tracks `:import apply` is adding to Liked Songs, and those not confirmed yet
+    import_saving: Option<(usize, HashSet<String>)>,
+    //the last left click and where it was, for noticing a double-click
+    last_click: Option<(Instant, u16, u16)>,
     //data for ui
     pub menu_tabs: Vec<String>,
     pub selected_menu_tab_id: usize,
     pub contents: Contents,
+    //the height of the content view in the last frame, for moving by pages
+    pub content_height: u16,
+    pub command_line: CommandLine,
+    pub key_prefix: KeyPrefix,
+    pub help: Help,
+    //toasts and the message log
+    pub messages: Messages,
+    pub details: Details,
+    pub covers: Covers,
 }
 
 impl SpotermClient {
     pub fn new(
         rx: crossbeam::channel::Receiver<SpotifyAPIResult>,
         api_event_tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
+        user_config: &UserConfig,
     ) -> SpotermClient {
+        let listening_history = ListeningHistory::new(config::data_dir().join("history"));
+        let import = Arc::new(Mutex::new(None));
+        let snapshots = Snapshots::new(config::data_dir().join("snapshots"));
+        let snapshot_diff = Arc::new(Mutex::new(None));
         let contents = Contents::new()
             .ui(RecentPlayed::new(api_event_tx.clone()))
-            .ui(LikedSongs::new(api_event_tx.clone()));
+            .ui(LikedSongs::new(
+                api_event_tx.clone(),
+                TrackTable::new(&user_config.liked_songs),
+            ))
+            .ui(History::new(
+                api_event_tx.clone(),
+                listening_history.clone(),
+            ))
+            .ui(Lyrics::new(user_config.lyrics.dir()))
+            .ui(Import::new(import.clone()))
+            .ui(LibraryDiff::new(
+                api_event_tx.clone(),
+                snapshots.clone(),
+                snapshot_diff.clone(),
+            ));
 
         SpotermClient {
             tx: api_event_tx.clone(),
             rx: rx.clone(),
             spotify_data: SpotifyData::new(),
+            ab_loop: ABLoop::default(),
+            listening_history,
+            history_recorder: HistoryRecorder::new(),
+            scrobble_event_tx: None,
+            scrobble_detector: ScrobbleDetector::new(),
+            relay_tx: None,
+            library_cache: None,
+            read_only: false,
+            saved_tracks_sync: None,
+            snapshots,
+            snapshot_diff,
+            import,
+            import_saving: None,
+            last_click: None,
             menu_tabs: vec![
                 "📝 Recently Played 📝".to_string(),
                 "❤ Liked Songs ❤".to_string(),
+                "🕘 History 🕘".to_string(),
+                "🎤 Lyrics 🎤".to_string(),
+                "📥 Import 📥".to_string(),
+                "🔀 Library Diff 🔀".to_string(),
                 //"Artists".to_string(),
             ],
             selected_menu_tab_id: 0,
             contents,
+            content_height: 0,
+            command_line: CommandLine::new(),
+            key_prefix: KeyPrefix::default(),
+            help: Help::default(),
+            messages: Messages::default(),
+            details: Details::default(),
+            covers: Covers::default(),
+        }
+    }
+
+    pub fn scrobble_event_tx(mut self, tx: crossbeam::channel::Sender<ScrobbleEvent>) -> Self {
+        self.scrobble_event_tx = Some(tx);
+        self
+    }
+    /// Shows the cached library until the first sync, and keeps the cache up to date.
+    pub fn library_cache(mut self, library_cache: LibraryCache) -> Self {
+        match library_cache.load() {
+            Ok(library) => {
+                self.spotify_data.saved_tracks = library.saved_tracks;
+                self.spotify_data.saved_tracks_total = library.saved_tracks_total;
+                self.spotify_data.recent_play_histories = library.recent_play_histories;
+                self.spotify_data.devices = library.devices;
+                for field in &[
+                    Field::SavedTracks,
+                    Field::SavedTracksTotal,
+                    Field::RecentPlayHistories,
+                    Field::Devices,
+                ] {
+                    self.spotify_data.touch(*field);
+                }
+            }
+            Err(e) => log::info!("no library cache in {:?}: {}", library_cache.path, e),
         }
+        self.library_cache = Some(library_cache);
+        self
+    }
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+    pub fn covers(mut self, covers: Covers) -> Self {
+        self.covers = covers;
+        self
+    }
+    pub fn relay_tx(mut self, tx: crossbeam::channel::Sender<SpotifyAPIResult>) -> Self {
+        self.relay_tx = Some(tx);
+        self
     }
 
     pub fn fetch_api_result(&mut self) {
         for result in self.rx.try_recv() {
+            if let Some(tx) = self.relay_tx.as_ref() {
+                if result.is_reply() {
+                    tx.send(result).unwrap();
+                    continue;
+                }
+            }
             match result {
                 SpotifyAPIResult::Device(devices) => {
                     self.spotify_data.devices = Some(devices);
+                    self.spotify_data.touch(Field::Devices);
                 }
                 SpotifyAPIResult::CurrentUserRecentlyPlayed(recent_play_histories) => {
+                    let played_at = |histories: &[PlayHistory]| {
+                        histories.iter().map(|x| x.played_at).collect::<Vec<_>>()
+                    };
+                    let changed = self
+                        .spotify_data
+                        .recent_play_histories
+                        .as_ref()
+                        .map(|x| played_at(x))
+                        != Some(played_at(&recent_play_histories));
                     self.spotify_data.recent_play_histories = Some(recent_play_histories);
+                    if changed {
+                        self.spotify_data.touch(Field::RecentPlayHistories);
+                        self.save_library_cache();
+                    }
                 }
                 SpotifyAPIResult::CurrentPlayBack(current_playback) => {
                     self.spotify_data.current_playback = current_playback;
+                    self.spotify_data.playback_updated_at = Some(Instant::now());
+                    self.spotify_data.touch(Field::CurrentPlayback);
+                    if let Some(entry) = self
+                        .history_recorder
+                        .update(self.spotify_data.current_playback.as_ref())
+                        .filter(|_| !self.read_only)
+                    {
+                        if let Err(e) = self.listening_history.append(&entry) {
+                            self.messages
+                                .error(format!("failed to record listening history: {}", e));
+                        }
+                    }
+                    if let Some(tx) = self.scrobble_event_tx.as_ref() {
+                        for event in self
+                            .scrobble_detector
+                            .update(self.spotify_data.current_playback.as_ref())
+                        {
+                            tx.send(event).unwrap();
+                        }
+                    }
+                    //the loop belongs to the track it was set on
+                    if !self.ab_loop.is_empty()
+                        && self.ab_loop.track_id != self.spotify_data.current_playing_track_id()
+                    {
+                        self.ab_loop.clear();
+                    }
+                }
+                //attached to a daemon, which records the history and scrobbles
+                SpotifyAPIResult::DaemonData(fields) => {
+                    if let Err(e) = self.spotify_data.update_fields(fields) {
+                        self.messages
+                            .error(format!("invalid data from the daemon: {}", e));
+                    }
+                    if !self.ab_loop.is_empty()
+                        && self.ab_loop.track_id != self.spotify_data.current_playing_track_id()
+                    {
+                        self.ab_loop.clear();
+                    }
                 }
                 SpotifyAPIResult::CheckCurrentUserSavedTracks(saved_tracks) => {
                     for (track_id, saved) in saved_tracks.iter() {
@@ -110,16 +476,30 @@ impl SpotermClient {
                                 .insert(track_id.clone(), SaveState::UNSAVED);
                         }
                     }
+                    self.spotify_data.touch(Field::SaveStateTrackIds);
                 }
                 SpotifyAPIResult::SuccessAddCurrentUserSavedTracks(track_ids) => {
+                    self.report_saved(&track_ids);
                     for track_id in track_ids {
                         self.spotify_data
                             .save_state_track_ids
                             .insert(track_id, SaveState::SAVED);
                     }
+                    self.spotify_data.touch(Field::SaveStateTrackIds);
                     self.request_current_user_saved_tracks();
                 }
+                //the import is reported by the failure, and later likes on their own
+                SpotifyAPIResult::FailureAddCurrentUserSavedTracks(track_ids) => {
+                    if let Some((_, saving)) = self.import_saving.as_ref() {
+                        if track_ids.iter().any(|x| saving.contains(x)) {
+                            self.import_saving = None;
+                        }
+                    }
+                }
                 SpotifyAPIResult::SuccessDeleteCurrentUserSavedTracks(track_ids) => {
+                    let tracks = self.tracks_text(&track_ids);
+                    self.messages
+                        .info(format!("Removed {} from Liked Songs", tracks));
                     self.spotify_data.saved_tracks.retain(|x| {
                         let track_id = x.track.id.as_ref().unwrap();
                         if track_ids.contains(track_id) {
@@ -127,46 +507,653 @@ impl SpotermClient {
                         }
                         true
                     });
+                    self.spotify_data.touch(Field::SavedTracks);
                 }
                 SpotifyAPIResult::CurrentUserSavedTracks(page_saved_tracks) => {
-                    let pre_track_ids: Vec<String> = self
-                        .spotify_data
-                        .saved_tracks
-                        .iter()
-                        .map(|x| x.track.id.clone().unwrap())
-                        .collect();
-                    self.spotify_data
-                        .saved_tracks
-                        .append(&mut page_saved_tracks.items.clone());
-                    //unique
-                    self.spotify_data
-                        .saved_tracks
-                        .sort_by_key(|x| x.track.id.clone());
-                    self.spotify_data
-                        .saved_tracks
-                        .dedup_by_key(|x| x.track.id.clone().unwrap_or("".to_string()));
-
-                    self.spotify_data.saved_tracks.sort_by_key(|x| x.added_at);
-                    self.spotify_data.saved_tracks.reverse();
-
-                    let new_track_ids: Vec<String> = self
-                        .spotify_data
-                        .saved_tracks
-                        .iter()
-                        .map(|x| x.track.id.clone().unwrap())
-                        .collect();
-                    if pre_track_ids != new_track_ids && page_saved_tracks.next.is_some() {
-                        log::info!("Request!!");
-                        self.tx
-                            .send(SpotifyAPIEvent::CurrentUserSavedTracks(Some(
-                                page_saved_tracks.offset + page_saved_tracks.limit,
-                            )))
-                            .unwrap();
+                    self.merge_saved_tracks_page(page_saved_tracks);
+                }
+                SpotifyAPIResult::SearchTracks(query, tracks) => {
+                    if let Some(session) = self.import.lock().unwrap().as_mut() {
+                        session.set_search_result(&query, &tracks);
                     }
                 }
+                SpotifyAPIResult::FailureSearchTracks(query) => {
+                    if let Some(session) = self.import.lock().unwrap().as_mut() {
+                        session.set_search_failure(&query);
+                    }
+                }
+                SpotifyAPIResult::SuccessAddTracksToPlaylist(name, count) => {
+                    self.messages
+                        .info(format!("Added {} tracks to {}", count, name));
+                }
+                SpotifyAPIResult::SuccessAddToQueue(count) => {
+                    self.messages
+                        .info(format!("Added {} tracks to the queue", count));
+                }
+                SpotifyAPIResult::Track(track) => self.details.set_track(track),
+                SpotifyAPIResult::FailureTrack(track_id, e) => {
+                    self.details.set_track_error(&track_id, e);
+                }
+                SpotifyAPIResult::AudioFeatures(track_id, features) => {
+                    self.details.set_features(&track_id, Ok(features));
+                }
+                SpotifyAPIResult::FailureAudioFeatures(track_id, e) => {
+                    self.details.set_features(&track_id, Err(e));
+                }
+                SpotifyAPIResult::Failure(message) => self.messages.error(message),
                 _ => {}
             }
         }
+        self.covers.receive();
+    }
+    /// Adds a page of Liked Songs. Paging stops at the first track already known, and the
+    /// whole list is fetched again when the count still disagrees with Spotify's total.
+    fn merge_saved_tracks_page(&mut self, page: Page<SavedTrack>) {
+        let sync = match self.saved_tracks_sync.take() {
+            Some(sync) if sync.next_offset == page.offset => sync,
+            sync => {
+                log::info!("dropped a stale page of Liked Songs at {}", page.offset);
+                self.saved_tracks_sync = sync;
+                return;
+            }
+        };
+        let next_offset = page.offset + page.limit;
+        if let Some(mut fetched) = sync.full {
+            fetched.extend(page.items);
+            if page.next.is_some() {
+                self.request_saved_tracks_page(next_offset, Some(fetched));
+                return;
+            }
+            self.spotify_data.saved_tracks = Self::sorted_saved_tracks(fetched);
+            self.spotify_data.saved_tracks_total = Some(page.total);
+            self.spotify_data.touch(Field::SavedTracks);
+            self.spotify_data.touch(Field::SavedTracksTotal);
+            self.library_synced(true);
+            return;
+        }
+        let newest_added_at = self.spotify_data.saved_tracks.first().map(|x| x.added_at);
+        let reached_known = page
+            .items
+            .iter()
+            .any(|x| Some(x.added_at) <= newest_added_at);
+        let new_tracks: Vec<SavedTrack> = page
+            .items
+            .into_iter()
+            .filter(|x| Some(x.added_at) > newest_added_at)
+            .collect();
+        let changed = !new_tracks.is_empty();
+        if changed {
+            let mut saved_tracks = new_tracks;
+            saved_tracks.append(&mut self.spotify_data.saved_tracks);
+            self.spotify_data.saved_tracks = Self::sorted_saved_tracks(saved_tracks);
+            self.spotify_data.touch(Field::SavedTracks);
+        }
+        if !reached_known && page.next.is_some() {
+            self.request_saved_tracks_page(next_offset, None);
+        } else if self.spotify_data.saved_tracks.len() != page.total as usize {
+            //removed on another device, or the cache is from before a gap
+            log::info!(
+                "{} liked songs cached but {} on Spotify, syncing all",
+                self.spotify_data.saved_tracks.len(),
+                page.total
+            );
+            self.request_saved_tracks_page(0, Some(vec![]));
+        } else {
+            let changed = changed || self.spotify_data.saved_tracks_total != Some(page.total);
+            if self.spotify_data.saved_tracks_total != Some(page.total) {
+                self.spotify_data.saved_tracks_total = Some(page.total);
+                self.spotify_data.touch(Field::SavedTracksTotal);
+            }
+            self.library_synced(changed);
+        }
+    }
+    //Liked Songs is complete and matches Spotify's total
+    fn library_synced(&mut self, changed: bool) {
+        if changed {
+            self.save_library_cache();
+        }
+        let today = Snapshots::today();
+        if !self.read_only && (changed || !self.snapshots.path(&today).exists()) {
+            if let Err(e) = self.snapshots.save(&today, &self.spotify_data.saved_tracks) {
+                self.messages
+                    .error(format!("failed to save the snapshot of {}: {}", today, e));
+            }
+        }
+    }
+    //newest first, without the older entry of a track liked again
+    fn sorted_saved_tracks(saved_tracks: Vec<SavedTrack>) -> Vec<SavedTrack> {
+        saved_tracks
+            .into_iter()
+            .sorted_by(|a, b| b.added_at.cmp(&a.added_at))
+            .unique_by(|x| x.track.id.clone())
+            .collect()
+    }
+    fn request_saved_tracks_page(&mut self, offset: u32, full: Option<Vec<SavedTrack>>) {
+        self.saved_tracks_sync = Some(SavedTracksSync {
+            next_offset: offset,
+            requested_at: Instant::now(),
+            full,
+        });
+        self.tx
+            .send(SpotifyAPIEvent::CurrentUserSavedTracks(Some(offset)))
+            .unwrap();
+    }
+    //the playing track is otherwise only written once the next one starts
+    pub fn flush_history(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if let Some(entry) = self.history_recorder.finish() {
+            if let Err(e) = self.listening_history.append(&entry) {
+                log::error!("failed to record listening history: {}", e);
+            }
+        }
+    }
+    pub fn save_library_cache(&mut self) {
+        if let Some(library_cache) = self.library_cache.as_ref() {
+            if let Err(e) = library_cache.save(&self.spotify_data) {
+                self.messages
+                    .error(format!("failed to save the library cache: {}", e));
+            }
+        }
+    }
+    //an import is reported once all of its requests succeeded, other tracks right away
+    fn report_saved(&mut self, track_ids: &[String]) {
+        if let Some((count, saving)) = self.import_saving.as_mut() {
+            let before = saving.len();
+            for x in track_ids {
+                saving.remove(x);
+            }
+            if saving.len() < before {
+                if saving.is_empty() {
+                    self.messages
+                        .info(format!("Added {} tracks to Liked Songs", count));
+                    self.import_saving = None;
+                }
+                return;
+            }
+        }
+        let tracks = self.tracks_text(track_ids);
+        self.messages
+            .info(format!("Added {} to Liked Songs", tracks));
+    }
+    //the name of a single track known here, otherwise the number of tracks
+    fn tracks_text(&self, track_ids: &[String]) -> String {
+        let name = match track_ids {
+            [track_id] => self
+                .spotify_data
+                .current_playback
+                .as_ref()
+                .and_then(|x| match x.item.as_ref() {
+                    Some(rspotify::model::PlayingItem::Track(track)) => Some(track),
+                    _ => None,
+                })
+                .into_iter()
+                .chain(self.spotify_data.saved_tracks.iter().map(|x| &x.track))
+                .find(|x| x.id.as_ref() == Some(track_id))
+                .map(|x| format!("\"{}\"", x.name)),
+            _ => None,
+        };
+        name.unwrap_or_else(|| match track_ids.len() {
+            1 => "1 track".to_string(),
+            n => format!("{} tracks", n),
+        })
+    }
+    //playback is controlled on the selected device
+    fn active_device(&self) -> Result<&Device, String> {
+        self.spotify_data
+            .selected_device
+            .as_ref()
+            .ok_or_else(|| "no active device".to_string())
+    }
+    /// Runs an action from a key or a `:` command; the error is for the command line.
+    pub fn dispatch(&mut self, action: Action) -> Result<(), String> {
+        let content_ui = &mut self.contents.uis[self.selected_menu_tab_id];
+        match action {
+            //handled by the event loop
+            Action::Quit => {}
+            Action::TogglePlayback => {
+                self.active_device()?;
+                self.pause();
+                self.request_current_playback();
+            }
+            Action::NextTrack => {
+                self.active_device()?;
+                self.request_next_track();
+                self.request_current_playback();
+            }
+            Action::PreviousTrack => {
+                self.active_device()?;
+                self.request_seek_to_zero_or_previous_track();
+                self.request_current_playback();
+            }
+            Action::Seek(position) => {
+                let progress_ms = self
+                    .spotify_data
+                    .current_progress_ms()
+                    .ok_or("nothing is playing")?;
+                self.request_seek(position.apply(progress_ms));
+                self.request_current_playback();
+            }
+            Action::VolumeUp => self.request_volume(true),
+            Action::VolumeDown => self.request_volume(false),
+            Action::Volume(volume) => {
+                let current_playback = self
+                    .spotify_data
+                    .current_playback
+                    .as_ref()
+                    .ok_or("nothing is playing")?;
+                let volume = cmp::min(volume.apply(current_playback.device.volume_percent), 100);
+                self.request_volume_percent(volume as u8);
+            }
+            Action::Shuffle(None) => {
+                self.active_device()?;
+                self.shuffle();
+                self.request_current_playback();
+            }
+            Action::Shuffle(Some(state)) => self.request_shuffle(state),
+            Action::Repeat(None) => self.request_repeat(),
+            Action::Repeat(Some(state)) => self.request_repeat_state(state),
+            Action::Like => {
+                self.spotify_data
+                    .current_playing_track_id()
+                    .ok_or("nothing is playing")?;
+                self.request_save_current_playback();
+            }
+            Action::Device(name) => self.select_device_by_name(&name)?,
+            Action::NextTab => self.move_to_next_menu_tab(),
+            Action::PreviousTab => self.move_to_previous_menu_tab(),
+            Action::Tab(name) => self.select_menu_tab_by_name(&name)?,
+            Action::Filter(filter) => self.contents.filter = filter,
+            Action::Export(path, columns) => {
+                let row_tracks = content_ui.row_tracks();
+                let mut tracks = content_ui.export_tracks();
+                if let Some(marked) = content_ui.marked_rows(&row_tracks) {
+                    let mut marked = marked.into_iter();
+                    tracks.retain(|_| marked.next().unwrap_or(false));
+                }
+                if tracks.is_empty() {
+                    return Err("nothing to export in this view".to_string());
+                }
+                export::write(&config::expand_home(&path), &tracks, &columns, None)
+                    .map_err(|e| format!("failed to export to {}: {}", path, e))?;
+                self.messages
+                    .info(format!("Exported {} tracks to {}", tracks.len(), path));
+            }
+            Action::Sort(column, descending) => content_ui.sort(column, descending)?,
+            Action::ReverseSort => content_ui.reverse_sort()?,
+            Action::Diff(from, to) => {
+                let diff = self.snapshots.diff(from.as_deref(), to.as_deref())?;
+                *self.snapshot_diff.lock().unwrap() = Some(diff);
+                self.select_menu_tab_by_name("diff")?;
+            }
+            Action::Import(path) => self.start_import(&path)?,
+            Action::ImportApply(playlist) => self.apply_import(playlist)?,
+            Action::ImportRetry => self.retry_import()?,
+            Action::FilterMode => self.contents.input_mode = true,
+            Action::CommandMode => self.command_line.open(),
+            Action::Down => content_ui.key_down(),
+            Action::Up => content_ui.key_up(),
+            Action::Move(motion) => {
+                //the rows inside the borders and below a table header
+                let page = self
+                    .content_height
+                    .saturating_sub(2 + content_ui.header_height());
+                content_ui.key_motion(motion, page as usize);
+            }
+            Action::JumpToPlaying => {
+                let track_id = self
+                    .spotify_data
+                    .current_playing_track_id()
+                    .ok_or("nothing is playing")?;
+                if !content_ui.select_track(&track_id) {
+                    return Err("the playing track is not in this view".to_string());
+                }
+            }
+            Action::Enter => content_ui.key_enter(),
+            Action::PreviousPeriod => content_ui.key_previous_period(),
+            Action::NextPeriod => content_ui.key_next_period(),
+            Action::Help => self.help.open(),
+            Action::Messages => self.messages.open(),
+            Action::Details => self.open_details()?,
+            Action::Mark => content_ui.toggle_mark()?,
+            Action::Visual => content_ui.toggle_visual()?,
+            Action::ClearMarks => content_ui.clear_marks(),
+            Action::Batch(batch) => self.batch(batch)?,
+            Action::LoopA => self.set_loop_a(),
+            Action::LoopB => self.set_loop_b(),
+            Action::ClearLoop => self.clear_loop(),
+        }
+        Ok(())
+    }
+    //runs `batch` on the marked tracks of the current view, which are then unmarked; those
+    //hidden by the filter stay marked
+    fn batch(&mut self, batch: Batch) -> Result<(), String> {
+        let content_ui = &mut self.contents.uis[self.selected_menu_tab_id];
+        let track_ids = content_ui.marked_track_ids(&content_ui.row_tracks());
+        if track_ids.is_empty() {
+            return Err("no tracks marked or selected".to_string());
+        }
+        let device_id = self
+            .spotify_data
+            .selected_device
+            .as_ref()
+            .map(|x| x.id.clone());
+        match batch {
+            Batch::Like => {
+                //at most 50 tracks per request
+                for chunk in track_ids.chunks(50) {
+                    self.tx
+                        .send(SpotifyAPIEvent::AddCurrentUserSavedTracks(chunk.to_vec()))
+                        .unwrap();
+                }
+            }
+            Batch::Unlike => {
+                for chunk in track_ids.chunks(50) {
+                    self.tx
+                        .send(SpotifyAPIEvent::DeleteCurrentUserSavedTracks(
+                            chunk.to_vec(),
+                        ))
+                        .unwrap();
+                }
+            }
+            Batch::Queue => {
+                self.active_device()?;
+                self.tx
+                    .send(SpotifyAPIEvent::AddToQueue(track_ids.clone(), device_id))
+                    .unwrap();
+            }
+            Batch::Play => {
+                self.active_device()?;
+                let uris = track_ids
+                    .iter()
+                    .map(|x| format!("spotify:track:{}", x))
+                    .collect();
+                self.tx
+                    .send(SpotifyAPIEvent::StartPlayBack((device_id, Some(uris))))
+                    .unwrap();
+                self.request_current_playback();
+            }
+            Batch::Playlist(playlist) => {
+                self.messages.info(format!(
+                    "Adding {} tracks to {}…",
+                    track_ids.len(),
+                    playlist
+                ));
+                self.tx
+                    .send(SpotifyAPIEvent::AddTracksToPlaylist(
+                        playlist,
+                        track_ids.clone(),
+                    ))
+                    .unwrap();
+            }
+        }
+        if let Some(marks) = self.contents.uis[self.selected_menu_tab_id].marks_mut() {
+            marks.visual = None;
+            marks.track_ids.retain(|x| !track_ids.contains(x));
+        }
+        Ok(())
+    }
+    //the track of the selected row, or else the playing one
+    fn open_details(&mut self) -> Result<(), String> {
+        let content_ui = &self.contents.uis[self.selected_menu_tab_id];
+        let track_id = content_ui
+            .selected()
+            .and_then(|x| content_ui.row_tracks().get(x).cloned().flatten())
+            .or_else(|| self.spotify_data.current_playing_track_id())
+            .ok_or("no track selected or playing")?;
+        let track = self.find_full_track(&track_id);
+        if track.is_none() {
+            self.tx
+                .send(SpotifyAPIEvent::Track(track_id.clone()))
+                .unwrap();
+        }
+        self.tx
+            .send(SpotifyAPIEvent::AudioFeatures(track_id.clone()))
+            .unwrap();
+        self.details.open(track_id, track);
+        Ok(())
+    }
+    //Liked Songs and the playing track are full tracks, other views only know some fields
+    fn find_full_track(&self, track_id: &str) -> Option<FullTrack> {
+        let playing =
+            self.spotify_data
+                .current_playback
+                .as_ref()
+                .and_then(|x| match x.item.as_ref() {
+                    Some(rspotify::model::PlayingItem::Track(track)) => Some(track),
+                    _ => None,
+                });
+        self.spotify_data
+            .saved_tracks
+            .iter()
+            .map(|x| &x.track)
+            .chain(playing)
+            .find(|x| x.id.as_deref() == Some(track_id))
+            .cloned()
+    }
+    //reads `path` and searches every entry; the matches are reviewed in the Import view
+    fn start_import(&mut self, path: &str) -> Result<(), String> {
+        let entries = import::parse_import_file(&config::expand_home(path))
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+        if entries.is_empty() {
+            return Err(format!("no tracks found in {}", path));
+        }
+        let session = ImportSession::new(path.to_string(), entries);
+        for query in session.search_queries() {
+            self.tx.send(SpotifyAPIEvent::SearchTracks(query)).unwrap();
+        }
+        self.messages
+            .info(format!("Searching {} tracks…", session.items.len()));
+        *self.import.lock().unwrap() = Some(session);
+        self.select_menu_tab_by_name("import")
+    }
+    fn retry_import(&mut self) -> Result<(), String> {
+        let queries = match self.import.lock().unwrap().as_mut() {
+            Some(session) => session.retry_failed(),
+            None => return Err("nothing to import; run :import <FILE> first".to_string()),
+        };
+        if queries.is_empty() {
+            return Err("no failed searches".to_string());
+        }
+        self.messages
+            .info(format!("Searching {} tracks again…", queries.len()));
+        for query in queries {
+            self.tx.send(SpotifyAPIEvent::SearchTracks(query)).unwrap();
+        }
+        Ok(())
+    }
+    //adds the accepted matches to Liked Songs, or to `playlist`
+    fn apply_import(&mut self, playlist: Option<String>) -> Result<(), String> {
+        let track_ids = match self.import.lock().unwrap().as_ref() {
+            Some(session) => session.accepted_track_ids(),
+            None => return Err("nothing to import; run :import <FILE> first".to_string()),
+        };
+        if track_ids.is_empty() {
+            return Err("no accepted tracks".to_string());
+        }
+        match playlist {
+            Some(playlist) => {
+                self.tx
+                    .send(SpotifyAPIEvent::AddTracksToPlaylist(
+                        playlist.clone(),
+                        track_ids.clone(),
+                    ))
+                    .unwrap();
+                self.messages.info(format!(
+                    "Adding {} tracks to {}…",
+                    track_ids.len(),
+                    playlist
+                ));
+            }
+            None => {
+                //at most 50 tracks per request
+                for chunk in track_ids.chunks(50) {
+                    self.tx
+                        .send(SpotifyAPIEvent::AddCurrentUserSavedTracks(chunk.to_vec()))
+                        .unwrap();
+                }
+                let saving: HashSet<String> = track_ids.iter().cloned().collect();
+                self.import_saving = Some((saving.len(), saving));
+                self.messages
+                    .info(format!("Adding {} tracks to Liked Songs…", track_ids.len()));
+            }
+        }
+        Ok(())
+    }
+    //tab titles without the icons, e.g. "liked songs"
+    pub fn menu_tab_names(&self) -> Vec<String> {
+        self.menu_tabs
+            .iter()
+            .map(|x| {
+                x.trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase()
+            })
+            .collect()
+    }
+    //the candidates for completing the argument of a `:` command
+    pub fn command_arguments(&self, command: &str) -> Vec<String> {
+        let words: &[&str] = match command {
+            "device" => {
+                return self
+                    .spotify_data
+                    .devices
+                    .iter()
+                    .flatten()
+                    .map(|x| x.name.clone())
+                    .collect();
+            }
+            "tab" => return self.menu_tab_names(),
+            "shuffle" => &["on", "off", "toggle"],
+            "repeat" => &["off", "track", "context"],
+            "loop" => &["a", "b", "clear"],
+            "import" => &["apply", "retry"],
+            "batch" => &["like", "unlike", "queue", "play", "playlist"],
+            "sort" => {
+                return export::ALL_COLUMNS
+                    .iter()
+                    .map(|x| x.name().to_string())
+                    .collect();
+            }
+            "diff" => return self.snapshots.dates().into_iter().rev().collect(),
+            _ => &[],
+        };
+        words.iter().map(|x| x.to_string()).collect()
+    }
+    fn select_menu_tab_by_name(&mut self, name: &str) -> Result<(), String> {
+        let name = name.to_lowercase();
+        let tab_id = match name.parse::<usize>() {
+            Ok(number) if number >= 1 && number <= self.menu_tabs.len() => Some(number - 1),
+            _ => self.menu_tab_names().iter().position(|x| x.contains(&name)),
+        };
+        self.selected_menu_tab_id = tab_id.ok_or(format!("no such tab: {}", name))?;
+        Ok(())
+    }
+    //transfers playback to the device whose name matches (or contains) `name`
+    fn select_device_by_name(&mut self, name: &str) -> Result<(), String> {
+        let name = name.to_lowercase();
+        let devices = self.spotify_data.devices.clone().unwrap_or_default();
+        let device = devices
+            .iter()
+            .find(|x| x.name.to_lowercase() == name)
+            .or_else(|| {
+                devices
+                    .iter()
+                    .find(|x| x.name.to_lowercase().contains(&name))
+            })
+            .ok_or(format!("no such device: {}", name))?;
+        self.tx
+            .send(SpotifyAPIEvent::TransferPlayBack(device.id.clone()))
+            .unwrap();
+        self.spotify_data.selected_device = Some(device.clone());
+        self.spotify_data.touch(Field::SelectedDevice);
+        self.request_current_playback();
+        Ok(())
+    }
+    /// Clicks select tabs and rows (a double-click on a row is Enter), the wheel scrolls the
+    /// view and a click on the progress bar seeks.
+    pub fn mouse(&mut self, event: MouseEvent, layout: &AppLayout) {
+        //the popups cover the views
+        if self.help.active || self.messages.active || self.details.active {
+            return;
+        }
+        let content_ui = &mut self.contents.uis[self.selected_menu_tab_id];
+        match event {
+            //termion counts from 1
+            MouseEvent::Press(MouseButton::Left, x, y) => {
+                self.click(x.saturating_sub(1), y.saturating_sub(1), layout)
+            }
+            MouseEvent::Press(MouseButton::WheelDown, ..) => {
+                for _ in 0..WHEEL_ROWS {
+                    content_ui.key_down();
+                }
+            }
+            MouseEvent::Press(MouseButton::WheelUp, ..) => {
+                for _ in 0..WHEEL_ROWS {
+                    content_ui.key_up();
+                }
+            }
+            _ => {}
+        }
+    }
+    fn click(&mut self, x: u16, y: u16, layout: &AppLayout) {
+        let double_click = self.last_click.take().is_some_and(|(at, last_x, last_y)| {
+            at.elapsed() < Duration::from_millis(DOUBLE_CLICK_MS) && (last_x, last_y) == (x, y)
+        });
+        if !double_click {
+            self.last_click = Some((Instant::now(), x, y));
+        }
+        let contains = |area: Rect| {
+            x >= area.left() && x < area.right() && y >= area.top() && y < area.bottom()
+        };
+        if contains(layout.menu) && y == layout.menu.y + 1 {
+            if let Some(tab_id) = self.menu_tab_at(x - layout.menu.x) {
+                self.selected_menu_tab_id = tab_id;
+            }
+        } else if contains(layout.content) {
+            let content_ui = &mut self.contents.uis[self.selected_menu_tab_id];
+            if let Some(row) = ui::row_at(content_ui.as_ref(), layout.content, y) {
+                content_ui.select(row);
+                if double_click {
+                    content_ui.key_enter();
+                }
+            }
+        } else if contains(layout.player_text) {
+            let compact = layout.devices.is_some();
+            self.seek_to_click(x - layout.player_text.x, y - layout.player_text.y, compact);
+        }
+    }
+    //the tab at column `x` of the menu, laid out as tui's Tabs lays out the titles
+    fn menu_tab_at(&self, x: u16) -> Option<usize> {
+        //after the border; each title is padded by a space on both sides, then a divider
+        let mut left = 1;
+        for (tab_id, title) in self.menu_tabs.iter().enumerate() {
+            let right = left + title.width() as u16 + 2;
+            if x >= left && x < right {
+                return Some(tab_id);
+            }
+            left = right + 1;
+        }
+        None
+    }
+    //`x` and `y` are relative to the text of the player
+    fn seek_to_click(&mut self, x: u16, y: u16, compact: bool) {
+        let duration_ms = match self
+            .spotify_data
+            .current_playback
+            .as_ref()
+            .and_then(|x| x.item.as_ref())
+        {
+            Some(rspotify::model::PlayingItem::Track(playing_track)) => playing_track.duration_ms,
+            _ => return,
+        };
+        let (line, column) = SpotermClient::progress_bar_position(compact);
+        if y != line || x < column || x >= column + PROGRESS_BAR_WIDTH {
+            return;
+        }
+        let progress_ms = (x - column) as u64 * duration_ms as u64 / PROGRESS_BAR_WIDTH as u64;
+        self.request_seek(progress_ms as u32);
+        self.request_current_playback();
     }
     pub fn move_to_next_menu_tab(&mut self) {
         if self.selected_menu_tab_id + 1 < self.menu_tabs.len() {
@@ -182,10 +1169,14 @@ impl SpotermClient {
             self.selected_menu_tab_id = self.menu_tabs.len() - 1;
         }
     }
-    pub fn request_current_user_saved_tracks(&self) {
-        self.tx
-            .send(SpotifyAPIEvent::CurrentUserSavedTracks(None))
-            .unwrap();
+    pub fn request_current_user_saved_tracks(&mut self) {
+        //wait for the paging in flight, unless a page failed
+        if let Some(sync) = self.saved_tracks_sync.as_ref() {
+            if sync.requested_at.elapsed() < Duration::from_secs(30) {
+                return;
+            }
+        }
+        self.request_saved_tracks_page(0, None);
     }
     pub fn request_current_playback(&self) {
         self.tx.send(SpotifyAPIEvent::CurrentPlayBack).unwrap();
@@ -241,6 +1232,7 @@ impl SpotermClient {
                                     self.spotify_data
                                         .save_state_track_ids
                                         .insert(track_id.clone(), SaveState::UNSAVING);
+                                    self.spotify_data.touch(Field::SaveStateTrackIds);
                                 }
                                 SaveState::UNSAVED | SaveState::UNSAVING => {
                                     self.tx
@@ -251,6 +1243,7 @@ impl SpotermClient {
                                     self.spotify_data
                                         .save_state_track_ids
                                         .insert(track_id.clone(), SaveState::SAVING);
+                                    self.spotify_data.touch(Field::SaveStateTrackIds);
                                 }
                                 _ => {}
                             }
@@ -289,6 +1282,16 @@ impl SpotermClient {
             }
         }
     }
+    pub fn request_seek(&self, progress_ms: u32) {
+        if let Some(current_playback) = self.spotify_data.current_playback.as_ref() {
+            self.tx
+                .send(SpotifyAPIEvent::SeekTrack(
+                    progress_ms,
+                    Some(current_playback.device.id.clone()),
+                ))
+                .unwrap();
+        }
+    }
     pub fn request_previous_track(&self) {
         if self.spotify_data.selected_device.is_none() {
             return;
@@ -324,6 +1327,44 @@ impl SpotermClient {
                 .unwrap();
         }
     }
+    pub fn request_volume_percent(&self, volume_percent: u8) {
+        if let Some(current_playback) = self.spotify_data.current_playback.as_ref() {
+            self.tx
+                .send(SpotifyAPIEvent::Volume(
+                    cmp::min(volume_percent, 100),
+                    Some(current_playback.device.id.clone()),
+                ))
+                .unwrap();
+        }
+    }
+    pub fn request_shuffle(&self, state: bool) {
+        if let Some(current_playback) = self.spotify_data.current_playback.as_ref() {
+            if current_playback.shuffle_state != state {
+                self.shuffle();
+            }
+        }
+    }
+    pub fn request_repeat_state(&self, state: RepeatState) {
+        if let Some(current_playback) = self.spotify_data.current_playback.as_ref() {
+            self.tx
+                .send(SpotifyAPIEvent::Repeat(
+                    state,
+                    Some(current_playback.device.id.clone()),
+                ))
+                .unwrap();
+        }
+    }
+    //e.g. spotify:track:xxxx
+    pub fn request_start_playback_uri(&self, uri: String) {
+        let device_id = self
+            .spotify_data
+            .selected_device
+            .as_ref()
+            .map(|x| x.id.clone());
+        self.tx
+            .send(SpotifyAPIEvent::StartPlayBack((device_id, Some(vec![uri]))))
+            .unwrap();
+    }
     pub fn request_check_unknown_saved_tracks(&mut self) {
         let mut unknown_track_ids = Vec::new();
         for (id, state) in self.spotify_data.save_state_track_ids.iter_mut() {
@@ -335,6 +1376,9 @@ impl SpotermClient {
                 _ => {}
             }
         }
+        if !unknown_track_ids.is_empty() {
+            self.spotify_data.touch(Field::SaveStateTrackIds);
+        }
         self.tx
             .send(SpotifyAPIEvent::CheckCurrentUserSavedTracks(
                 unknown_track_ids,
@@ -372,7 +1416,100 @@ impl SpotermClient {
         }
     }
 
-    pub fn player_items(&mut self) -> Vec<Text> {
+    pub fn set_loop_a(&mut self) {
+        let track_id = self.spotify_data.current_playing_track_id();
+        if track_id.is_none() {
+            return;
+        }
+        if let Some(progress_ms) = self.spotify_data.current_progress_ms() {
+            if self.ab_loop.track_id != track_id {
+                self.ab_loop.clear();
+                self.ab_loop.track_id = track_id;
+            }
+            self.ab_loop.a_ms = Some(progress_ms);
+            if let Some(b_ms) = self.ab_loop.b_ms {
+                if b_ms <= progress_ms {
+                    self.ab_loop.b_ms = None;
+                }
+            }
+        }
+    }
+    pub fn set_loop_b(&mut self) {
+        let track_id = self.spotify_data.current_playing_track_id();
+        if track_id.is_none() {
+            return;
+        }
+        if let Some(progress_ms) = self.spotify_data.current_progress_ms() {
+            if self.ab_loop.track_id != track_id {
+                self.ab_loop.clear();
+                self.ab_loop.track_id = track_id;
+            }
+            let a_ms = self.ab_loop.a_ms.unwrap_or(0);
+            if progress_ms <= a_ms {
+                return;
+            }
+            self.ab_loop.a_ms = Some(a_ms);
+            self.ab_loop.b_ms = Some(progress_ms);
+        }
+    }
+    pub fn clear_loop(&mut self) {
+        self.ab_loop.clear();
+    }
+    //seek back to A when playback passes B
+    pub fn check_ab_loop(&mut self) {
+        let (a_ms, b_ms) = match (self.ab_loop.a_ms, self.ab_loop.b_ms) {
+            (Some(a_ms), Some(b_ms)) => (a_ms, b_ms),
+            _ => return,
+        };
+        if self.ab_loop.track_id != self.spotify_data.current_playing_track_id() {
+            return;
+        }
+        let progress_ms = match self.spotify_data.current_progress_ms() {
+            Some(progress_ms) => progress_ms,
+            None => return,
+        };
+        if progress_ms < b_ms {
+            return;
+        }
+        if let Some(current_playback) = self.spotify_data.current_playback.as_mut() {
+            self.tx
+                .send(SpotifyAPIEvent::SeekTrack(
+                    a_ms,
+                    Some(current_playback.device.id.clone()),
+                ))
+                .unwrap();
+            //don't seek again until the next poll
+            current_playback.progress_ms = Some(a_ms);
+            self.spotify_data.playback_updated_at = Some(Instant::now());
+        }
+    }
+    //the line and column of the progress bar inside the borders of the player
+    fn progress_bar_position(compact: bool) -> (u16, u16) {
+        if compact {
+            (3, "Progress: 00:00 ".len() as u16)
+        } else {
+            (1, "    Progress: 00:00 ".len() as u16)
+        }
+    }
+    fn progress_bar(&self, progress_ms: u32, duration_ms: u32, width: usize) -> String {
+        if duration_ms == 0 {
+            return String::new();
+        }
+        let position = |ms: u32| cmp::min(ms as usize * width / duration_ms as usize, width - 1);
+        let mut bar: Vec<char> = vec!['─'; width];
+        if let Some(a_ms) = self.ab_loop.a_ms {
+            bar[position(a_ms)] = '[';
+        }
+        if let Some(b_ms) = self.ab_loop.b_ms {
+            bar[position(b_ms)] = ']';
+        }
+        bar[position(progress_ms)] = '●';
+        bar.into_iter().collect()
+    }
+
+    /// The lines of the player; `compact` puts the song, artist and album on lines of their
+    /// own for the narrow side panel.
+    pub fn player_items(&mut self, compact: bool) -> Vec<Text> {
         let mut items = vec![];
         if let Some(current_playback) = self.spotify_data.current_playback.clone() {
             if let Some(playing_track) = current_playback.item.clone() {
@@ -384,16 +1521,32 @@ impl SpotermClient {
                         _ => "❓",
                     };
 
-                    items.push(Text::styled(
-                        format!(
-                            "🎵  {} Song: {} |🎤 Artist: {} | 💿 Album: {}",
-                            like_track_icon,
-                            playing_track.name,
-                            playing_track.artists[0].name,
-                            playing_track.album.name
-                        ),
-                        Style::default().fg(Color::White),
-                    ));
+                    if compact {
+                        let style = Style::default().fg(Color::White);
+                        items.push(Text::styled(
+                            format!("🎵 {} {}", like_track_icon, playing_track.name),
+                            style,
+                        ));
+                        items.push(Text::styled(
+                            format!("🎤 {}", playing_track.artists[0].name),
+                            style,
+                        ));
+                        items.push(Text::styled(
+                            format!("💿 {}", playing_track.album.name),
+                            style,
+                        ));
+                    } else {
+                        items.push(Text::styled(
+                            format!(
+                                "🎵  {} Song: {} |🎤 Artist: {} | 💿 Album: {}",
+                                like_track_icon,
+                                playing_track.name,
+                                playing_track.artists[0].name,
+                                playing_track.album.name
+                            ),
+                            Style::default().fg(Color::White),
+                        ));
+                    }
                     //Status
                     let playing_icon = if current_playback.is_playing {
                         //headphone
@@ -415,16 +1568,58 @@ impl SpotermClient {
                     };
                     let duration_sec = playing_track.duration_ms / 1000;
                     let duration = format!("{:02}:{:02}", duration_sec / 60, duration_sec % 60);
-                    let progress_sec = current_playback.progress_ms.unwrap_or(0) / 1000;
+                    let progress_ms = self.spotify_data.current_progress_ms().unwrap_or(0);
+                    let progress_sec = progress_ms / 1000;
                     let progress = format!("{:02}:{:02}", progress_sec / 60, progress_sec % 60);
-
-                    items.push(Text::styled(
-                        format!(
-                            "    Progress: {} / {} | Playing: {}  | Shuffle: {} | Repeat:  {}",
-                            progress, duration, playing_icon, shuffle_state_icon, repeat_state_icon
+                    let progress_bar = self.progress_bar(
+                        progress_ms,
+                        playing_track.duration_ms,
+                        PROGRESS_BAR_WIDTH as usize,
+                    );
+                    let ab_loop = match (self.ab_loop.a_ms, self.ab_loop.b_ms) {
+                        (Some(a_ms), Some(b_ms)) => format!(
+                            " | Loop: {:02}:{:02} - {:02}:{:02}",
+                            a_ms / 1000 / 60,
+                            a_ms / 1000 % 60,
+                            b_ms / 1000 / 60,
+                            b_ms / 1000 % 60
                         ),
-                        Style::default(),
-                    ));
+                        (Some(a_ms), None) => format!(
+                            " | Loop: {:02}:{:02} - ",
+                            a_ms / 1000 / 60,
+                            a_ms / 1000 % 60
+                        ),
+                        _ => "".to_string(),
+                    };
+
+                    //progress_bar_position() depends on the text before the bar
+                    if compact {
+                        items.push(Text::styled(
+                            format!("Progress: {} {} {}", progress, progress_bar, duration),
+                            Style::default(),
+                        ));
+                        items.push(Text::styled(
+                            format!(
+                                "Playing: {} | Shuffle: {} | Repeat: {}{}",
+                                playing_icon, shuffle_state_icon, repeat_state_icon, ab_loop
+                            ),
+                            Style::default(),
+                        ));
+                    } else {
+                        items.push(Text::styled(
+                            format!(
+                                "    Progress: {} {} {}{} | Playing: {}  | Shuffle: {} | Repeat:  {}",
+                                progress,
+                                progress_bar,
+                                duration,
+                                ab_loop,
+                                playing_icon,
+                                shuffle_state_icon,
+                                repeat_state_icon
+                            ),
+                            Style::default(),
+                        ));
+                    }
                 }
             }
 
@@ -438,6 +1633,129 @@ impl SpotermClient {
         }
         items
     }
+    /// The layout of `size`, with room for the album art when it is shown.
+    pub fn layout(&self, size: Rect) -> Option<AppLayout> {
+        let layout = AppLayout::new(size)?;
+        if self.covers.enabled() {
+            Some(layout.with_cover())
+        } else {
+            Some(layout)
+        }
+    }
+    //the album id and art of the playing track, once fetched
+    pub fn playing_cover(&mut self) -> Option<(String, Arc<Image>)> {
+        let album = match self.spotify_data.current_playback.as_ref()?.item.as_ref()? {
+            rspotify::model::PlayingItem::Track(track) => &track.album,
+            _ => return None,
+        };
+        let album_id = album.id.clone()?;
+        let image = self.covers.get(&album_id, &album.images)?;
+        Some((album_id, image))
+    }
+    //the line under the menu and its title; a filter with a syntax error is marked in red
+    fn filter_line(&self) -> (Vec<Text<'static>>, &'static str) {
+        if self.command_line.active {
+            (
+                vec![Text::raw(format!(":{}", self.command_line.input))],
+                "Command(Complete: Tab, History: Up/Down, Quit: Esc)",
+            )
+        } else if self.contents.input_mode {
+            (
+                query::filter_text(&self.contents.filter),
+                "Filter(Entering.... Quit: Enter)",
+            )
+        } else if !self.key_prefix.pending().is_empty() {
+            (
+                vec![Text::raw(self.key_prefix.pending())],
+                "Count(Cancel: Esc)",
+            )
+        } else {
+            (
+                query::filter_text(&self.contents.filter),
+                "Filter(Filter Mode: /, Command Mode: :, Help: ?)",
+            )
+        }
+    }
+    /// Draws the whole UI, laid out again on every frame so that resizing takes effect on the
+    /// next tick. Kitty and sixel covers are written after the frame by `cover::Graphics`.
+    pub fn render(&mut self, buf: &mut Buffer, size: Rect) {
+        let layout = match self.layout(size) {
+            Some(layout) => layout,
+            None => {
+                Paragraph::new([Text::raw("The terminal is too small")].iter())
+                    .wrap(true)
+                    .draw(size, buf);
+                return;
+            }
+        };
+        self.content_height = layout.content.height;
+
+        Tabs::default()
+            .block(Block::default().borders(Borders::ALL).title("Menu"))
+            .titles(&self.menu_tabs)
+            .select(self.selected_menu_tab_id)
+            .style(Style::default().fg(Color::Cyan))
+            .highlight_style(Style::default().fg(Color::Red))
+            .draw(layout.menu, buf);
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Player")
+            .draw(layout.player, buf);
+        List::new(self.player_items(layout.devices.is_some()).into_iter())
+            .draw(layout.player_text, buf);
+        if let Some(devices) = layout.devices {
+            List::new(self.device_items().into_iter())
+                .block(Block::default().borders(Borders::ALL).title("Devices"))
+                .draw(devices, buf);
+        }
+        if let Some(area) = layout.cover {
+            if !self.covers.protocol.is_graphics() {
+                if let Some((_, image)) = self.playing_cover() {
+                    HalfBlocks::new(&image, self.covers.protocol).draw(area, buf);
+                }
+            }
+        }
+
+        let (filter, filter_title) = self.filter_line();
+        Paragraph::new(filter.iter())
+            .style(Style::default().fg(Color::White))
+            .block(Block::default().borders(Borders::ALL).title(filter_title))
+            .draw(layout.filter, buf);
+
+        self.contents.uis[self.selected_menu_tab_id].render(buf, layout.content);
+        Paragraph::new([self.messages.status_text()].iter()).draw(layout.status, buf);
+        if self.details.active {
+            self.details.render(buf, size);
+        }
+        if self.messages.active {
+            self.messages.render(buf, size);
+        }
+        if self.help.active {
+            self.help.render(buf, size);
+        }
+    }
+    //the side panel of wide terminals; the active device is highlighted
+    pub fn device_items(&self) -> Vec<Text<'static>> {
+        self.spotify_data
+            .devices
+            .iter()
+            .flatten()
+            .map(|device| {
+                let (marker, style) = if device.is_active {
+                    ("▶", Style::default().fg(Color::Yellow))
+                } else {
+                    (" ", Style::default())
+                };
+                Text::styled(
+                    format!(
+                        "{} {} ({:?}) 🔊 {}",
+                        marker, device.name, device._type, device.volume_percent
+                    ),
+                    style,
+                )
+            })
+            .collect()
+    }
     pub fn pause(&self) {
         if self.spotify_data.selected_device.is_none() {
             return;
@@ -478,6 +1796,7 @@ impl SpotermClient {
         self.spotify_data
             .save_state_track_ids
             .insert(id.clone(), UNKNOWN);
+        self.spotify_data.touch(Field::SaveStateTrackIds);
         SaveState::UNKNOWN
     }
     pub fn set_selected_device(&mut self) -> Result<(), failure::Error> {
@@ -491,9 +1810,19 @@ impl SpotermClient {
         for device in devices {
             if device.name == local_hostname {
                 self.spotify_data.selected_device = Some(device);
+                self.spotify_data.touch(Field::SelectedDevice);
                 return Ok(());
             }
         }
         Ok(())
     }
 }
+
+/// `SpotermClient::render` as a widget, for `Frame::render`.
+pub struct Screen<'a>(pub &'a mut SpotermClient);
+
+impl<'a> Widget for Screen<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        self.0.render(buf, area);
+    }
+}
@@ -1,12 +1,17 @@
 extern crate crossbeam;
 extern crate rspotify;
+extern crate serde_json;
 
 use self::rspotify::client;
 use self::rspotify::model;
+use self::rspotify::model::audio::AudioFeatures;
 use self::rspotify::model::page::Page;
-use self::rspotify::model::track::SavedTrack;
-use self::rspotify::senum::RepeatState;
+use self::rspotify::model::search::SearchResult;
+use self::rspotify::model::track::{FullTrack, SavedTrack};
+use self::rspotify::senum::{RepeatState, SearchType};
+use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Serialize, Deserialize)]
 pub enum SpotifyAPIEvent {
     Shuffle(bool, Option<String>),
     Pause(Option<String>),
@@ -23,8 +28,17 @@ pub enum SpotifyAPIEvent {
     CheckCurrentUserSavedTracks(Vec<String>),
     CurrentUserSavedTracks(Option<u32>), //offset
     StartPlayBack((Option<String>, Option<Vec<String>>)),
+    TransferPlayBack(String), //device_id
+    SearchTracks(String),     //query
+    //playlist name, ID or URI and track ids
+    AddTracksToPlaylist(String, Vec<String>),
+    //track ids, in order, and the device
+    AddToQueue(Vec<String>, Option<String>),
+    Track(String),         //track id
+    AudioFeatures(String), //track id
 }
 
+#[derive(Debug, Serialize, Deserialize)]
 pub enum SpotifyAPIResult {
     CurrentPlayBack(Option<model::context::CurrentlyPlaybackContext>),
     CurrentUserPlayingTrack(Option<model::playing::Playing>),
@@ -33,7 +47,71 @@ pub enum SpotifyAPIResult {
     CurrentUserSavedTracks(Page<SavedTrack>),
     Device(Vec<model::device::Device>),
     SuccessAddCurrentUserSavedTracks(Vec<String>),
+    FailureAddCurrentUserSavedTracks(Vec<String>),
     SuccessDeleteCurrentUserSavedTracks(Vec<String>),
+    //fields of SpotifyData changed in the daemon (see daemon::Response::Data)
+    DaemonData(serde_json::Map<String, serde_json::Value>),
+    SearchTracks(String, Vec<FullTrack>),      //query
+    FailureSearchTracks(String),               //query
+    SuccessAddTracksToPlaylist(String, usize), //playlist name, number of tracks
+    SuccessAddToQueue(usize),                  //number of tracks
+    Track(FullTrack),
+    FailureTrack(String, String),         //track id, the error
+    AudioFeatures(String, AudioFeatures), //track id
+    FailureAudioFeatures(String, String), //track id, the error
+    //a request or the token refresh failed, e.g. "failed to skip the track: ..."
+    Failure(String),
+    //a reply to the request of a daemon client (see SpotifyService::api_request_tx)
+    Reply(usize, Box<SpotifyAPIResult>), //connection id
+}
+
+impl SpotifyAPIEvent {
+    //what the request does, for the message of a failure
+    pub fn description(&self) -> &'static str {
+        match self {
+            SpotifyAPIEvent::Shuffle(_, _) => "change shuffle",
+            SpotifyAPIEvent::Pause(_) => "pause",
+            SpotifyAPIEvent::Device => "get the devices",
+            SpotifyAPIEvent::Volume(_, _) => "change the volume",
+            SpotifyAPIEvent::Repeat(_, _) => "change repeat",
+            SpotifyAPIEvent::SeekTrack(_, _) => "seek",
+            SpotifyAPIEvent::NextTrack(_) => "skip to the next track",
+            SpotifyAPIEvent::PreviousTrack(_) => "skip to the previous track",
+            SpotifyAPIEvent::CurrentPlayBack => "get the playback",
+            SpotifyAPIEvent::CurrentUserRecentlyPlayed => "get the recently played tracks",
+            SpotifyAPIEvent::DeleteCurrentUserSavedTracks(_) => "remove from Liked Songs",
+            SpotifyAPIEvent::AddCurrentUserSavedTracks(_) => "add to Liked Songs",
+            SpotifyAPIEvent::CheckCurrentUserSavedTracks(_) => "check Liked Songs",
+            SpotifyAPIEvent::CurrentUserSavedTracks(_) => "get Liked Songs",
+            SpotifyAPIEvent::StartPlayBack(_) => "start playback",
+            SpotifyAPIEvent::TransferPlayBack(_) => "transfer playback",
+            SpotifyAPIEvent::SearchTracks(_) => "search",
+            SpotifyAPIEvent::AddTracksToPlaylist(_, _) => "add to the playlist",
+            SpotifyAPIEvent::AddToQueue(_, _) => "add to the queue",
+            SpotifyAPIEvent::Track(_) => "get the track",
+            SpotifyAPIEvent::AudioFeatures(_) => "get the audio features",
+        }
+    }
+}
+
+impl SpotifyAPIResult {
+    //answers for the client that asked, rather than updates of SpotifyData
+    pub fn is_reply(&self) -> bool {
+        matches!(
+            self,
+            SpotifyAPIResult::SearchTracks(_, _)
+                | SpotifyAPIResult::FailureSearchTracks(_)
+                | SpotifyAPIResult::FailureAddCurrentUserSavedTracks(_)
+                | SpotifyAPIResult::SuccessAddTracksToPlaylist(_, _)
+                | SpotifyAPIResult::SuccessAddToQueue(_)
+                | SpotifyAPIResult::Track(_)
+                | SpotifyAPIResult::FailureTrack(_, _)
+                | SpotifyAPIResult::AudioFeatures(_, _)
+                | SpotifyAPIResult::FailureAudioFeatures(_, _)
+                | SpotifyAPIResult::Failure(_)
+                | SpotifyAPIResult::Reply(_, _)
+        )
+    }
 }
 
 pub struct SpotifyService {
@@ -42,6 +120,9 @@ pub struct SpotifyService {
     pub api_result_tx: Option<crossbeam::channel::Sender<SpotifyAPIResult>>,
     pub api_event_tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
     pub api_event_rx: crossbeam::channel::Receiver<SpotifyAPIEvent>,
+    //events of daemon clients, whose replies are tagged with the connection id
+    pub api_request_tx: crossbeam::channel::Sender<(usize, SpotifyAPIEvent)>,
+    pub api_request_rx: crossbeam::channel::Receiver<(usize, SpotifyAPIEvent)>,
 }
 
 impl SpotifyService {
@@ -57,6 +138,7 @@ impl SpotifyService {
             .build();
 
         let (tx, rx) = crossbeam::channel::unbounded();
+        let (request_tx, request_rx) = crossbeam::channel::unbounded();
 
         SpotifyService {
             client: spotify,
@@ -64,6 +146,8 @@ impl SpotifyService {
             api_result_tx: None,
             api_event_tx: tx,
             api_event_rx: rx,
+            api_request_tx: request_tx,
+            api_request_rx: request_rx,
         }
     }
     pub fn api_result_tx(mut self, tx: crossbeam::channel::Sender<SpotifyAPIResult>) -> Self {
@@ -93,7 +177,7 @@ impl SpotifyService {
                 .oauth
                 .refresh_access_token(&refresh_token)
                 .await
-                .unwrap();
+                .ok_or("failed to refresh the access token")?;
             log::info!("{:?}", token_info);
 
             let client_credential = rspotify::oauth2::SpotifyClientCredentials::default()
@@ -107,66 +191,110 @@ impl SpotifyService {
         }
         Ok(())
     }
+    fn send_failure(&self, message: String) {
+        log::error!("{}", message);
+        if let Some(tx) = self.api_result_tx.as_ref() {
+            tx.send(SpotifyAPIResult::Failure(message)).ok();
+        }
+    }
     pub async fn run(mut self) -> Result<(), Box<dyn std::error::Error>> {
         let rx = self.api_event_rx.clone();
+        let request_rx = self.api_request_rx.clone();
 
         tokio::spawn(async move {
             loop {
-                self.refresh_client().await;
-                match rx.recv().unwrap() {
-                    SpotifyAPIEvent::Shuffle(state, device_id) => {
-                        self.fetch_shuffle(state, device_id).await;
-                    }
-                    SpotifyAPIEvent::Pause(device_id) => {
-                        self.fetch_pause_playback(device_id).await;
+                if let Err(e) = self.refresh_client().await {
+                    self.send_failure(e.to_string());
+                }
+                let (connection, event) = crossbeam::channel::select! {
+                    recv(rx) -> event => (None, event.unwrap()),
+                    recv(request_rx) -> request => {
+                        let (connection, event) = request.unwrap();
+                        (Some(connection), event)
                     }
-                    SpotifyAPIEvent::Device => {
-                        self.fetch_device().await;
+                };
+                //the results of a client's request are collected to tag its replies
+                let result_tx = self.api_result_tx.clone();
+                let (reply_tx, reply_rx) = crossbeam::channel::unbounded();
+                if connection.is_some() {
+                    self.api_result_tx = Some(reply_tx);
+                }
+                let description = event.description();
+                let result = match event {
+                    SpotifyAPIEvent::Shuffle(state, device_id) => {
+                        self.fetch_shuffle(state, device_id).await
                     }
+                    SpotifyAPIEvent::Pause(device_id) => self.fetch_pause_playback(device_id).await,
+                    SpotifyAPIEvent::Device => self.fetch_device().await,
                     SpotifyAPIEvent::SeekTrack(progress_ms, device_id) => {
-                        self.fetch_seek_track(progress_ms, device_id).await;
+                        self.fetch_seek_track(progress_ms, device_id).await
                     }
                     SpotifyAPIEvent::Volume(volume_percent, device_id) => {
-                        self.fetch_volume(volume_percent, device_id).await;
+                        self.fetch_volume(volume_percent, device_id).await
                     }
                     SpotifyAPIEvent::Repeat(state, device_id) => {
-                        self.fetch_repeat(state, device_id).await;
-                    }
-                    SpotifyAPIEvent::NextTrack(device_id) => {
-                        self.fetch_next_track(device_id).await;
+                        self.fetch_repeat(state, device_id).await
                     }
+                    SpotifyAPIEvent::NextTrack(device_id) => self.fetch_next_track(device_id).await,
                     SpotifyAPIEvent::PreviousTrack(device_id) => {
-                        self.fetch_previous_track(device_id).await;
+                        self.fetch_previous_track(device_id).await
                     }
                     SpotifyAPIEvent::CurrentUserRecentlyPlayed => {
-                        self.fetch_current_user_recently_played().await;
+                        self.fetch_current_user_recently_played().await
                     }
                     SpotifyAPIEvent::DeleteCurrentUserSavedTracks(track_ids) => {
                         self.fetch_delete_current_user_saved_tracks(&track_ids)
-                            .await;
+                            .await
                     }
                     SpotifyAPIEvent::AddCurrentUserSavedTracks(track_ids) => {
-                        self.fetch_add_current_user_saved_tracks(&track_ids).await;
+                        self.fetch_add_current_user_saved_tracks(&track_ids).await
                     }
                     SpotifyAPIEvent::CheckCurrentUserSavedTracks(track_ids) => {
-                        self.fetch_check_current_user_saved_tracks(&track_ids).await;
+                        self.fetch_check_current_user_saved_tracks(&track_ids).await
                     }
                     SpotifyAPIEvent::CurrentUserSavedTracks(offset) => {
-                        self.fetch_current_user_saved_tracks(offset).await;
+                        self.fetch_current_user_saved_tracks(offset).await
                     }
                     SpotifyAPIEvent::StartPlayBack((device_id, uris)) => {
-                        self.fetch_start_playback(device_id, uris).await;
+                        self.fetch_start_playback(device_id, uris).await
+                    }
+                    SpotifyAPIEvent::TransferPlayBack(device_id) => {
+                        self.fetch_transfer_playback(device_id).await
+                    }
+                    SpotifyAPIEvent::SearchTracks(query) => self.fetch_search_tracks(query).await,
+                    SpotifyAPIEvent::AddTracksToPlaylist(playlist, track_ids) => {
+                        self.fetch_add_tracks_to_playlist(playlist, track_ids).await
+                    }
+                    SpotifyAPIEvent::AddToQueue(track_ids, device_id) => {
+                        self.fetch_add_to_queue(track_ids, device_id).await
                     }
-                    SpotifyAPIEvent::CurrentPlayBack => {
-                        self.fetch_current_playback().await;
+                    SpotifyAPIEvent::Track(track_id) => self.fetch_track(track_id).await,
+                    SpotifyAPIEvent::AudioFeatures(track_id) => {
+                        self.fetch_audio_features(track_id).await
+                    }
+                    SpotifyAPIEvent::CurrentPlayBack => self.fetch_current_playback().await,
+                };
+                if let Err(e) = result {
+                    self.send_failure(format!("failed to {}: {}", description, e));
+                }
+                if let Some(connection) = connection {
+                    self.api_result_tx = result_tx.clone();
+                    for result in reply_rx.try_iter() {
+                        let result = if result.is_reply() {
+                            SpotifyAPIResult::Reply(connection, Box::new(result))
+                        } else {
+                            result
+                        };
+                        if let Some(tx) = result_tx.as_ref() {
+                            tx.send(result).ok();
+                        }
                     }
-                    _ => {}
                 }
             }
         });
         Ok(())
     }
-    async fn fetch_check_current_user_saved_tracks(
+    pub async fn fetch_check_current_user_saved_tracks(
         &self,
         track_ids: &Vec<String>,
     ) -> Result<(), failure::Error> {
@@ -185,7 +313,7 @@ impl SpotifyService {
             .send(SpotifyAPIResult::CheckCurrentUserSavedTracks(result))?;
         Ok(())
     }
-    async fn fetch_start_playback(
+    pub async fn fetch_start_playback(
         &self,
         device_id: Option<String>,
         uris: Option<Vec<String>>,
@@ -194,7 +322,150 @@ impl SpotifyService {
             .start_playback(device_id, None, uris, None, None)
             .await
     }
-    async fn fetch_current_user_recently_played(&self) -> Result<(), failure::Error> {
+    pub async fn fetch_transfer_playback(&self, device_id: String) -> Result<(), failure::Error> {
+        self.client.transfer_playback(&device_id, false).await
+    }
+    pub async fn fetch_search_tracks(&self, query: String) -> Result<(), failure::Error> {
+        let result = self
+            .client
+            .search(
+                &query,
+                SearchType::Track,
+                crate::import::SEARCH_LIMIT,
+                0,
+                None,
+                None,
+            )
+            .await;
+        //a failed search is not a search without results; the entry can be searched again
+        let tracks = match result {
+            Ok(SearchResult::Tracks(page)) => page.items,
+            Ok(_) => vec![],
+            Err(e) => {
+                self.api_result_tx
+                    .clone()
+                    .unwrap()
+                    .send(SpotifyAPIResult::FailureSearchTracks(query))?;
+                return Err(e);
+            }
+        };
+        self.api_result_tx
+            .clone()
+            .unwrap()
+            .send(SpotifyAPIResult::SearchTracks(query, tracks))?;
+        Ok(())
+    }
+    //`playlist` is an ID, a URI or the name of one of the user's playlists
+    pub async fn fetch_add_tracks_to_playlist(
+        &self,
+        playlist: String,
+        track_ids: Vec<String>,
+    ) -> Result<(), failure::Error> {
+        let user_id = self.client.current_user().await?.id;
+        let mut found = None;
+        let mut offset = 0;
+        while found.is_none() {
+            let page = self.client.current_user_playlists(50, offset).await?;
+            found = page
+                .items
+                .iter()
+                .find(|x| {
+                    x.id == playlist || x.uri == playlist || x.name.eq_ignore_ascii_case(&playlist)
+                })
+                .map(|x| (x.id.clone(), x.name.clone()));
+            if page.next.is_none() {
+                break;
+            }
+            offset = page.offset + page.limit;
+        }
+        let (playlist_id, name) =
+            found.ok_or_else(|| failure::err_msg(format!("no such playlist: {}", playlist)))?;
+        //at most 100 tracks per request
+        for chunk in track_ids.chunks(100) {
+            self.client
+                .user_playlist_add_tracks(&user_id, &playlist_id, chunk, None)
+                .await?;
+        }
+        self.api_result_tx
+            .clone()
+            .unwrap()
+            .send(SpotifyAPIResult::SuccessAddTracksToPlaylist(
+                name,
+                track_ids.len(),
+            ))?;
+        Ok(())
+    }
+    //one request per track; the tracks after a failure are still queued, and the failures
+    //are reported together
+    pub async fn fetch_add_to_queue(
+        &self,
+        track_ids: Vec<String>,
+        device_id: Option<String>,
+    ) -> Result<(), failure::Error> {
+        let mut failures = vec![];
+        for track_id in track_ids.iter() {
+            if let Err(e) = self
+                .client
+                .add_item_to_queue(format!("spotify:track:{}", track_id), device_id.clone())
+                .await
+            {
+                log::error!("failed to queue {}: {}", track_id, e);
+                failures.push(e);
+            }
+        }
+        if failures.len() < track_ids.len() {
+            self.api_result_tx
+                .clone()
+                .unwrap()
+                .send(SpotifyAPIResult::SuccessAddToQueue(
+                    track_ids.len() - failures.len(),
+                ))?;
+        }
+        match failures.first() {
+            Some(e) => Err(failure::format_err!(
+                "{} of {} tracks were not queued: {}",
+                failures.len(),
+                track_ids.len(),
+                e
+            )),
+            None => Ok(()),
+        }
+    }
+    //a failure is also sent with the track id, so the details popup does not stay loading
+    pub async fn fetch_track(&self, track_id: String) -> Result<(), failure::Error> {
+        let track = match self.client.track(&track_id).await {
+            Ok(track) => track,
+            Err(e) => {
+                self.api_result_tx
+                    .clone()
+                    .unwrap()
+                    .send(SpotifyAPIResult::FailureTrack(track_id, e.to_string()))?;
+                return Err(e);
+            }
+        };
+        self.api_result_tx
+            .clone()
+            .unwrap()
+            .send(SpotifyAPIResult::Track(track))?;
+        Ok(())
+    }
+    pub async fn fetch_audio_features(&self, track_id: String) -> Result<(), failure::Error> {
+        let features = match self.client.audio_features(&track_id).await {
+            Ok(features) => features,
+            Err(e) => {
+                self.api_result_tx.clone().unwrap().send(
+                    SpotifyAPIResult::FailureAudioFeatures(track_id, e.to_string()),
+                )?;
+                return Err(e);
+            }
+        };
+        self.api_result_tx
+            .clone()
+            .unwrap()
+            .send(SpotifyAPIResult::AudioFeatures(track_id, features))?;
+        Ok(())
+    }
+    pub async fn fetch_current_user_recently_played(&self) -> Result<(), failure::Error> {
         let items = self.client.current_user_recently_played(50).await?.items;
         self.api_result_tx
             .clone()
@@ -202,7 +473,7 @@ impl SpotifyService {
             .send(SpotifyAPIResult::CurrentUserRecentlyPlayed(items))?;
         Ok(())
     }
-    async fn fetch_current_playback(&self) -> Result<(), failure::Error> {
+    pub async fn fetch_current_playback(&self) -> Result<(), failure::Error> {
         let current_playback = self.client.current_playback(None, None).await?;
         self.api_result_tx
             .clone()
@@ -210,7 +481,7 @@ impl SpotifyService {
             .send(SpotifyAPIResult::CurrentPlayBack(current_playback))?;
         Ok(())
     }
-    async fn fetch_current_user_playing_track(&self) -> Result<(), failure::Error> {
+    pub async fn fetch_current_user_playing_track(&self) -> Result<(), failure::Error> {
         let playing_track = self.client.current_user_playing_track().await?;
         self.api_result_tx
             .clone()
@@ -218,7 +489,7 @@ impl SpotifyService {
             .send(SpotifyAPIResult::CurrentUserPlayingTrack(playing_track))?;
         Ok(())
     }
-    async fn fetch_seek_track(
+    pub async fn fetch_seek_track(
         &self,
         progress_ms: u32,
         device_id: Option<String>,
@@ -226,7 +497,7 @@ impl SpotifyService {
         self.client.seek_track(progress_ms, device_id).await?;
         Ok(())
     }
-    async fn fetch_repeat(
+    pub async fn fetch_repeat(
         &self,
         state: RepeatState,
         device_id: Option<String>,
@@ -234,7 +505,7 @@ impl SpotifyService {
         self.client.repeat(state, device_id).await?;
         Ok(())
     }
-    async fn fetch_volume(
+    pub async fn fetch_volume(
         &self,
         volume_percent: u8,
         device_id: Option<String>,
@@ -242,7 +513,7 @@ impl SpotifyService {
         self.client.volume(volume_percent, device_id).await?;
         Ok(())
     }
-    async fn fetch_device(&self) -> Result<(), failure::Error> {
+    pub async fn fetch_device(&self) -> Result<(), failure::Error> {
         let devices = self.client.device().await?.devices;
         self.api_result_tx
             .clone()
@@ -250,7 +521,7 @@ impl SpotifyService {
             .send(SpotifyAPIResult::Device(devices))?;
         Ok(())
     }
-    async fn fetch_delete_current_user_saved_tracks(
+    pub async fn fetch_delete_current_user_saved_tracks(
         &self,
         track_ids: &Vec<String>,
     ) -> Result<(), failure::Error> {
@@ -262,17 +533,22 @@ impl SpotifyService {
         )?;
         Ok(())
     }
-    async fn fetch_add_current_user_saved_tracks(
+    pub async fn fetch_add_current_user_saved_tracks(
         &self,
         track_ids: &Vec<String>,
     ) -> Result<(), failure::Error> {
-        self.client.current_user_saved_tracks_add(track_ids).await?;
+        if let Err(e) = self.client.current_user_saved_tracks_add(track_ids).await {
+            self.api_result_tx.clone().unwrap().send(
+                SpotifyAPIResult::FailureAddCurrentUserSavedTracks(track_ids.clone()),
+            )?;
+            return Err(e);
+        }
         self.api_result_tx.clone().unwrap().send(
             SpotifyAPIResult::SuccessAddCurrentUserSavedTracks(track_ids.clone()),
         )?;
         Ok(())
     }
-    async fn fetch_current_user_saved_tracks(
+    pub async fn fetch_current_user_saved_tracks(
         &self,
         offset: Option<u32>,
     ) -> Result<(), failure::Error> {
@@ -286,7 +562,7 @@ impl SpotifyService {
             .send(SpotifyAPIResult::CurrentUserSavedTracks(saved_tracks))?;
         Ok(())
     }
-    async fn fetch_shuffle(
+    pub async fn fetch_shuffle(
         &self,
         state: bool,
         device_id: Option<String>,
@@ -294,15 +570,21 @@ impl SpotifyService {
         self.client.shuffle(state, device_id).await?;
         Ok(())
     }
-    async fn fetch_pause_playback(&self, device_id: Option<String>) -> Result<(), failure::Error> {
+    pub async fn fetch_pause_playback(
+        &self,
+        device_id: Option<String>,
+    ) -> Result<(), failure::Error> {
         self.client.pause_playback(device_id).await?;
         Ok(())
     }
-    async fn fetch_previous_track(&self, device_id: Option<String>) -> Result<(), failure::Error> {
+    pub async fn fetch_previous_track(
+        &self,
+        device_id: Option<String>,
+    ) -> Result<(), failure::Error> {
         self.client.previous_track(device_id).await?;
         Ok(())
     }
-    async fn fetch_next_track(&self, device_id: Option<String>) -> Result<(), failure::Error> {
+    pub async fn fetch_next_track(&self, device_id: Option<String>) -> Result<(), failure::Error> {
         self.client.next_track(device_id).await?;
         Ok(())
     }
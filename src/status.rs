@@ -0,0 +1,232 @@
+extern crate rspotify;
+extern crate serde_json;
+
+use self::rspotify::model::context::CurrentlyPlaybackContext;
+use self::rspotify::model::PlayingItem;
+use self::rspotify::senum::RepeatState;
+use serde::Serialize;
+
+pub const DEFAULT_FORMAT: &str = "{artist} - {title} [{progress}/{duration}] {like_icon}";
+
+/// The current playback flattened for status bars (`spoterm status`).
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Status {
+    pub is_playing: bool,
+    pub artist: String,
+    pub artists: Vec<String>,
+    pub title: String,
+    pub album: String,
+    pub progress_ms: u32,
+    pub duration_ms: u32,
+    pub liked: Option<bool>,
+    pub shuffle: bool,
+    pub repeat: String,
+    pub volume: u32,
+    pub device: String,
+    pub uri: String,
+    pub track_id: Option<String>,
+    pub context_uri: Option<String>,
+}
+
+fn format_ms(ms: u32) -> String {
+    let sec = ms / 1000;
+    format!("{:02}:{:02}", sec / 60, sec % 60)
+}
+
+impl Status {
+    pub fn new(current_playback: &CurrentlyPlaybackContext, liked: Option<bool>) -> Status {
+        let mut status = Status {
+            is_playing: current_playback.is_playing,
+            progress_ms: current_playback.progress_ms.unwrap_or(0),
+            liked,
+            shuffle: current_playback.shuffle_state,
+            repeat: match current_playback.repeat_state {
+                RepeatState::Off => "off",
+                RepeatState::Track => "track",
+                RepeatState::Context => "context",
+            }
+            .to_string(),
+            volume: current_playback.device.volume_percent,
+            device: current_playback.device.name.clone(),
+            context_uri: current_playback.context.as_ref().map(|x| x.uri.clone()),
+            ..Status::default()
+        };
+        match current_playback.item.as_ref() {
+            Some(PlayingItem::Track(track)) => {
+                status.artists = track.artists.iter().map(|x| x.name.clone()).collect();
+                status.artist = status.artists.first().cloned().unwrap_or_default();
+                status.title = track.name.clone();
+                status.album = track.album.name.clone();
+                status.duration_ms = track.duration_ms;
+                status.uri = track.uri.clone();
+                status.track_id = track.id.clone();
+            }
+            Some(PlayingItem::Episode(episode)) => {
+                status.artist = episode.show.publisher.clone();
+                status.artists = vec![status.artist.clone()];
+                status.title = episode.name.clone();
+                status.album = episode.show.name.clone();
+                status.duration_ms = episode.duration_ms;
+                status.uri = episode.uri.clone();
+                status.track_id = Some(episode.id.clone());
+            }
+            None => {}
+        }
+        status
+    }
+    fn placeholder(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "artist" => self.artist.clone(),
+            "artists" => self.artists.join(", "),
+            "title" => self.title.clone(),
+            "album" => self.album.clone(),
+            "progress" => format_ms(self.progress_ms),
+            "duration" => format_ms(self.duration_ms),
+            "percent" => format!(
+                "{}",
+                (self.progress_ms as u64 * 100)
+                    .checked_div(self.duration_ms as u64)
+                    .unwrap_or(0)
+            ),
+            //same icons as the player block
+            "like_icon" => match self.liked {
+                Some(true) => "❤",
+                Some(false) => "♡",
+                None => "",
+            }
+            .to_string(),
+            "status" => if self.is_playing { "playing" } else { "paused" }.to_string(),
+            "status_icon" => if self.is_playing { "▶" } else { "⏸" }.to_string(),
+            "shuffle" => if self.shuffle { "on" } else { "off" }.to_string(),
+            "repeat" => self.repeat.clone(),
+            "volume" => format!("{}", self.volume),
+            "device" => self.device.clone(),
+            "uri" => self.uri.clone(),
+            "track_id" => self.track_id.clone().unwrap_or_default(),
+            "context_uri" => self.context_uri.clone().unwrap_or_default(),
+            _ => return None,
+        };
+        Some(value)
+    }
+    /// Replaces `{name}` placeholders; `{{` and `}}` are literal braces and unknown names are kept.
+    pub fn render(&self, format: &str) -> String {
+        let mut result = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    result.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    result.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    match self.placeholder(&name) {
+                        Some(value) if closed => result.push_str(&value),
+                        _ => {
+                            result.push('{');
+                            result.push_str(&name);
+                            if closed {
+                                result.push('}');
+                            }
+                        }
+                    }
+                }
+                c => result.push(c),
+            }
+        }
+        result
+    }
+    //the status fields plus the rendered template as "text" (e.g. for waybar)
+    pub fn to_json(&self, format: &str) -> String {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        value["text"] = serde_json::json!(self.render(format));
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Status {
+        Status {
+            is_playing: true,
+            artist: "Massive Attack".to_string(),
+            artists: vec!["Massive Attack".to_string(), "Tracey Thorn".to_string()],
+            title: "Protection".to_string(),
+            album: "Protection".to_string(),
+            progress_ms: 83_500,
+            duration_ms: 471_000,
+            liked: Some(true),
+            shuffle: false,
+            repeat: "context".to_string(),
+            volume: 40,
+            device: "Kitchen".to_string(),
+            uri: "spotify:track:1".to_string(),
+            track_id: Some("1".to_string()),
+            context_uri: None,
+        }
+    }
+
+    #[test]
+    fn renders_the_default_format() {
+        assert_eq!(
+            status().render(DEFAULT_FORMAT),
+            "Massive Attack - Protection [01:23/07:51] ❤"
+        );
+    }
+
+    #[test]
+    fn renders_every_placeholder() {
+        let status = status();
+        assert_eq!(
+            status.render("{artists}|{percent}%|{status} {status_icon}|{shuffle}/{repeat}"),
+            "Massive Attack, Tracey Thorn|17%|playing ▶|off/context"
+        );
+        assert_eq!(
+            status.render("{volume} {device} {uri} {track_id} [{context_uri}]"),
+            "40 Kitchen spotify:track:1 1 []"
+        );
+        let paused = Status {
+            is_playing: false,
+            liked: None,
+            ..status
+        };
+        assert_eq!(paused.render("{status_icon}{like_icon}"), "⏸");
+    }
+
+    #[test]
+    fn keeps_braces_and_unknown_placeholders() {
+        let status = status();
+        assert_eq!(status.render("{{title}}"), "{title}");
+        assert_eq!(status.render("{{{title}}}"), "{Protection}");
+        assert_eq!(status.render("{year} {title"), "{year} {title");
+        assert_eq!(status.render("}{"), "}{");
+    }
+
+    #[test]
+    fn no_percent_without_a_duration() {
+        let status = Status::default();
+        assert_eq!(status.render("{percent} {progress}"), "0 00:00");
+    }
+
+    #[test]
+    fn json_has_the_fields_and_the_text() {
+        let value: serde_json::Value = serde_json::from_str(&status().to_json("{title}")).unwrap();
+        assert_eq!(value["text"], "Protection");
+        assert_eq!(value["volume"], 40);
+        assert_eq!(value["liked"], true);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::config;
+use crate::export;
+use crate::export::Column;
+use crate::fuzzy;
+use crate::fuzzy::{FuzzyMatch, Segments};
+use crate::ui::RowList;
+
+use itertools::Itertools;
+use rspotify::model::track::{FullTrack, SavedTrack};
+use std::cmp::Reverse;
+use tui::style::{Color, Modifier, Style};
+
+/// The columns the filter searches, in the order of filter_fields().
+pub const FILTER_COLUMNS: [Column; 3] = [Column::Name, Column::Artists, Column::Album];
+
+pub fn filter_fields(track: &FullTrack) -> Vec<String> {
+    vec![
+        track.name.clone(),
+        artists_text(track),
+        track.album.name.clone(),
+    ]
+}
+
+fn artists_text(track: &FullTrack) -> String {
+    track.artists.iter().map(|x| x.name.clone()).join(", ")
+}
+
+pub const DEFAULT_COLUMNS: [(Column, u16); 6] = [
+    (Column::Name, 4),
+    (Column::Artists, 3),
+    (Column::Album, 3),
+    (Column::Duration, 1),
+    (Column::Popularity, 1),
+    (Column::AddedAt, 2),
+];
+
+/// Parses `["name:4", "artists"]`; a column without a width gets 1.
+pub fn parse_table_columns(columns: &[String]) -> Result<Vec<(Column, u16)>, String> {
+    let columns = columns
+        .iter()
+        .map(|x| {
+            let (name, width) = match x.find(':') {
+                Some(index) => (&x[..index], x[index + 1..].trim()),
+                None => (x.as_str(), "1"),
+            };
+            let column = match export::parse_columns(name)?.as_slice() {
+                [column] => *column,
+                _ => return Err(format!("invalid column: {}", x)),
+            };
+            match width.parse::<u16>() {
+                Ok(width) if width > 0 => Ok((column, width)),
+                _ => Err(format!("invalid width: {}", x)),
+            }
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if columns.is_empty() {
+        return Err("no columns".to_string());
+    }
+    Ok(columns)
+}
+
+/// Parses `added_at desc` or `name` (ascending).
+pub fn parse_sort(sort: &str) -> Result<(Column, bool), String> {
+    let mut words = sort.split_whitespace();
+    let column = match export::parse_columns(words.next().unwrap_or(""))?.as_slice() {
+        [column] => *column,
+        _ => return Err(format!("invalid sort: {}", sort)),
+    };
+    let descending = match words.next() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(order) => return Err(format!("invalid order: {}", order)),
+    };
+    Ok((column, descending))
+}
+
+//newest, longest and most popular first when switching to those columns
+fn descending_by_default(column: Column) -> bool {
+    matches!(
+        column,
+        Column::AddedAt | Column::Duration | Column::Popularity
+    )
+}
+
+/// The columns and order of a table of saved tracks.
+#[derive(Clone, Debug)]
+pub struct TrackTable {
+    //with relative widths
+    pub columns: Vec<(Column, u16)>,
+    pub sort_column: Column,
+    pub descending: bool,
+}
+
+impl TrackTable {
+    //invalid settings are logged and replaced by the defaults
+    pub fn new(config: &config::LikedSongs) -> TrackTable {
+        let columns = match config.columns.as_ref().map(|x| parse_table_columns(x)) {
+            Some(Ok(columns)) => columns,
+            Some(Err(e)) => {
+                log::error!("[liked_songs] columns: {}", e);
+                DEFAULT_COLUMNS.to_vec()
+            }
+            None => DEFAULT_COLUMNS.to_vec(),
+        };
+        let (sort_column, descending) = match config.sort.as_ref().map(|x| parse_sort(x)) {
+            Some(Ok(sort)) => sort,
+            Some(Err(e)) => {
+                log::error!("[liked_songs] sort: {}", e);
+                (Column::AddedAt, true)
+            }
+            None => (Column::AddedAt, true),
+        };
+        TrackTable {
+            columns,
+            sort_column,
+            descending,
+        }
+    }
+    /// Sorts by `column` (the next shown column when None); sorting by the current column
+    /// again reverses the order unless `descending` is given.
+    pub fn sort_by(&mut self, column: Option<Column>, descending: Option<bool>) {
+        let column = column.unwrap_or_else(|| {
+            let shown: Vec<Column> = self.columns.iter().map(|(x, _)| *x).collect();
+            match shown.iter().position(|x| *x == self.sort_column) {
+                Some(index) => shown[(index + 1) % shown.len()],
+                None => shown[0],
+            }
+        });
+        self.descending = match descending {
+            Some(descending) => descending,
+            None if column == self.sort_column => !self.descending,
+            None => descending_by_default(column),
+        };
+        self.sort_column = column;
+    }
+    pub fn reverse(&mut self) {
+        self.descending = !self.descending;
+    }
+    /// The indexes of `saved_tracks` in the table's order; the text of a column is
+    /// lowercased once per track rather than once per comparison.
+    pub fn sort(&self, saved_tracks: &[SavedTrack]) -> Vec<usize> {
+        match self.sort_column {
+            Column::Duration => self.sort_by_key(saved_tracks, |x| x.track.duration_ms),
+            Column::Popularity => self.sort_by_key(saved_tracks, |x| x.track.popularity),
+            Column::AddedAt => self.sort_by_key(saved_tracks, |x| x.added_at),
+            column => self.sort_by_key(saved_tracks, |x| cell(x, column).to_lowercase()),
+        }
+    }
+    fn sort_by_key<K: Ord>(
+        &self,
+        saved_tracks: &[SavedTrack],
+        key: impl Fn(&SavedTrack) -> K,
+    ) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..saved_tracks.len()).collect();
+        if self.descending {
+            order.sort_by_cached_key(|x| Reverse(key(&saved_tracks[*x])));
+        } else {
+            order.sort_by_cached_key(|x| key(&saved_tracks[*x]));
+        }
+        order
+    }
+    //the sorted column is marked with ▲ or ▼
+    pub fn header(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|(column, _)| {
+                if *column == self.sort_column {
+                    let arrow = if self.descending { "▼" } else { "▲" };
+                    format!("{} {}", column.name(), arrow)
+                } else {
+                    column.name().to_string()
+                }
+            })
+            .collect()
+    }
+    //the relative widths spread over `width` cells, less the spacing between columns
+    pub fn widths(&self, width: u16) -> Vec<u16> {
+        //a space between columns
+        let spacing = self.columns.len() as u16;
+        let width = width.saturating_sub(spacing) as u32;
+        let total: u32 = self.columns.iter().map(|(_, x)| *x as u32).sum();
+        self.columns
+            .iter()
+            .map(|(_, x)| (width * *x as u32 / total) as u16)
+            .collect()
+    }
+    //the cells, with the characters matched by the filter highlighted
+    pub fn row(&self, saved_track: &SavedTrack, fuzzy_match: &FuzzyMatch) -> Vec<Segments> {
+        self.columns
+            .iter()
+            .map(|(column, _)| {
+                let text = cell(saved_track, *column);
+                match FILTER_COLUMNS.iter().position(|x| x == column) {
+                    Some(field) => fuzzy_match.segments(field, &text),
+                    None => vec![(text, false)],
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn cell(saved_track: &SavedTrack, column: Column) -> String {
+    let track = &saved_track.track;
+    match column {
+        Column::Name => track.name.clone(),
+        Column::Artists => artists_text(track),
+        Column::Album => track.album.name.clone(),
+        Column::Duration => {
+            let sec = track.duration_ms / 1000;
+            format!("{:02}:{:02}", sec / 60, sec % 60)
+        }
+        Column::Popularity => track.popularity.to_string(),
+        Column::AddedAt => saved_track
+            .added_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        Column::Uri => track.uri.clone(),
+        Column::Isrc => track.external_ids.get("isrc").cloned().unwrap_or_default(),
+    }
+}
+
+/// A table with a header, whose selected row is highlighted, for RowList to render.
+pub fn table_list<'a>(
+    title: &'a str,
+    header: Vec<String>,
+    widths: &[u16],
+    rows: Vec<Vec<Segments>>,
+) -> RowList<'a> {
+    //cells are cut to their column and a space apart
+    let header = header
+        .into_iter()
+        .zip(widths)
+        .map(|(x, width)| {
+            fuzzy::fit(vec![(x, false)], *width as usize)
+                .into_iter()
+                .map(|(x, _)| x)
+                .collect::<String>()
+        })
+        .join(" ");
+    let rows = rows
+        .into_iter()
+        .map(|row| {
+            let cells = row
+                .into_iter()
+                .zip(widths)
+                .map(|(cell, width)| fuzzy::fit(cell, *width as usize));
+            Itertools::intersperse(cells, vec![(" ".to_string(), false)])
+                .flatten()
+                .collect()
+        })
+        .collect();
+    RowList::new(title, rows)
+        .header(header)
+        .highlight_style(Style::default().fg(Color::Yellow).modifier(Modifier::BOLD))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(x: &[&str]) -> Vec<String> {
+        x.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_columns_and_widths() {
+        assert_eq!(
+            parse_table_columns(&strings(&["name:4", "Artists", "added_at: 2"])).unwrap(),
+            vec![
+                (Column::Name, 4),
+                (Column::Artists, 1),
+                (Column::AddedAt, 2)
+            ]
+        );
+        assert_eq!(parse_table_columns(&[]).unwrap_err(), "no columns");
+        assert_eq!(
+            parse_table_columns(&strings(&["name:0"])).unwrap_err(),
+            "invalid width: name:0"
+        );
+        assert_eq!(
+            parse_table_columns(&strings(&["name:wide"])).unwrap_err(),
+            "invalid width: name:wide"
+        );
+        assert_eq!(
+            parse_table_columns(&strings(&["name,album"])).unwrap_err(),
+            "invalid column: name,album"
+        );
+        assert_eq!(
+            parse_table_columns(&strings(&["rating:2"])).unwrap_err(),
+            "unknown column: rating"
+        );
+    }
+
+    #[test]
+    fn parses_sort() {
+        assert_eq!(parse_sort("name").unwrap(), (Column::Name, false));
+        assert_eq!(
+            parse_sort("added_at desc").unwrap(),
+            (Column::AddedAt, true)
+        );
+        assert_eq!(
+            parse_sort(" duration  asc ").unwrap(),
+            (Column::Duration, false)
+        );
+        assert_eq!(parse_sort("").unwrap_err(), "invalid sort: ");
+        assert_eq!(parse_sort("name up").unwrap_err(), "invalid order: up");
+        assert_eq!(parse_sort("rating").unwrap_err(), "unknown column: rating");
+    }
+
+    #[test]
+    fn invalid_settings_fall_back_to_the_defaults() {
+        let table = TrackTable::new(&config::LikedSongs {
+            columns: Some(strings(&["name:0"])),
+            sort: Some("name sideways".to_string()),
+        });
+        assert_eq!(table.columns, DEFAULT_COLUMNS.to_vec());
+        assert_eq!(
+            (table.sort_column, table.descending),
+            (Column::AddedAt, true)
+        );
+    }
+
+    #[test]
+    fn sorting_again_reverses() {
+        let mut table = TrackTable::new(&config::LikedSongs {
+            columns: Some(strings(&["name:2", "duration"])),
+            sort: Some("name".to_string()),
+        });
+        table.sort_by(Some(Column::Name), None);
+        assert_eq!((table.sort_column, table.descending), (Column::Name, true));
+        //the next shown column, longest first
+        table.sort_by(None, None);
+        assert_eq!(
+            (table.sort_column, table.descending),
+            (Column::Duration, true)
+        );
+        table.sort_by(None, Some(false));
+        assert_eq!((table.sort_column, table.descending), (Column::Name, false));
+        assert_eq!(table.header(), vec!["name ▲", "duration"]);
+        //two spaces, then 2:1
+        assert_eq!(table.widths(32), vec![20, 10]);
+    }
+}
@@ -1,27 +1,376 @@
-use crate::spoterm::SpotifyData;
+use crate::command::Motion;
+use crate::export::{Column, ExportTrack};
+use crate::fuzzy;
+use crate::fuzzy::{FuzzyMatch, Segments};
+use crate::history::{HistoryEntry, ListeningHistory};
+use crate::import::{ImportItem, ImportSession};
+use crate::lyrics;
+use crate::query;
+use crate::query::{Queryable, RankCache};
+use crate::snapshot::{Change, SnapshotDiff, Snapshots};
+use crate::spoterm::{Field, SpotifyData};
 use crate::spotify::SpotifyAPIEvent;
+use crate::table;
+use crate::table::TrackTable;
 use itertools::Itertools;
 use rspotify::model::playing::PlayHistory;
 use rspotify::model::track::SavedTrack;
-use termion::input::MouseTerminal;
-use termion::raw::RawTerminal;
-use tui::widgets::{Block, Borders, SelectableList, Widget};
+use std::cmp;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tui::buffer::Buffer;
+use tui::layout::{Alignment, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Borders, List, Paragraph, Text, Widget};
 
 pub trait UI {
     fn key_down(&mut self);
     fn key_up(&mut self);
     fn key_enter(&mut self);
+    //move between pages of data older/newer than the current one (e.g. months of history)
+    fn key_previous_period(&mut self) {}
+    fn key_next_period(&mut self) {}
     fn set_data(&mut self, data: &SpotifyData);
     fn set_filter(&mut self, filter: String);
-    fn render(
+    //changes the order of a table, see TrackTable::sort_by
+    fn sort(&mut self, _column: Option<Column>, _descending: Option<bool>) -> Result<(), String> {
+        Err("this view can not be sorted".to_string())
+    }
+    fn reverse_sort(&mut self) -> Result<(), String> {
+        Err("this view can not be sorted".to_string())
+    }
+    //the filtered rows for `:export`, empty if the view has no tracks
+    fn export_tracks(&self) -> Vec<ExportTrack> {
+        vec![]
+    }
+    //the rows that can be clicked; views without selectable rows keep the defaults
+    fn row_count(&self) -> usize {
+        0
+    }
+    fn selected(&self) -> Option<usize> {
+        None
+    }
+    fn select(&mut self, _index: usize) {}
+    //moves the selection; `page` is the number of rows the view shows
+    fn key_motion(&mut self, motion: Motion, page: usize) {
+        let len = self.row_count();
+        if len > 0 {
+            self.select(motion.target(self.selected(), len, page));
+        }
+    }
+    //selects the first row of the track, false if the view does not list it
+    fn select_track(&mut self, _track_id: &str) -> bool {
+        false
+    }
+    //lines between the top border and the first row (the header of a table)
+    fn header_height(&self) -> u16 {
+        0
+    }
+    //the track of each row as shown, which marks the row; None for a row without one
+    fn row_tracks(&self) -> Vec<Option<String>> {
+        vec![]
+    }
+    //None in views whose rows can not be marked
+    fn marks(&self) -> Option<&Marks> {
+        None
+    }
+    fn marks_mut(&mut self) -> Option<&mut Marks> {
+        None
+    }
+    //whether each of the rows of row_tracks() is marked; None while nothing is
+    fn marked_rows(&self, row_tracks: &[Option<String>]) -> Option<Vec<bool>> {
+        let marks = self.marks().filter(|x| !x.is_empty())?;
+        let visual_rows = marks.visual_rows(row_tracks, self.selected());
+        Some(
+            row_tracks
+                .iter()
+                .enumerate()
+                .map(|(row, track_id)| marks.is_marked(row, track_id.as_deref(), visual_rows))
+                .collect(),
+        )
+    }
+    /// The tracks of a batch action: the marked rows in their order, or else the selected
+    /// one. As with `:export`, marked tracks hidden by the filter are left out.
+    fn marked_track_ids(&self, row_tracks: &[Option<String>]) -> Vec<String> {
+        match self.marked_rows(row_tracks) {
+            Some(marked) => row_tracks
+                .iter()
+                .zip(marked)
+                .filter_map(|(track_id, marked)| track_id.clone().filter(|_| marked))
+                .unique()
+                .collect(),
+            None => self
+                .selected()
+                .and_then(|x| row_tracks.get(x).cloned().flatten())
+                .into_iter()
+                .collect(),
+        }
+    }
+    //" [3 marked]" for the title, and how many more the filter hides
+    fn marks_title(&self, row_tracks: &[Option<String>]) -> String {
+        let marks = match self.marks() {
+            Some(marks) if !marks.is_empty() => marks,
+            _ => return String::new(),
+        };
+        let shown: HashSet<&str> = row_tracks.iter().flatten().map(|x| x.as_str()).collect();
+        let hidden = marks
+            .track_ids
+            .iter()
+            .filter(|x| !shown.contains(x.as_str()))
+            .count();
+        marks.title(self.marked_track_ids(row_tracks).len(), hidden)
+    }
+    //marks or unmarks the selected row and moves to the next one
+    fn toggle_mark(&mut self) -> Result<(), String> {
+        let selected = self.selected().ok_or("no row selected")?;
+        let track_id = self
+            .row_tracks()
+            .get(selected)
+            .cloned()
+            .flatten()
+            .ok_or("this row has no track")?;
+        self.marks_mut()
+            .ok_or("rows can not be marked in this view")?
+            .toggle(&track_id);
+        if selected + 1 < self.row_count() {
+            self.select(selected + 1);
+        }
+        Ok(())
+    }
+    //starts a visual selection at the selected row, or marks its rows
+    fn toggle_visual(&mut self) -> Result<(), String> {
+        self.marks().ok_or("rows can not be marked in this view")?;
+        if self.row_count() == 0 {
+            return Err("no rows to mark".to_string());
+        }
+        let selected = match self.selected() {
+            Some(selected) => selected,
+            None => {
+                self.select(0);
+                0
+            }
+        };
+        let rows = self.row_tracks();
+        let marks = self.marks_mut().unwrap();
+        match marks.visual_rows(&rows, Some(selected)) {
+            Some((first, last)) => {
+                for track_id in rows.iter().skip(first).take(last + 1 - first).flatten() {
+                    if !marks.track_ids.contains(track_id) {
+                        marks.track_ids.push(track_id.clone());
+                    }
+                }
+                marks.visual = None;
+            }
+            None => {
+                marks.visual = Some(
+                    rows.get(selected)
+                        .cloned()
+                        .flatten()
+                        .ok_or("this row has no track")?,
+                )
+            }
+        }
+        Ok(())
+    }
+    //leaves the visual selection, or unmarks every track
+    fn clear_marks(&mut self) {
+        if let Some(marks) = self.marks_mut() {
+            if marks.visual.take().is_none() {
+                marks.track_ids.clear();
+            }
+        }
+    }
+    fn render(&self, buf: &mut Buffer, area: Rect);
+}
+
+/// The row of `ui` drawn at line `y` of `area`, scrolled as RowList scrolls to the
+/// selected row.
+pub fn row_at(ui: &dyn UI, area: Rect, y: u16) -> Option<usize> {
+    let top = area.y + 1 + ui.header_height();
+    let height = area.height.saturating_sub(2 + ui.header_height()) as usize;
+    if y < top || (y - top) as usize >= height {
+        return None;
+    }
+    let offset = match ui.selected() {
+        Some(selected) if selected >= height => selected + 1 - height,
+        _ => 0,
+    };
+    let row = offset + (y - top) as usize;
+    if row < ui.row_count() {
+        Some(row)
+    } else {
+        None
+    }
+}
+
+/// The tracks marked for a batch action, one by one with Space or as the range of a
+/// visual selection started with `v`; a mark follows the track when the rows are filtered,
+/// sorted or refreshed.
+#[derive(Clone, Debug, Default)]
+pub struct Marks {
+    //in the order marked
+    pub track_ids: Vec<String>,
+    //the track the visual selection started at; it ends at the selected row
+    pub visual: Option<String>,
+}
+
+impl Marks {
+    pub fn is_empty(&self) -> bool {
+        self.track_ids.is_empty() && self.visual.is_none()
+    }
+    /// The first and last row of the visual selection. It starts at the row of its track
+    /// nearest the selected row, or at the selected row once the track is not shown.
+    pub fn visual_rows(
+        &self,
+        row_tracks: &[Option<String>],
+        selected: Option<usize>,
+    ) -> Option<(usize, usize)> {
+        let track_id = self.visual.as_deref()?;
+        let mut rows = row_tracks
+            .iter()
+            .positions(|x| x.as_deref() == Some(track_id));
+        let (start, end) = match selected {
+            Some(selected) => (
+                rows.min_by_key(|x| x.abs_diff(selected))
+                    .unwrap_or(selected),
+                selected,
+            ),
+            None => {
+                let start = rows.next()?;
+                (start, start)
+            }
+        };
+        Some((cmp::min(start, end), cmp::max(start, end)))
+    }
+    pub fn is_marked(
         &self,
-        f: &mut tui::terminal::Frame<
-            tui::backend::TermionBackend<
-                termion::screen::AlternateScreen<MouseTerminal<RawTerminal<std::io::Stdout>>>,
-            >,
-        >,
-        area: tui::layout::Rect,
-    );
+        row: usize,
+        track_id: Option<&str>,
+        visual_rows: Option<(usize, usize)>,
+    ) -> bool {
+        let in_visual = visual_rows.is_some_and(|(first, last)| first <= row && row <= last);
+        in_visual || track_id.is_some_and(|x| self.track_ids.iter().any(|y| y == x))
+    }
+    pub fn toggle(&mut self, track_id: &str) {
+        match self.track_ids.iter().position(|x| x == track_id) {
+            Some(index) => {
+                self.track_ids.remove(index);
+            }
+            None => self.track_ids.push(track_id.to_string()),
+        }
+    }
+    //`count` tracks are marked, and `hidden` more are not shown
+    pub fn title(&self, count: usize, hidden: usize) -> String {
+        let hidden = match hidden {
+            0 => String::new(),
+            hidden => format!(", {} hidden", hidden),
+        };
+        if self.visual.is_some() {
+            format!(" [VISUAL {} marked{}]", count, hidden)
+        } else {
+            format!(" [{} marked{}]", count, hidden)
+        }
+    }
+}
+
+/// A list of rows whose characters matched by the filter are highlighted, which
+/// SelectableList and Table can not do; it scrolls to the selected row as they do.
+pub struct RowList<'a> {
+    title: &'a str,
+    header: Option<String>,
+    rows: Vec<Segments>,
+    selected: Option<usize>,
+    highlight_symbol: &'a str,
+    highlight_style: Style,
+    marks: Option<Vec<bool>>,
+}
+
+impl<'a> RowList<'a> {
+    pub fn new(title: &'a str, rows: Vec<Segments>) -> RowList<'a> {
+        RowList {
+            title,
+            header: None,
+            rows,
+            selected: None,
+            highlight_symbol: "",
+            highlight_style: Style::default(),
+            marks: None,
+        }
+    }
+    //shown above the rows, followed by a blank line
+    pub fn header(mut self, header: String) -> Self {
+        self.header = Some(header);
+        self
+    }
+    pub fn select(mut self, selected: Option<usize>) -> Self {
+        self.selected = selected;
+        self
+    }
+    pub fn highlight_symbol(mut self, highlight_symbol: &'a str) -> Self {
+        self.highlight_symbol = highlight_symbol;
+        self
+    }
+    pub fn highlight_style(mut self, highlight_style: Style) -> Self {
+        self.highlight_style = highlight_style;
+        self
+    }
+    //whether each row is marked; a column for the marks is shown unless None
+    pub fn marks(mut self, marks: Option<Vec<bool>>) -> Self {
+        self.marks = marks;
+        self
+    }
+}
+
+impl<'a> Widget for RowList<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        let header_height = if self.header.is_some() { 2 } else { 0 };
+        let height = area.height.saturating_sub(2 + header_height) as usize;
+        let offset = match self.selected {
+            Some(selected) if selected >= height => selected + 1 - height,
+            _ => 0,
+        };
+        let mark_column = if self.marks.is_some() { "  " } else { "" };
+        let mut lines: Vec<Text> = vec![];
+        if let Some(header) = self.header.take() {
+            lines.push(Text::styled(
+                format!("{}{}\n\n", mark_column, header),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        //SelectableList puts the symbol and a space before the selected row
+        let blank_symbol = " ".repeat(unicode_width::UnicodeWidthStr::width(self.highlight_symbol));
+        let rows = std::mem::take(&mut self.rows);
+        for (i, row) in rows.into_iter().enumerate().skip(offset).take(height) {
+            let (symbol, style) = if Some(i) == self.selected {
+                (self.highlight_symbol, self.highlight_style)
+            } else {
+                (blank_symbol.as_str(), Style::default())
+            };
+            if !symbol.is_empty() {
+                lines.push(Text::styled(format!("{} ", symbol), style));
+            }
+            if let Some(marks) = self.marks.as_ref() {
+                if marks.get(i) == Some(&true) {
+                    lines.push(Text::styled("● ", Style::default().fg(Color::Green)));
+                } else {
+                    lines.push(Text::raw(mark_column));
+                }
+            }
+            for (text, matched) in row {
+                let style = if matched {
+                    style.fg(Color::Magenta).modifier(Modifier::BOLD)
+                } else {
+                    style
+                };
+                lines.push(Text::styled(text, style));
+            }
+            lines.push(Text::raw("\n"));
+        }
+        Paragraph::new(lines.iter())
+            .block(Block::default().borders(Borders::ALL).title(self.title))
+            .draw(area, buf);
+    }
 }
 
 pub struct Contents {
@@ -49,13 +398,20 @@ pub struct RecentPlayed {
     pub selected_id: Option<usize>,
     pub device_id: Option<String>,
     pub recent_play_histories: Option<Vec<PlayHistory>>,
+    //the revision of SpotifyData::recent_play_histories copied above
+    pub recent_play_histories_revision: Option<u64>,
     pub filter: String,
+    pub ranked: RankCache,
+    pub marks: Marks,
     pub tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
 }
 
 impl UI for RecentPlayed {
     fn key_down(&mut self) {
-        let max_track_size = self.recent_play_histories.clone().unwrap().len();
+        let max_track_size = self.rows().len();
+        if max_track_size == 0 {
+            return;
+        }
         if let Some(selected) = self.selected_id {
             if selected + 1 < max_track_size {
                 self.selected_id = Some(selected + 1);
@@ -67,29 +423,35 @@ impl UI for RecentPlayed {
         }
     }
     fn key_up(&mut self) {
+        let max_track_size = self.rows().len();
+        if max_track_size == 0 {
+            return;
+        }
         if let Some(selected) = self.selected_id {
             if selected > 0 {
                 self.selected_id = Some(selected - 1);
             } else {
-                self.selected_id = Some(self.recent_play_histories.clone().unwrap().len() - 1);
+                self.selected_id = Some(max_track_size - 1);
             }
         } else {
             self.selected_id = Some(0);
         }
     }
     fn key_enter(&mut self) {
-        if self.selected_id.is_none() || self.recent_play_histories.is_none() {
+        let selected_id = match self.selected_id {
+            Some(selected_id) => selected_id,
+            None => return,
+        };
+        let uris: Vec<String> = self
+            .rows()
+            .iter()
+            .skip(selected_id)
+            .filter_map(|(x, _)| x.track.id.as_ref())
+            .map(|id| format!("spotify:track:{}", id))
+            .collect();
+        if uris.is_empty() {
             return;
         }
-        let selected_id = self.selected_id.unwrap();
-        let play_histories = self.recent_play_histories.clone().unwrap();
-        let mut uris = vec![];
-        for play_history in play_histories.iter().skip(selected_id) {
-            uris.push(format!(
-                "spotify:track:{}",
-                play_history.track.id.clone().unwrap()
-            ));
-        }
         self.tx
             .send(SpotifyAPIEvent::StartPlayBack((
                 self.device_id.clone(),
@@ -98,33 +460,67 @@ impl UI for RecentPlayed {
             .unwrap();
     }
     fn set_data(&mut self, data: &SpotifyData) {
-        self.recent_play_histories = data.recent_play_histories.clone();
+        let revision = data.revision(Field::RecentPlayHistories);
+        if self.recent_play_histories_revision != Some(revision) {
+            self.recent_play_histories = data.recent_play_histories.clone();
+            self.recent_play_histories_revision = Some(revision);
+        }
         if let Some(device) = data.selected_device.as_ref() {
             self.device_id = Some(device.clone().id);
         }
     }
     fn set_filter(&mut self, filter: String) {
+        if filter != self.filter {
+            self.selected_id = None;
+            self.marks.visual = None;
+        }
         self.filter = filter;
     }
-    fn render(
-        &self,
-        f: &mut tui::terminal::Frame<
-            tui::backend::TermionBackend<
-                termion::screen::AlternateScreen<MouseTerminal<RawTerminal<std::io::Stdout>>>,
-            >,
-        >,
-        area: tui::layout::Rect,
-    ) {
-        SelectableList::default()
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("Recently Played"),
-            )
-            .items(&self.items_from_play_history(self.recent_play_histories.clone()))
+    fn row_count(&self) -> usize {
+        self.rows().len()
+    }
+    fn select_track(&mut self, track_id: &str) -> bool {
+        let row = self
+            .rows()
+            .iter()
+            .position(|(x, _)| x.track.id.as_deref() == Some(track_id));
+        self.selected_id = row.or(self.selected_id);
+        row.is_some()
+    }
+    fn selected(&self) -> Option<usize> {
+        self.selected_id
+    }
+    fn select(&mut self, index: usize) {
+        self.selected_id = Some(index);
+    }
+    fn export_tracks(&self) -> Vec<ExportTrack> {
+        self.rows()
+            .into_iter()
+            .map(|(x, _)| ExportTrack::from_play_history(x))
+            .collect()
+    }
+    fn row_tracks(&self) -> Vec<Option<String>> {
+        self.rows()
+            .into_iter()
+            .map(|(x, _)| x.track.id.clone())
+            .collect()
+    }
+    fn marks(&self) -> Option<&Marks> {
+        Some(&self.marks)
+    }
+    fn marks_mut(&mut self) -> Option<&mut Marks> {
+        Some(&mut self.marks)
+    }
+    fn render(&self, buf: &mut Buffer, area: tui::layout::Rect) {
+        let rows = self.rows();
+        let row_tracks: Vec<Option<String>> =
+            rows.iter().map(|(x, _)| x.track.id.clone()).collect();
+        let title = format!("Recently Played{}", self.marks_title(&row_tracks));
+        RowList::new(&title, RecentPlayed::items_from_play_history(&rows))
             .select(self.selected_id)
+            .marks(self.marked_rows(&row_tracks))
             .highlight_symbol(">")
-            .render(f, area);
+            .draw(area, buf);
     }
 }
 
@@ -134,38 +530,40 @@ impl RecentPlayed {
             selected_id: None,
             device_id: None,
             recent_play_histories: None,
+            recent_play_histories_revision: None,
             filter: String::default(),
+            ranked: RankCache::default(),
+            marks: Marks::default(),
             tx,
         }
     }
+    //the played tracks matching the filter, best first
+    fn rows(&self) -> Vec<(&PlayHistory, FuzzyMatch)> {
+        let histories = self.recent_play_histories.as_deref().unwrap_or(&[]);
+        let revision = self.recent_play_histories_revision.unwrap_or(0);
+        self.ranked
+            .rank(&self.filter, revision, || histories.iter().collect())
+            .into_iter()
+            .map(|(index, fuzzy_match)| (&histories[index], fuzzy_match))
+            .collect()
+    }
 
-    fn items_from_play_history(&self, play_histories: Option<Vec<PlayHistory>>) -> Vec<String> {
-        if play_histories.is_none() {
-            return vec![];
-        }
-        let play_histories = play_histories.as_ref().unwrap();
-        //let play_history_items: Vec<PlayHistory> = .items.into_iter().unique_by(|x| x.track.clone().id).collect();
-
-        let mut items = vec![];
-        let max_track_name_width = play_histories
+    fn items_from_play_history(rows: &[(&PlayHistory, FuzzyMatch)]) -> Vec<Segments> {
+        let max_track_name_width = rows
             .iter()
-            .map(|x| unicode_width::UnicodeWidthStr::width(x.track.name.as_str()))
+            .map(|(x, _)| unicode_width::UnicodeWidthStr::width(x.track.name.as_str()))
             .max()
             .unwrap_or(0)
             + 15;
-        for history in play_histories.iter() {
-            let mut whitespace: String = "".to_string();
-            let mut tmp = history.track.name.clone() + &whitespace;
-            while unicode_width::UnicodeWidthStr::width(tmp.as_str()) < max_track_name_width {
-                whitespace += " ";
-                tmp = history.track.name.clone() + &whitespace;
-            }
-            items.push(format!(
-                "{}{}{}",
-                history.track.name, whitespace, history.track.artists[0].name
-            ));
-        }
-        items
+        rows.iter()
+            .map(|(history, fuzzy_match)| {
+                let fields = history.filter_fields();
+                let mut items =
+                    fuzzy::fit(fuzzy_match.segments(0, &fields[0]), max_track_name_width);
+                items.extend(fuzzy_match.segments(1, &fields[1]));
+                items
+            })
+            .collect()
     }
 }
 
@@ -174,101 +572,328 @@ pub struct LikedSongs {
     pub selected_id: Option<usize>,
     pub device_id: Option<String>,
     pub saved_tracks: Vec<SavedTrack>,
+    //the revision of SpotifyData::saved_tracks copied above
+    pub saved_tracks_revision: Option<u64>,
+    //indexes of saved_tracks in the table's order, sorted again when the data or the sort changes
+    pub sorted: Vec<usize>,
+    //a new one whenever saved_tracks are sorted
+    pub revision: u64,
     pub filter: String,
+    pub ranked: RankCache,
+    pub marks: Marks,
+    pub table: TrackTable,
     pub tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
 }
 
 impl LikedSongs {
-    pub fn new(tx: crossbeam::channel::Sender<SpotifyAPIEvent>) -> LikedSongs {
+    pub fn new(tx: crossbeam::channel::Sender<SpotifyAPIEvent>, table: TrackTable) -> LikedSongs {
         LikedSongs {
             selected_id: None,
             device_id: None,
             saved_tracks: Vec::new(),
+            saved_tracks_revision: None,
+            sorted: Vec::new(),
+            revision: 0,
             filter: String::default(),
+            ranked: RankCache::default(),
+            marks: Marks::default(),
+            table,
             tx,
         }
     }
-    fn trim_text(text: &String, width_max_limit: usize) -> String {
-        let mut result = String::new();
-        text.chars().for_each(|x| {
-            let size = unicode_width::UnicodeWidthChar::width(x).unwrap_or_default();
-            let current_size = unicode_width::UnicodeWidthStr::width(result.as_str());
-            if current_size + size <= width_max_limit {
-                result.push(x.clone());
+    //the table's order, then the best matches of the filter first
+    fn rows(&self) -> Vec<(&SavedTrack, FuzzyMatch)> {
+        let sorted = || self.sorted.iter().map(|x| &self.saved_tracks[*x]).collect();
+        self.ranked
+            .rank(&self.filter, self.revision, sorted)
+            .into_iter()
+            .map(|(index, fuzzy_match)| (&self.saved_tracks[self.sorted[index]], fuzzy_match))
+            .collect()
+    }
+    fn sort_rows(&mut self) {
+        self.sorted = self.table.sort(&self.saved_tracks);
+        self.revision = query::new_revision();
+    }
+}
+
+impl UI for LikedSongs {
+    fn key_down(&mut self) {
+        let max_track_size = self.rows().len();
+        if max_track_size == 0 {
+            return;
+        }
+        if let Some(selected) = self.selected_id {
+            if selected + 1 < max_track_size {
+                self.selected_id = Some(selected + 1);
+            } else {
+                self.selected_id = Some(0);
+            }
+        } else {
+            self.selected_id = Some(0);
+        }
+    }
+    fn key_up(&mut self) {
+        let max_track_size = self.rows().len();
+        if max_track_size == 0 {
+            return;
+        }
+        if let Some(selected) = self.selected_id {
+            if selected > 0 {
+                self.selected_id = Some(selected - 1);
+            } else {
+                self.selected_id = Some(max_track_size - 1);
             }
-        });
-        while unicode_width::UnicodeWidthStr::width(result.as_str()) < width_max_limit {
-            result.push(' ');
+        } else {
+            self.selected_id = Some(0);
         }
-        result
     }
-    fn filter_saved_tracks(&self) -> Vec<&SavedTrack> {
-        let filter = self.filter.to_ascii_lowercase();
-        let saved_tracks: Vec<&SavedTrack> = self
-            .saved_tracks
+    fn key_enter(&mut self) {
+        let selected_id = match self.selected_id {
+            Some(selected_id) => selected_id,
+            None => return,
+        };
+        let uris: Vec<String> = self
+            .rows()
             .iter()
-            .filter(|&save_track| {
-                if self.filter == "" {
-                    true
-                } else {
-                    let lower_track_name = save_track.track.name.to_ascii_lowercase();
-                    if lower_track_name.contains(&filter) {
-                        return true;
-                    }
-                    let lower_album_name = save_track.track.album.name.to_ascii_lowercase();
-                    if lower_album_name.contains(&filter) {
-                        return true;
-                    }
-                    let lower_artist = save_track
-                        .track
-                        .artists
-                        .iter()
-                        .map(|x| x.name.clone().to_ascii_lowercase())
-                        .join(" ");
-                    if lower_artist.contains(&filter) {
-                        return true;
-                    }
-                    false
-                }
-            })
+            .skip(selected_id)
+            .filter_map(|(x, _)| x.track.id.as_ref())
+            .map(|id| format!("spotify:track:{}", id))
             .collect();
-        saved_tracks
+        if uris.is_empty() {
+            return;
+        }
+        self.tx
+            .send(SpotifyAPIEvent::StartPlayBack((
+                self.device_id.clone(),
+                Some(uris),
+            )))
+            .unwrap();
     }
-    fn items_from_saved_tracks(&self) -> Vec<String> {
-        let mut items = vec![];
+    fn set_data(&mut self, data: &SpotifyData) {
+        //copied and sorted only when they changed, as this runs every frame
+        let revision = data.revision(Field::SavedTracks);
+        if self.saved_tracks_revision != Some(revision) {
+            self.saved_tracks = data.saved_tracks.clone();
+            self.saved_tracks_revision = Some(revision);
+            self.sort_rows();
+        }
+        if let Some(device) = data.selected_device.as_ref() {
+            self.device_id = Some(device.clone().id);
+        }
+    }
+    fn set_filter(&mut self, filter: String) {
+        if filter != self.filter {
+            self.selected_id = None;
+            self.marks.visual = None;
+        }
+        self.filter = filter;
+    }
+    fn sort(&mut self, column: Option<Column>, descending: Option<bool>) -> Result<(), String> {
+        self.table.sort_by(column, descending);
+        self.sort_rows();
+        Ok(())
+    }
+    fn reverse_sort(&mut self) -> Result<(), String> {
+        self.table.reverse();
+        self.sort_rows();
+        Ok(())
+    }
+    fn row_count(&self) -> usize {
+        self.rows().len()
+    }
+    fn select_track(&mut self, track_id: &str) -> bool {
+        let row = self
+            .rows()
+            .iter()
+            .position(|(x, _)| x.track.id.as_deref() == Some(track_id));
+        self.selected_id = row.or(self.selected_id);
+        row.is_some()
+    }
+    fn selected(&self) -> Option<usize> {
+        self.selected_id
+    }
+    fn select(&mut self, index: usize) {
+        self.selected_id = Some(index);
+    }
+    //the header and the blank line below it
+    fn header_height(&self) -> u16 {
+        2
+    }
+    fn export_tracks(&self) -> Vec<ExportTrack> {
+        self.rows()
+            .into_iter()
+            .map(|(x, _)| ExportTrack::from_saved_track(x))
+            .collect()
+    }
+    fn row_tracks(&self) -> Vec<Option<String>> {
+        self.rows()
+            .into_iter()
+            .map(|(x, _)| x.track.id.clone())
+            .collect()
+    }
+    fn marks(&self) -> Option<&Marks> {
+        Some(&self.marks)
+    }
+    fn marks_mut(&mut self) -> Option<&mut Marks> {
+        Some(&mut self.marks)
+    }
+    fn render(&self, buf: &mut Buffer, area: tui::layout::Rect) {
+        let rows = self.rows();
+        let row_tracks: Vec<Option<String>> =
+            rows.iter().map(|(x, _)| x.track.id.clone()).collect();
+        let title = format!(
+            "Liked Songs (sort: o, reverse: O){}",
+            self.marks_title(&row_tracks)
+        );
+        let marks = self.marked_rows(&row_tracks);
+        let rows = rows
+            .iter()
+            .map(|(x, fuzzy_match)| self.table.row(x, fuzzy_match))
+            .collect();
+        //less the column of the marks
+        let width = area
+            .width
+            .saturating_sub(if marks.is_some() { 4 } else { 2 });
+        let widths = self.table.widths(width);
+        table::table_list(&title, self.table.header(), &widths, rows)
+            .select(self.selected_id)
+            .marks(marks)
+            .draw(area, buf);
+    }
+}
 
-        for saved_track in self.filter_saved_tracks().iter() {
-            let track = LikedSongs::trim_text(&saved_track.track.name, 30);
-            let artist = LikedSongs::trim_text(
-                &saved_track
-                    .track
-                    .artists
-                    .iter()
-                    .map(|x| x.name.clone())
-                    .join(" "),
-                20,
-            );
-            let album = LikedSongs::trim_text(&saved_track.track.album.name, 20);
-            let popularity = LikedSongs::trim_text(&format!("{}", saved_track.track.popularity), 3);
-            let total_sec = saved_track.track.duration_ms / 1000;
-            let duration = format!("{:02}:{:02}", total_sec / 60, total_sec % 60);
-            let added_at = saved_track.added_at.format("%Y-%m-%d %H:%M:%S").to_string();
+#[derive(Clone, Debug)]
+pub struct History {
+    pub selected_id: Option<usize>,
+    pub device_id: Option<String>,
+    pub listening_history: ListeningHistory,
+    pub months: Vec<String>,
+    pub selected_month_id: Option<usize>,
+    //newest first
+    pub entries: Vec<HistoryEntry>,
+    //size of the loaded month file, to notice newly recorded entries
+    pub loaded_len: Option<u64>,
+    //a new one whenever the entries are loaded
+    pub revision: u64,
+    pub filter: String,
+    pub ranked: RankCache,
+    pub marks: Marks,
+    pub tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
+}
 
-            let line = format!(
-                "❤   {}     {}     {}     {}     {}     {}",
-                track, artist, album, duration, popularity, added_at
+impl History {
+    pub fn new(
+        tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
+        listening_history: ListeningHistory,
+    ) -> History {
+        History {
+            selected_id: None,
+            device_id: None,
+            listening_history,
+            months: vec![],
+            selected_month_id: None,
+            entries: vec![],
+            loaded_len: None,
+            revision: 0,
+            filter: String::default(),
+            ranked: RankCache::default(),
+            marks: Marks::default(),
+            tx,
+        }
+    }
+    fn selected_month(&self) -> Option<&String> {
+        self.selected_month_id.and_then(|x| self.months.get(x))
+    }
+    fn reload_if_needed(&mut self) {
+        if self.selected_month_id.is_none() {
+            self.months = self.listening_history.months();
+            if self.months.is_empty() {
+                return;
+            }
+            self.selected_month_id = Some(self.months.len() - 1);
+        }
+        let month = self.selected_month().unwrap().clone();
+        let len = std::fs::metadata(self.listening_history.month_path(&month))
+            .map(|x| x.len())
+            .ok();
+        if len == self.loaded_len {
+            return;
+        }
+        match self.listening_history.load_month(&month) {
+            Ok(mut entries) => {
+                entries.reverse();
+                self.entries = entries;
+            }
+            Err(e) => {
+                log::error!("failed to load history {}: {}", month, e);
+                self.entries = vec![];
+            }
+        }
+        self.loaded_len = len;
+        self.revision = query::new_revision();
+    }
+    fn select_month(&mut self, month_id: usize) {
+        self.months = self.listening_history.months();
+        if month_id >= self.months.len() {
+            return;
+        }
+        self.selected_month_id = Some(month_id);
+        self.selected_id = None;
+        self.loaded_len = None;
+    }
+    //the entries of the month matching the filter, best first
+    fn rows(&self) -> Vec<(&HistoryEntry, FuzzyMatch)> {
+        self.ranked
+            .rank(&self.filter, self.revision, || {
+                self.entries.iter().collect()
+            })
+            .into_iter()
+            .map(|(index, fuzzy_match)| (&self.entries[index], fuzzy_match))
+            .collect()
+    }
+    fn items_from_entries(rows: &[(&HistoryEntry, FuzzyMatch)]) -> Vec<Segments> {
+        let mut items = vec![];
+        for (entry, fuzzy_match) in rows {
+            let fields = entry.filter_fields();
+            let played_sec = entry.played_ms / 1000;
+            let duration_sec = entry.duration_ms / 1000;
+            let played = format!(
+                "{:02}:{:02} / {:02}:{:02}",
+                played_sec / 60,
+                played_sec % 60,
+                duration_sec / 60,
+                duration_sec % 60
             );
-            items.push(line);
+            let skipped = if entry.skipped { "⏭" } else { " " };
+            let started_at = entry
+                .started_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string();
+            let mut item = vec![(format!("{}   ", started_at), false)];
+            item.extend(fuzzy::fit(fuzzy_match.segments(0, &fields[0]), 30));
+            item.push(("     ".to_string(), false));
+            item.extend(fuzzy::fit(fuzzy_match.segments(1, &fields[1]), 20));
+            item.push(("     ".to_string(), false));
+            item.extend(fuzzy::fit(fuzzy_match.segments(2, &fields[2]), 20));
+            item.push((
+                format!("     {}  {}   {}", played, skipped, entry.device_name),
+                false,
+            ));
+            items.push(item);
         }
         items
     }
 }
 
-impl UI for LikedSongs {
+impl UI for History {
     fn key_down(&mut self) {
-        let max_track_size = self.filter_saved_tracks().len();
+        let len = self.rows().len();
+        if len == 0 {
+            return;
+        }
         if let Some(selected) = self.selected_id {
-            if selected + 1 < max_track_size {
+            if selected + 1 < len {
                 self.selected_id = Some(selected + 1);
             } else {
                 self.selected_id = Some(0);
@@ -278,32 +903,35 @@ impl UI for LikedSongs {
         }
     }
     fn key_up(&mut self) {
+        let len = self.rows().len();
+        if len == 0 {
+            return;
+        }
         if let Some(selected) = self.selected_id {
-            log::info!("{}", selected);
             if selected > 0 {
                 self.selected_id = Some(selected - 1);
             } else {
-                self.selected_id = Some(self.filter_saved_tracks().len() - 1);
+                self.selected_id = Some(len - 1);
             }
         } else {
             self.selected_id = Some(0);
         }
     }
     fn key_enter(&mut self) {
-        if self.selected_id.is_none() || self.filter_saved_tracks().is_empty() {
+        let selected_id = match self.selected_id {
+            Some(selected_id) => selected_id,
+            None => return,
+        };
+        let uris: Vec<String> = self
+            .rows()
+            .iter()
+            .skip(selected_id)
+            .filter_map(|(x, _)| x.track_id.as_ref())
+            .map(|id| format!("spotify:track:{}", id))
+            .collect();
+        if uris.is_empty() {
             return;
         }
-        let selected_id = self.selected_id.unwrap();
-        let saved_tracks = self.filter_saved_tracks().clone();
-
-        let mut uris = vec![];
-
-        //let saved_track = saved_tracks.windows(selected_id);
-        for saved_track in saved_tracks.iter().skip(selected_id) {
-            if let Some(id) = saved_track.track.id.as_ref() {
-                uris.push(format!("spotify:track:{}", id));
-            }
-        }
         self.tx
             .send(SpotifyAPIEvent::StartPlayBack((
                 self.device_id.clone(),
@@ -311,30 +939,639 @@ impl UI for LikedSongs {
             )))
             .unwrap();
     }
+    fn key_previous_period(&mut self) {
+        if let Some(month_id) = self.selected_month_id {
+            if month_id > 0 {
+                self.select_month(month_id - 1);
+            }
+        }
+    }
+    fn key_next_period(&mut self) {
+        if let Some(month_id) = self.selected_month_id {
+            self.select_month(month_id + 1);
+        }
+    }
     fn set_data(&mut self, data: &SpotifyData) {
-        self.saved_tracks = data.saved_tracks.clone();
-
         if let Some(device) = data.selected_device.as_ref() {
             self.device_id = Some(device.clone().id);
         }
+        self.reload_if_needed();
     }
     fn set_filter(&mut self, filter: String) {
+        if filter != self.filter {
+            self.selected_id = None;
+            self.marks.visual = None;
+        }
         self.filter = filter;
     }
-    fn render(
-        &self,
-        f: &mut tui::terminal::Frame<
-            tui::backend::TermionBackend<
-                termion::screen::AlternateScreen<MouseTerminal<RawTerminal<std::io::Stdout>>>,
-            >,
-        >,
-        area: tui::layout::Rect,
-    ) {
-        SelectableList::default()
-            .block(Block::default().borders(Borders::ALL).title("Liked Songs"))
-            .items(&self.items_from_saved_tracks())
+    fn row_count(&self) -> usize {
+        self.rows().len()
+    }
+    fn select_track(&mut self, track_id: &str) -> bool {
+        let row = self
+            .rows()
+            .iter()
+            .position(|(x, _)| x.track_id.as_deref() == Some(track_id));
+        self.selected_id = row.or(self.selected_id);
+        row.is_some()
+    }
+    fn selected(&self) -> Option<usize> {
+        self.selected_id
+    }
+    fn select(&mut self, index: usize) {
+        self.selected_id = Some(index);
+    }
+    //the selected month
+    fn export_tracks(&self) -> Vec<ExportTrack> {
+        self.rows()
+            .into_iter()
+            .map(|(x, _)| ExportTrack::from_history_entry(x))
+            .collect()
+    }
+    fn row_tracks(&self) -> Vec<Option<String>> {
+        self.rows()
+            .into_iter()
+            .map(|(x, _)| x.track_id.clone())
+            .collect()
+    }
+    fn marks(&self) -> Option<&Marks> {
+        Some(&self.marks)
+    }
+    fn marks_mut(&mut self) -> Option<&mut Marks> {
+        Some(&mut self.marks)
+    }
+    fn render(&self, buf: &mut Buffer, area: tui::layout::Rect) {
+        let rows = self.rows();
+        let row_tracks: Vec<Option<String>> =
+            rows.iter().map(|(x, _)| x.track_id.clone()).collect();
+        let title = match self.selected_month() {
+            Some(month) => format!(
+                "History {} ({}/{}, older: [ newer: ]){}",
+                month,
+                self.selected_month_id.unwrap() + 1,
+                self.months.len(),
+                self.marks_title(&row_tracks)
+            ),
+            None => "History (nothing recorded yet)".to_string(),
+        };
+        RowList::new(&title, History::items_from_entries(&rows))
+            .select(self.selected_id)
+            .marks(self.marked_rows(&row_tracks))
+            .highlight_symbol(">")
+            .draw(area, buf);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Lyrics {
+    pub dir: PathBuf,
+    //(track id, artist, title) the lyrics were looked up for
+    pub track: Option<(Option<String>, String, String)>,
+    pub lyrics: Option<lyrics::Lyrics>,
+    pub progress_ms: Option<u32>,
+    //manual scroll offset for plain-text lyrics
+    pub scroll: usize,
+}
+
+impl Lyrics {
+    pub fn new(dir: PathBuf) -> Lyrics {
+        Lyrics {
+            dir,
+            track: None,
+            lyrics: None,
+            progress_ms: None,
+            scroll: 0,
+        }
+    }
+    fn load(&mut self) {
+        self.lyrics = None;
+        self.scroll = 0;
+        if let Some((track_id, artist, title)) = self.track.as_ref() {
+            let path = lyrics::find_lyrics_file(&self.dir, track_id.as_deref(), artist, title);
+            if let Some(path) = path {
+                match std::fs::read_to_string(&path) {
+                    Ok(content) => self.lyrics = Some(lyrics::Lyrics::parse(&content)),
+                    Err(e) => log::error!("failed to read {:?}: {}", path, e),
+                }
+            }
+        }
+    }
+}
+
+impl UI for Lyrics {
+    fn key_down(&mut self) {
+        if let Some(lyrics) = self.lyrics.as_ref() {
+            if !lyrics.is_synced() && self.scroll + 1 < lyrics.lines.len() {
+                self.scroll += 1;
+            }
+        }
+    }
+    fn key_up(&mut self) {
+        if self.scroll > 0 {
+            self.scroll -= 1;
+        }
+    }
+    fn key_enter(&mut self) {}
+    //only plain-text lyrics scroll, synced ones follow the playback
+    fn key_motion(&mut self, motion: Motion, page: usize) {
+        if let Some(lyrics) = self.lyrics.as_ref() {
+            if !lyrics.is_synced() && !lyrics.lines.is_empty() {
+                self.scroll = motion.target(Some(self.scroll), lyrics.lines.len(), page);
+            }
+        }
+    }
+    fn set_data(&mut self, data: &SpotifyData) {
+        self.progress_ms = data.current_progress_ms();
+        let mut track = None;
+        if let Some(current_playback) = data.current_playback.as_ref() {
+            if let Some(rspotify::model::PlayingItem::Track(playing_track)) =
+                current_playback.item.as_ref()
+            {
+                track = Some((
+                    playing_track.id.clone(),
+                    playing_track
+                        .artists
+                        .first()
+                        .map(|x| x.name.clone())
+                        .unwrap_or_default(),
+                    playing_track.name.clone(),
+                ));
+            }
+        }
+        if track != self.track {
+            self.track = track;
+            self.load();
+        }
+    }
+    fn set_filter(&mut self, _filter: String) {}
+    fn render(&self, buf: &mut Buffer, area: tui::layout::Rect) {
+        let title = match self.track.as_ref() {
+            Some((_, artist, title)) => format!("Lyrics: {} - {}", artist, title),
+            None => "Lyrics".to_string(),
+        };
+        let block = Block::default().borders(Borders::ALL).title(&title);
+        let lyrics = match self.lyrics.as_ref() {
+            Some(lyrics) if !lyrics.lines.is_empty() => lyrics,
+            _ => {
+                let message = match self.track.as_ref() {
+                    Some(_) => format!("No lyrics found in {}", self.dir.display()),
+                    None => "Nothing is playing".to_string(),
+                };
+                Paragraph::new(
+                    [Text::styled(message, Style::default().fg(Color::DarkGray))].iter(),
+                )
+                .block(block)
+                .alignment(Alignment::Center)
+                .draw(area, buf);
+                return;
+            }
+        };
+
+        let height = area.height.saturating_sub(2) as usize;
+        let current = self
+            .progress_ms
+            .and_then(|progress_ms| lyrics.current_line(progress_ms));
+        //keep the current line in the middle
+        let offset = match current {
+            Some(current) => current.saturating_sub(height / 2),
+            None if lyrics.is_synced() => 0,
+            None => self.scroll,
+        };
+        let items = lyrics
+            .lines
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(height)
+            .map(|(i, line)| {
+                if Some(i) == current {
+                    Text::styled(
+                        line.text.clone(),
+                        Style::default().fg(Color::Yellow).modifier(Modifier::BOLD),
+                    )
+                } else {
+                    Text::raw(line.text.clone())
+                }
+            });
+        List::new(items).block(block).draw(area, buf);
+    }
+}
+
+/// Review of `:import`: the best match of each entry, its confidence and the alternatives.
+pub struct Import {
+    pub selected_id: Option<usize>,
+    //shared with SpotermClient, which fills in the search results
+    pub session: Arc<Mutex<Option<ImportSession>>>,
+    pub filter: String,
+    pub ranked: RankCache,
+}
+
+impl Import {
+    pub fn new(session: Arc<Mutex<Option<ImportSession>>>) -> Import {
+        Import {
+            selected_id: None,
+            session,
+            filter: String::default(),
+            ranked: RankCache::default(),
+        }
+    }
+    //the indexes of the items matching the filter, best first
+    fn rows(&self) -> Vec<(usize, FuzzyMatch)> {
+        match self.session.lock().unwrap().as_ref() {
+            Some(session) => self.ranked.rank(&self.filter, session.revision, || {
+                session.items.iter().collect()
+            }),
+            None => vec![],
+        }
+    }
+    fn with_selected_item<F: FnOnce(&mut ImportItem)>(&mut self, f: F) {
+        let index = match self
+            .selected_id
+            .and_then(|x| self.rows().get(x).map(|x| x.0))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            if let Some(item) = session.items.get_mut(index) {
+                f(item);
+                session.revision = query::new_revision();
+            }
+        }
+    }
+    fn item_text(item: &ImportItem, fuzzy_match: &FuzzyMatch) -> Segments {
+        let check = if item.accepted { "[x]" } else { "[ ]" };
+        let fields = item.filter_fields();
+        let (score, alternatives) = match item.selected_candidate() {
+            Some((_, score)) => (
+                format!("{:>3}%", (score * 100.0).round()),
+                format!(" ({}/{})", item.selected + 1, item.candidates.len()),
+            ),
+            None => ("    ".to_string(), String::new()),
+        };
+        let mut text = vec![(format!("{} ", check), false)];
+        text.extend(fuzzy::fit(
+            vec![(item.confidence().name().to_string(), false)],
+            6,
+        ));
+        text.push((format!(" {}  ", score), false));
+        text.extend(fuzzy::fit(fuzzy_match.segments(0, &fields[0]), 40));
+        text.push(("  →  ".to_string(), false));
+        text.extend(fuzzy_match.segments(1, &fields[1]));
+        text.push((alternatives, false));
+        text
+    }
+}
+
+impl UI for Import {
+    fn key_down(&mut self) {
+        let len = self.rows().len();
+        if len == 0 {
+            return;
+        }
+        self.selected_id = match self.selected_id {
+            Some(selected) if selected + 1 < len => Some(selected + 1),
+            _ => Some(0),
+        };
+    }
+    fn key_up(&mut self) {
+        let len = self.rows().len();
+        if len == 0 {
+            return;
+        }
+        self.selected_id = match self.selected_id {
+            Some(selected) if selected > 0 => Some(selected - 1),
+            Some(_) => Some(len - 1),
+            None => Some(0),
+        };
+    }
+    //accepts or rejects the selected match
+    fn key_enter(&mut self) {
+        self.with_selected_item(|item| {
+            if item.selected_candidate().is_some() {
+                item.accepted = !item.accepted;
+            }
+        });
+    }
+    fn key_previous_period(&mut self) {
+        self.with_selected_item(|item| item.select_previous());
+    }
+    fn key_next_period(&mut self) {
+        self.with_selected_item(|item| item.select_next());
+    }
+    fn set_data(&mut self, _data: &SpotifyData) {
+        //the session may have been replaced by a shorter one
+        if self.selected_id.is_some_and(|x| x >= self.rows().len()) {
+            self.selected_id = None;
+        }
+    }
+    fn set_filter(&mut self, filter: String) {
+        if filter != self.filter {
+            self.selected_id = None;
+        }
+        self.filter = filter;
+    }
+    fn row_count(&self) -> usize {
+        self.rows().len()
+    }
+    fn selected(&self) -> Option<usize> {
+        self.selected_id
+    }
+    fn select(&mut self, index: usize) {
+        self.selected_id = Some(index);
+    }
+    fn render(&self, buf: &mut Buffer, area: tui::layout::Rect) {
+        let rows = self.rows();
+        let session = self.session.lock().unwrap();
+        let session = match session.as_ref() {
+            Some(session) => session,
+            None => {
+                Paragraph::new(
+                    [Text::styled(
+                        "Nothing to import; run :import <FILE> (CSV, M3U or \"Artist - Title\" lines)",
+                        Style::default().fg(Color::DarkGray),
+                    )]
+                    .iter(),
+                )
+                .block(Block::default().borders(Borders::ALL).title("Import"))
+                .alignment(Alignment::Center)
+                .draw(area, buf);
+                return;
+            }
+        };
+        let accepted = session.items.iter().filter(|x| x.accepted).count();
+        let searching = match session.searching() {
+            0 => String::new(),
+            n => format!(", {} searching", n),
+        };
+        let failed = match session.failed() {
+            0 => String::new(),
+            n => format!(", {} failed, :import retry", n),
+        };
+        let title = format!(
+            "Import {} ({}/{} accepted{}{}; Enter: accept, [ ]: alternatives, :import apply [playlist NAME])",
+            session.path,
+            accepted,
+            session.items.len(),
+            searching,
+            failed
+        );
+        let items: Vec<Segments> = rows
+            .iter()
+            .map(|(index, fuzzy_match)| Import::item_text(&session.items[*index], fuzzy_match))
+            .collect();
+        RowList::new(&title, items)
+            .select(self.selected_id)
+            .highlight_symbol(">")
+            .draw(area, buf);
+    }
+}
+
+/// Liked Songs added, removed or made unavailable between two snapshots.
+pub struct LibraryDiff {
+    pub selected_id: Option<usize>,
+    pub snapshots: Snapshots,
+    //the revision of the snapshots when the diff was last checked
+    pub snapshots_revision: Option<u64>,
+    //shared with SpotermClient for `:diff`; the latest two snapshots when None
+    pub diff: Arc<Mutex<Option<SnapshotDiff>>>,
+    pub error: Option<String>,
+    //removed tracks liked again from this view
+    pub reliked: HashSet<String>,
+    pub filter: String,
+    pub ranked: RankCache,
+    pub marks: Marks,
+    pub tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
+}
+
+impl LibraryDiff {
+    pub fn new(
+        tx: crossbeam::channel::Sender<SpotifyAPIEvent>,
+        snapshots: Snapshots,
+        diff: Arc<Mutex<Option<SnapshotDiff>>>,
+    ) -> LibraryDiff {
+        LibraryDiff {
+            selected_id: None,
+            snapshots,
+            snapshots_revision: None,
+            diff,
+            error: None,
+            reliked: HashSet::new(),
+            filter: String::default(),
+            ranked: RankCache::default(),
+            marks: Marks::default(),
+            tx,
+        }
+    }
+    //the indexes of the changes matching the filter, best first
+    fn rows(&self) -> Vec<(usize, FuzzyMatch)> {
+        match self.diff.lock().unwrap().as_ref() {
+            Some(diff) => self.ranked.rank(&self.filter, diff.revision, || {
+                diff.changes.iter().map(|(_, track)| track).collect()
+            }),
+            None => vec![],
+        }
+    }
+    //compares `to` with the snapshot before (older) or after (newer) the current `from`
+    fn move_from(&mut self, older: bool) {
+        let (from, to) = match self.diff.lock().unwrap().as_ref() {
+            Some(diff) => (diff.from.clone(), diff.to.clone()),
+            None => return,
+        };
+        let dates = self.snapshots.dates();
+        let from = if older {
+            dates.iter().rev().find(|x| **x < from)
+        } else {
+            dates.iter().find(|x| **x > from && **x < to)
+        };
+        if let Some(from) = from {
+            let result = self.snapshots.diff(Some(from), Some(&to));
+            self.set_diff(result);
+        }
+    }
+    fn set_diff(&mut self, result: Result<SnapshotDiff, String>) {
+        self.selected_id = None;
+        match result {
+            Ok(diff) => {
+                *self.diff.lock().unwrap() = Some(diff);
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+impl UI for LibraryDiff {
+    fn key_down(&mut self) {
+        let len = self.rows().len();
+        if len == 0 {
+            return;
+        }
+        self.selected_id = match self.selected_id {
+            Some(selected) if selected + 1 < len => Some(selected + 1),
+            _ => Some(0),
+        };
+    }
+    fn key_up(&mut self) {
+        let len = self.rows().len();
+        if len == 0 {
+            return;
+        }
+        self.selected_id = match self.selected_id {
+            Some(selected) if selected > 0 => Some(selected - 1),
+            Some(_) => Some(len - 1),
+            None => Some(0),
+        };
+    }
+    //likes a removed track again
+    fn key_enter(&mut self) {
+        let index = match self
+            .selected_id
+            .and_then(|x| self.rows().get(x).map(|x| x.0))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        let track_id = match self.diff.lock().unwrap().as_ref() {
+            Some(diff) => match diff.changes.get(index) {
+                Some((Change::Removed, track)) => track.id.clone(),
+                _ => return,
+            },
+            None => return,
+        };
+        if self.reliked.insert(track_id.clone()) {
+            self.tx
+                .send(SpotifyAPIEvent::AddCurrentUserSavedTracks(vec![track_id]))
+                .unwrap();
+        }
+    }
+    fn key_previous_period(&mut self) {
+        self.move_from(true);
+    }
+    fn key_next_period(&mut self) {
+        self.move_from(false);
+    }
+    fn set_data(&mut self, _data: &SpotifyData) {
+        //the latest two again once a snapshot is saved, unless another diff was chosen
+        let revision = self.snapshots.revision();
+        if self.snapshots_revision == Some(revision) {
+            return;
+        }
+        self.snapshots_revision = Some(revision);
+        let latest = self.diff.lock().unwrap().as_ref().is_none_or(|x| x.latest);
+        if latest {
+            let result = self.snapshots.diff(None, None);
+            self.set_diff(result);
+        }
+    }
+    fn set_filter(&mut self, filter: String) {
+        if filter != self.filter {
+            self.selected_id = None;
+            self.marks.visual = None;
+        }
+        self.filter = filter;
+    }
+    fn row_count(&self) -> usize {
+        self.rows().len()
+    }
+    fn select_track(&mut self, track_id: &str) -> bool {
+        let rows = self.rows();
+        let row = self.diff.lock().unwrap().as_ref().and_then(|diff| {
+            rows.iter()
+                .position(|(index, _)| diff.changes[*index].1.id == track_id)
+        });
+        self.selected_id = row.or(self.selected_id);
+        row.is_some()
+    }
+    fn row_tracks(&self) -> Vec<Option<String>> {
+        let rows = self.rows();
+        match self.diff.lock().unwrap().as_ref() {
+            Some(diff) => rows
+                .iter()
+                .map(|(index, _)| Some(diff.changes[*index].1.id.clone()))
+                .collect(),
+            None => vec![],
+        }
+    }
+    fn marks(&self) -> Option<&Marks> {
+        Some(&self.marks)
+    }
+    fn marks_mut(&mut self) -> Option<&mut Marks> {
+        Some(&mut self.marks)
+    }
+    fn selected(&self) -> Option<usize> {
+        self.selected_id
+    }
+    fn select(&mut self, index: usize) {
+        self.selected_id = Some(index);
+    }
+    fn render(&self, buf: &mut Buffer, area: tui::layout::Rect) {
+        let rows = self.rows();
+        let diff = self.diff.lock().unwrap();
+        let diff = match (diff.as_ref(), self.error.as_ref()) {
+            (Some(diff), None) => diff,
+            (_, error) => {
+                let message = error
+                    .cloned()
+                    .unwrap_or_else(|| "no snapshots yet".to_string());
+                Paragraph::new(
+                    [Text::styled(message, Style::default().fg(Color::DarkGray))].iter(),
+                )
+                .block(Block::default().borders(Borders::ALL).title("Library Diff"))
+                .alignment(Alignment::Center)
+                .draw(area, buf);
+                return;
+            }
+        };
+        let row_tracks: Vec<Option<String>> = rows
+            .iter()
+            .map(|(index, _)| Some(diff.changes[*index].1.id.clone()))
+            .collect();
+        let title = format!(
+            "Library Diff {} → {} ({} removed, {} added, {} unavailable; older: [ newer: ], Enter: like again){}",
+            diff.from,
+            diff.to,
+            diff.count(Change::Removed),
+            diff.count(Change::Added),
+            diff.count(Change::Unavailable),
+            self.marks_title(&row_tracks)
+        );
+        let items: Vec<Segments> = rows
+            .iter()
+            .map(|(index, fuzzy_match)| {
+                let track = &diff.changes[*index].1;
+                let liked = if self.reliked.contains(&track.id) {
+                    "❤"
+                } else {
+                    " "
+                };
+                let fields = track.filter_fields();
+                let mut item = vec![(
+                    format!("{} {}  ", diff.changes[*index].0.symbol(), liked),
+                    false,
+                )];
+                item.extend(fuzzy::fit(fuzzy_match.segments(0, &fields[0]), 30));
+                item.push(("     ".to_string(), false));
+                item.extend(fuzzy::fit(fuzzy_match.segments(1, &fields[1]), 20));
+                item.push(("     ".to_string(), false));
+                item.extend(fuzzy::fit(fuzzy_match.segments(2, &fields[2]), 20));
+                item.push((
+                    format!(
+                        "     {}",
+                        track
+                            .added_at
+                            .with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d")
+                    ),
+                    false,
+                ));
+                item
+            })
+            .collect();
+        RowList::new(&title, items)
             .select(self.selected_id)
+            .marks(self.marked_rows(&row_tracks))
             .highlight_symbol(">")
-            .render(f, area);
+            .draw(area, buf);
     }
 }
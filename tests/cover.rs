@@ -0,0 +1,225 @@
+//album art fetched from a local HTTP server, cached, and drawn in half blocks
+extern crate rspotify;
+
+use self::rspotify::model::image::Image as AlbumImage;
+use spoterm::config::UserConfig;
+use spoterm::cover::{CoverService, Covers, HalfBlocks, Image, Protocol};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::Color;
+use tui::widgets::Widget;
+
+//16×16, the top half red and the bottom half blue
+const COVER: &[u8] = include_bytes!("fixtures/cover.jpg");
+
+struct Server {
+    address: SocketAddr,
+    requests: Arc<AtomicUsize>,
+}
+
+impl Server {
+    //serves fixtures/cover.jpg at /cover.jpg, and nothing else
+    fn start() -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                //the headers, up to the blank line
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = match request_line.split_whitespace().nth(1) {
+                    Some("/cover.jpg") => ("200 OK", COVER),
+                    _ => ("404 Not Found", &[][..]),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        Server { address, requests }
+    }
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+fn album_image(url: &str, width: u32) -> AlbumImage {
+    AlbumImage {
+        url: url.to_string(),
+        width: Some(width),
+        height: Some(width),
+    }
+}
+
+//the cover once the service has sent it
+async fn received(covers: &mut Covers, album_id: &str, images: &[AlbumImage]) -> Arc<Image> {
+    for _ in 0..500 {
+        covers.receive();
+        if let Some(image) = covers.get(album_id, images) {
+            return image;
+        }
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+    }
+    panic!("no album art for {}", album_id);
+}
+
+fn assert_close(pixel: [u8; 3], expected: [u8; 3]) {
+    let close = pixel
+        .iter()
+        .zip(expected.iter())
+        .all(|(x, y)| (*x as i32 - *y as i32).abs() <= 8);
+    assert!(close, "{:?} is not close to {:?}", pixel, expected);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn fetches_decodes_and_caches_each_album_once() {
+    let server = Server::start();
+    let service = CoverService::default();
+    let mut covers = Covers::new(Protocol::TrueColor, &service);
+    service.run().await.unwrap();
+    //half blocks take the smallest image at least 64 wide
+    let images = [
+        album_image(&server.url("/large.jpg"), 640),
+        album_image(&server.url("/cover.jpg"), 64),
+    ];
+
+    assert!(covers.get("album", &images).is_none());
+    let image = received(&mut covers, "album", &images).await;
+    assert_eq!((image.width, image.height), (16, 16));
+    assert_close(image.pixels[0], [255, 0, 0]);
+    assert_close(image.pixels[16 * 16 - 1], [0, 0, 255]);
+    assert!(covers.get("album", &images).is_some());
+    assert_eq!(server.requests(), 1);
+
+    //a failure is not fetched again
+    let missing = [album_image(&server.url("/missing.jpg"), 64)];
+    assert!(covers.get("missing", &missing).is_none());
+    while server.requests() < 2 {
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+    }
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    covers.receive();
+    assert!(covers.get("missing", &missing).is_none());
+    assert_eq!(server.requests(), 2);
+}
+
+#[test]
+fn keeps_the_images_of_the_last_fifty_albums() {
+    let service = CoverService::default();
+    let mut covers = Covers::new(Protocol::TrueColor, &service);
+    let images = [album_image("http://localhost/cover.jpg", 64)];
+    let pixel = Image {
+        width: 1,
+        height: 1,
+        pixels: vec![[0, 0, 0]],
+    };
+    for album in 0..51 {
+        assert!(covers.get(&album.to_string(), &images).is_none());
+    }
+    assert_eq!(service.cover_request_rx.try_iter().count(), 51);
+    for album in 0..51 {
+        service
+            .cover_result_tx
+            .send((album.to_string(), Ok(pixel.clone())))
+            .unwrap();
+    }
+    covers.receive();
+    assert!(covers.get("1", &images).is_some());
+    assert!(covers.get("50", &images).is_some());
+    //the first one was dropped, so it is requested again
+    assert!(covers.get("0", &images).is_none());
+    assert_eq!(service.cover_request_rx.try_iter().count(), 1);
+}
+
+#[test]
+fn no_album_art_once_the_service_stopped() {
+    let service = CoverService::default();
+    let mut covers = Covers::new(Protocol::TrueColor, &service);
+    drop(service);
+    let images = [album_image("http://localhost/cover.jpg", 64)];
+    assert!(covers.get("album", &images).is_none());
+    assert!(!covers.enabled());
+}
+
+#[test]
+fn draws_two_pixels_in_each_half_block() {
+    let image = Image {
+        width: 1,
+        height: 2,
+        pixels: vec![[255, 0, 0], [128, 128, 128]],
+    };
+    let area = Rect::new(0, 0, 2, 1);
+    let colors = |protocol| {
+        let mut buf = Buffer::empty(area);
+        HalfBlocks::new(&image, protocol).draw(area, &mut buf);
+        (0..area.width)
+            .map(|x| {
+                let cell = buf.get(x, 0);
+                assert_eq!(cell.symbol, "▀");
+                (cell.style.fg, cell.style.bg)
+            })
+            .collect::<Vec<(Color, Color)>>()
+    };
+    assert_eq!(
+        colors(Protocol::TrueColor),
+        vec![(Color::Rgb(255, 0, 0), Color::Rgb(128, 128, 128)); 2]
+    );
+    //red in the color cube, gray on the gray ramp
+    assert_eq!(
+        colors(Protocol::Indexed),
+        vec![(Color::Indexed(196), Color::Indexed(244)); 2]
+    );
+}
+
+//the only test reading the environment, which the tests of this file share
+#[test]
+fn detects_the_protocol_from_the_environment() {
+    assert_eq!(Protocol::parse("kitty"), Some(Protocol::Kitty));
+    assert_eq!(Protocol::parse("256"), Some(Protocol::Indexed));
+    assert_eq!(Protocol::parse("off"), Some(Protocol::Off));
+    assert_eq!(Protocol::parse("ascii"), None);
+
+    for name in ["KITTY_WINDOW_ID", "TMUX", "TERM_PROGRAM", "COLORTERM"] {
+        std::env::remove_var(name);
+    }
+    std::env::set_var("TERM", "xterm-kitty");
+    assert_eq!(Protocol::detect(), Protocol::Kitty);
+    assert_eq!(Protocol::parse("auto"), Some(Protocol::Kitty));
+    std::env::set_var("TERM", "foot");
+    assert_eq!(Protocol::detect(), Protocol::Sixel);
+    //tmux does not pass graphics through
+    std::env::set_var("TMUX", "/tmp/tmux-0/default,1,0");
+    assert_eq!(Protocol::detect(), Protocol::Indexed);
+    std::env::set_var("COLORTERM", "truecolor");
+    assert_eq!(Protocol::detect(), Protocol::TrueColor);
+
+    //an unknown protocol in config.toml falls back to the detected one
+    let mut user_config = UserConfig::new();
+    user_config.cover.protocol = Some("ascii".to_string());
+    assert_eq!(user_config.cover.protocol(), Protocol::TrueColor);
+}
@@ -0,0 +1,196 @@
+//MPRIS on a private `dbus-daemon`, so the desktop session is left alone
+#![cfg(feature = "mpris")]
+
+extern crate crossbeam;
+extern crate rspotify;
+extern crate serde_json;
+extern crate zbus;
+
+use self::rspotify::model::context::CurrentlyPlaybackContext;
+use self::zbus::blocking::{Connection, ConnectionBuilder, Proxy, ProxyBuilder};
+use self::zbus::zvariant::OwnedValue;
+use self::zbus::CacheProperties;
+use spoterm::config::UserConfig;
+use spoterm::mpris::Mpris;
+use spoterm::spoterm::SpotermClient;
+use spoterm::spotify::{SpotifyAPIEvent, SpotifyAPIResult};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.spoterm";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+//Mpris::new reads the bus address from the environment, which the tests share
+static SESSION_ENV: Mutex<()> = Mutex::new(());
+
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    fn start() -> Bus {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--print-address", "--nofork"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon is needed for the MPRIS tests");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Bus {
+            daemon,
+            address: address.trim().to_string(),
+        }
+    }
+    fn connect(&self) -> Connection {
+        ConnectionBuilder::address(self.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        self.daemon.kill().ok();
+        self.daemon.wait().ok();
+    }
+}
+
+struct Fixture {
+    bus: Bus,
+    mpris: Mpris,
+    spoterm: SpotermClient,
+    api_event_rx: crossbeam::channel::Receiver<SpotifyAPIEvent>,
+    //kept so that the client can send results
+    _api_result_tx: crossbeam::channel::Sender<SpotifyAPIResult>,
+}
+
+impl Fixture {
+    //a client playing the track of fixtures/playback.json, published on a new bus
+    fn new() -> Fixture {
+        let bus = Bus::start();
+        let mpris = {
+            let _env = SESSION_ENV.lock().unwrap_or_else(|e| e.into_inner());
+            std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+            Mpris::new().unwrap()
+        };
+        let user_config = UserConfig::new();
+        let (api_result_tx, api_result_rx) = crossbeam::channel::unbounded();
+        let (api_event_tx, api_event_rx) = crossbeam::channel::unbounded();
+        let mut spoterm = SpotermClient::new(api_result_rx, api_event_tx, &user_config).read_only();
+        let playback: CurrentlyPlaybackContext =
+            serde_json::from_str(include_str!("fixtures/playback.json")).unwrap();
+        spoterm.spotify_data.selected_device = Some(playback.device.clone());
+        spoterm.spotify_data.current_playback = Some(playback);
+        let mut fixture = Fixture {
+            bus,
+            mpris,
+            spoterm,
+            api_event_rx,
+            _api_result_tx: api_result_tx,
+        };
+        fixture.mpris.sync(&mut fixture.spoterm);
+        fixture
+    }
+    fn proxy<'a>(&self, connection: &'a Connection, interface: &'static str) -> Proxy<'a> {
+        ProxyBuilder::new_bare(connection)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .interface(interface)
+            .unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .unwrap()
+    }
+    //calls a Player method, then lets the client apply it like the event loop does
+    fn call(&mut self, method: &str, body: &(impl serde::Serialize + zbus::zvariant::DynamicType)) {
+        let connection = self.bus.connect();
+        self.proxy(&connection, PLAYER)
+            .call_method(method, body)
+            .unwrap();
+        self.mpris.sync(&mut self.spoterm);
+    }
+    fn events(&self) -> Vec<SpotifyAPIEvent> {
+        self.api_event_rx.try_iter().collect()
+    }
+}
+
+#[test]
+fn publishes_the_playback_as_properties() {
+    let fixture = Fixture::new();
+    let connection = fixture.bus.connect();
+    let player = fixture.proxy(&connection, PLAYER);
+
+    assert_eq!(
+        player.get_property::<String>("PlaybackStatus").unwrap(),
+        "Playing"
+    );
+    assert_eq!(
+        player.get_property::<String>("LoopStatus").unwrap(),
+        "Playlist"
+    );
+    assert!(player.get_property::<bool>("Shuffle").unwrap());
+    assert_eq!(player.get_property::<f64>("Volume").unwrap(), 0.4);
+    assert_eq!(player.get_property::<i64>("Position").unwrap(), 30_000_000);
+    assert!(player.get_property::<bool>("CanSeek").unwrap());
+
+    let metadata = player
+        .get_property::<HashMap<String, OwnedValue>>("Metadata")
+        .unwrap();
+    let text = |key: &str| String::try_from(metadata[key].clone()).unwrap();
+    assert_eq!(text("xesam:title"), "Paranoid Android");
+    assert_eq!(text("xesam:album"), "OK Computer");
+    assert_eq!(
+        i64::try_from(metadata["mpris:length"].clone()).unwrap(),
+        284_000_000
+    );
+
+    let root = fixture.proxy(&connection, "org.mpris.MediaPlayer2");
+    assert_eq!(root.get_property::<String>("Identity").unwrap(), "spoterm");
+}
+
+#[test]
+fn play_pause_pauses_the_selected_device() {
+    let mut fixture = Fixture::new();
+    fixture.call("PlayPause", &());
+    let events = fixture.events();
+    assert!(
+        matches!(events.first(), Some(SpotifyAPIEvent::Pause(Some(id))) if id == "device"),
+        "{:?}",
+        events
+    );
+}
+
+#[test]
+fn next_skips_on_the_selected_device() {
+    let mut fixture = Fixture::new();
+    fixture.call("Next", &());
+    let events = fixture.events();
+    assert!(
+        matches!(events.first(), Some(SpotifyAPIEvent::NextTrack(Some(id))) if id == "device"),
+        "{:?}",
+        events
+    );
+}
+
+#[test]
+fn seek_is_relative_to_the_progress() {
+    let mut fixture = Fixture::new();
+    fixture.call("Seek", &(5_000_000i64));
+    let events = fixture.events();
+    assert!(
+        matches!(events.first(), Some(SpotifyAPIEvent::SeekTrack(35_000, Some(id))) if id == "device"),
+        "{:?}",
+        events
+    );
+}
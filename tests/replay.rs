@@ -0,0 +1,104 @@
+//replays fixtures/recording.jsonl on a clock moved by hand and compares the screens drawn
+extern crate crossbeam;
+
+use spoterm::config::UserConfig;
+use spoterm::record::{self, ManualClock, Replay};
+use spoterm::spoterm::{Screen, SpotermClient};
+use spoterm::spotify::SpotifyAPIResult;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tui::backend::TestBackend;
+use tui::Terminal;
+
+const RECORDING: &str = "tests/fixtures/recording.jsonl";
+
+fn replay() -> Replay {
+    Replay::load(Path::new(RECORDING)).unwrap()
+}
+
+fn client(
+    tx: crossbeam::channel::Sender<spoterm::spotify::SpotifyAPIEvent>,
+    rx: crossbeam::channel::Receiver<SpotifyAPIResult>,
+) -> SpotermClient {
+    SpotermClient::new(rx, tx, &UserConfig::new()).read_only()
+}
+
+//handles `count` results as the ticks of the event loop do, once the replay has sent them
+fn receive(spoterm: &mut SpotermClient, count: usize) {
+    let started_at = Instant::now();
+    while spoterm.rx.len() < count {
+        assert!(
+            started_at.elapsed() < Duration::from_secs(5),
+            "the replay sent {} of {} results",
+            spoterm.rx.len(),
+            count
+        );
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    for _ in 0..count {
+        spoterm.fetch_api_result();
+    }
+}
+
+//drawn on a new terminal, as the diff of tui 0.5 leaves cells after wide characters behind
+fn screen(spoterm: &mut SpotermClient) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+    let content_ui = &mut spoterm.contents.uis[spoterm.selected_menu_tab_id];
+    content_ui.set_data(&spoterm.spotify_data);
+    terminal
+        .draw(|mut f| {
+            let size = f.size();
+            f.render(&mut Screen(spoterm), size);
+        })
+        .unwrap();
+    let buffer = terminal.backend().buffer();
+    let mut lines = vec![];
+    for y in 0..buffer.area().height {
+        let line: String = (0..buffer.area().width)
+            .map(|x| buffer.get(x, y).symbol.as_str())
+            .collect();
+        lines.push(line.trim_end().to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+fn expected(name: &str) -> String {
+    std::fs::read_to_string(Path::new("tests/fixtures").join(name)).unwrap()
+}
+
+#[test]
+fn results_are_due_at_their_recorded_times() {
+    let mut replay = replay();
+    assert_eq!(replay.next_at(), Some(300));
+    assert!(replay.due(299).is_empty());
+    let due = replay.due(400);
+    assert!(matches!(
+        due.as_slice(),
+        [
+            SpotifyAPIResult::Device(_),
+            SpotifyAPIResult::CurrentPlayBack(Some(_))
+        ]
+    ));
+    assert_eq!(replay.due(4999).len(), 1);
+    assert_eq!(replay.next_at(), Some(5000));
+    assert_eq!(replay.due(60_000).len(), 1);
+    assert_eq!(replay.next_at(), None);
+}
+
+#[test]
+fn screens_follow_the_replay_clock() {
+    let clock = ManualClock::new();
+    let (tx, rx) = record::replay_with(replay(), clock.clone());
+    let mut spoterm = client(tx, rx);
+
+    clock.set(1000);
+    receive(&mut spoterm, 3);
+    assert_eq!(screen(&mut spoterm), expected("replay_1000ms.txt"));
+    //the next result is recorded at 5000ms
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(spoterm.rx.is_empty());
+
+    clock.set(5000);
+    receive(&mut spoterm, 1);
+    assert_eq!(screen(&mut spoterm), expected("replay_5000ms.txt"));
+}
@@ -0,0 +1,220 @@
+//listens submitted to a local stand-in for ListenBrainz
+extern crate serde_json;
+
+use spoterm::scrobble::{Listen, ScrobbleEvent, ScrobbleService};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+//the listen_type and the track names of a request
+type Request = (String, Vec<String>);
+
+struct Server {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+    //answers every request with `status`, except those with a track named "rejected"
+    fn start(status: &'static str) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                let mut authorized = false;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim().to_lowercase();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(length) = header.strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    authorized |= header == "authorization: token secret";
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let track_names: Vec<String> = body["payload"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|x| {
+                        x["track_metadata"]["track_name"]
+                            .as_str()
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect();
+                let status = if !authorized {
+                    "401 Unauthorized"
+                } else if track_names.iter().any(|x| x == "rejected") {
+                    "400 Bad Request"
+                } else {
+                    status
+                };
+                received.lock().unwrap().push((
+                    body["listen_type"].as_str().unwrap().to_string(),
+                    track_names,
+                ));
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        Server { address, requests }
+    }
+    fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+    //waits for the service to send `count` requests in all
+    async fn wait_for(&self, count: usize) {
+        for _ in 0..500 {
+            if self.requests().len() >= count {
+                return;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("expected {} requests, got {:?}", count, self.requests());
+    }
+}
+
+fn listen(track_name: &str) -> Listen {
+    Listen {
+        listened_at: Some(1_600_000_000),
+        artist_name: "Artist".to_string(),
+        track_name: track_name.to_string(),
+        release_name: None,
+        duration_ms: 200_000,
+        spotify_id: None,
+    }
+}
+
+fn queue_path(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("spoterm-scrobble-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    dir.join("listenbrainz_queue.json")
+}
+
+fn track_names(path: &PathBuf) -> Vec<String> {
+    let listens: Vec<Listen> = std::fs::read_to_string(path)
+        .map(|x| serde_json::from_str(&x).unwrap())
+        .unwrap_or_default();
+    listens.into_iter().map(|x| x.track_name).collect()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn submits_listens_and_sets_rejected_ones_aside() {
+    let server = Server::start("200 OK");
+    let path = queue_path("rejected");
+    let service = ScrobbleService::new(
+        format!("http://{}/", server.address),
+        "secret".to_string(),
+        path.clone(),
+    );
+    let rejected_path = service.rejected_path.clone();
+    let tx = service.scrobble_event_tx.clone();
+    service.run().await.unwrap();
+
+    tx.send(ScrobbleEvent::PlayingNow(Listen {
+        listened_at: None,
+        ..listen("first")
+    }))
+    .unwrap();
+    tx.send(ScrobbleEvent::Listen(listen("rejected"))).unwrap();
+    tx.send(ScrobbleEvent::Listen(listen("second"))).unwrap();
+    server.wait_for(3).await;
+
+    assert_eq!(
+        server.requests(),
+        vec![
+            ("playing_now".to_string(), vec!["first".to_string()]),
+            ("single".to_string(), vec!["rejected".to_string()]),
+            ("single".to_string(), vec!["second".to_string()]),
+        ]
+    );
+    assert!(track_names(&path).is_empty());
+    assert_eq!(track_names(&rejected_path), vec!["rejected"]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keeps_listens_queued_while_the_server_fails() {
+    let server = Server::start("503 Service Unavailable");
+    let path = queue_path("failing");
+    let service = ScrobbleService::new(
+        format!("http://{}", server.address),
+        "secret".to_string(),
+        path.clone(),
+    );
+    let rejected_path = service.rejected_path.clone();
+    let tx = service.scrobble_event_tx.clone();
+    service.run().await.unwrap();
+
+    tx.send(ScrobbleEvent::Listen(listen("first"))).unwrap();
+    server.wait_for(1).await;
+    tx.send(ScrobbleEvent::Listen(listen("second"))).unwrap();
+    server.wait_for(2).await;
+
+    //the second attempt sends the first listen again with the new one
+    assert_eq!(
+        server.requests()[1],
+        (
+            "import".to_string(),
+            vec!["first".to_string(), "second".to_string()]
+        )
+    );
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(track_names(&path), vec!["first", "second"]);
+    assert!(track_names(&rejected_path).is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn sends_a_rejected_batch_one_listen_at_a_time() {
+    let server = Server::start("200 OK");
+    let path = queue_path("batch");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let queued = vec![listen("first"), listen("rejected")];
+    std::fs::write(&path, serde_json::to_string(&queued).unwrap()).unwrap();
+    let service = ScrobbleService::new(
+        format!("http://{}", server.address),
+        "secret".to_string(),
+        path.clone(),
+    );
+    let rejected_path = service.rejected_path.clone();
+    let tx = service.scrobble_event_tx.clone();
+    service.run().await.unwrap();
+
+    tx.send(ScrobbleEvent::Listen(listen("second"))).unwrap();
+    server.wait_for(4).await;
+
+    let track_names_sent: Vec<Vec<String>> =
+        server.requests().into_iter().map(|(_, x)| x).collect();
+    assert_eq!(
+        track_names_sent,
+        vec![
+            vec!["first", "rejected", "second"],
+            vec!["first"],
+            vec!["rejected"],
+            vec!["second"],
+        ]
+    );
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert!(track_names(&path).is_empty());
+    assert_eq!(track_names(&rejected_path), vec!["rejected"]);
+}